@@ -1,17 +1,44 @@
-use near_sdk::{json_types::U128, near, require, store::IterableMap, AccountId};
+use crate::storage::StorageKey;
+use near_sdk::{
+    env,
+    json_types::U128,
+    log, near, require,
+    store::{IterableMap, IterableSet},
+    AccountId, Timestamp,
+};
+
+/// Cumulative amounts an account has moved into and out of the contract for a single token.
+#[near(serializers = [json, borsh])]
+#[derive(Clone, Default)]
+pub struct DepositLifetimeStats {
+    pub total_deposited: U128,
+    pub total_withdrawn: U128,
+}
 
 #[near(serializers = [borsh])]
 pub struct DepositManager {
     // AccountId -> TokenId -> Balance
     pub deposits: IterableMap<AccountId, IterableMap<AccountId, U128>>,
     pub locked_deposits: IterableMap<AccountId, IterableMap<AccountId, U128>>,
+    // (AccountId, TokenId) -> Lifetime totals of external deposits/withdrawals
+    pub lifetime_stats: IterableMap<(AccountId, AccountId), DepositLifetimeStats>,
+    // TokenId -> Sum of every account's total balance
+    pub ledger_totals: IterableMap<AccountId, U128>,
+    // AccountId -> Block timestamp of the last change to any of the account's balances
+    pub last_activity: IterableMap<AccountId, Timestamp>,
+    // TokenId -> Accounts with a balance entry for the token
+    pub token_holders: IterableMap<AccountId, IterableSet<AccountId>>,
 }
 
 impl DepositManager {
     pub fn new() -> Self {
         Self {
-            deposits: IterableMap::new(b"d"),
-            locked_deposits: IterableMap::new(b"l"),
+            deposits: IterableMap::new(StorageKey::Deposits),
+            locked_deposits: IterableMap::new(StorageKey::LockedDeposits),
+            lifetime_stats: IterableMap::new(StorageKey::DepositLifetimeStats),
+            ledger_totals: IterableMap::new(StorageKey::LedgerTotals),
+            last_activity: IterableMap::new(StorageKey::AccountActivity),
+            token_holders: IterableMap::new(StorageKey::TokenHolders),
         }
     }
 
@@ -27,9 +54,113 @@ impl DepositManager {
             .unwrap_or_default()
     }
 
+    /// Collects every account with deposits. Unbounded, so it must never back a public view.
     pub fn get_all_users_with_deposits(&self) -> Vec<AccountId> {
         self.deposits.keys().cloned().collect()
     }
+
+    /// Returns one page of the accounts with deposits, in ledger order.
+    pub fn get_users_with_deposits(&self, from_index: usize, limit: usize) -> Vec<AccountId> {
+        self.deposits
+            .keys()
+            .skip(from_index)
+            .take(limit)
+            .cloned()
+            .collect()
+    }
+
+    /// Returns `(account, total, locked)` for one page of the accounts holding `token_id`,
+    /// paged over the token's holder index so other tokens' holders are never scanned.
+    pub fn get_token_balances(
+        &self,
+        token_id: &AccountId,
+        from_index: usize,
+        limit: usize,
+    ) -> Vec<(AccountId, U128, U128)> {
+        let Some(holders) = self.token_holders.get(token_id) else {
+            return Vec::new();
+        };
+        holders
+            .iter()
+            .skip(from_index)
+            .take(limit)
+            .map(|account_id| {
+                (
+                    account_id.clone(),
+                    self.get_total_balance(account_id, token_id),
+                    self.get_locked_balance(account_id, token_id),
+                )
+            })
+            .collect()
+    }
+
+    /// Returns the sum of all accounts' total balances of `token_id`, kept up to date by
+    /// `credit_total` and `debit_total`.
+    pub fn get_ledger_total(&self, token_id: &AccountId) -> U128 {
+        self.ledger_totals.get(token_id).copied().unwrap_or(U128(0))
+    }
+
+    /// When any of the account's balances last changed; `None` if it never has since this was recorded.
+    pub fn get_last_activity(&self, account_id: &AccountId) -> Option<Timestamp> {
+        self.last_activity.get(account_id).copied()
+    }
+
+    fn record_activity(&mut self, account_id: &AccountId) {
+        self.last_activity
+            .insert(account_id.clone(), env::block_timestamp());
+    }
+
+    fn record_holder(&mut self, account_id: &AccountId, token_id: &AccountId) {
+        if !self.token_holders.contains_key(token_id) {
+            self.token_holders.insert(
+                token_id.clone(),
+                IterableSet::new(StorageKey::token_holders(token_id)),
+            );
+        }
+        self.token_holders
+            .get_mut(token_id)
+            .unwrap()
+            .insert(account_id.clone());
+    }
+
+    pub fn get_lifetime_stats(
+        &self,
+        account_id: &AccountId,
+        token_id: &AccountId,
+    ) -> DepositLifetimeStats {
+        self.lifetime_stats
+            .get(&(account_id.clone(), token_id.clone()))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Records tokens that entered the contract from the account's wallet.
+    /// Internal lock/unlock movements must not be recorded here.
+    pub fn record_external_deposit(
+        &mut self,
+        account_id: &AccountId,
+        token_id: &AccountId,
+        amount: U128,
+    ) {
+        let mut stats = self.get_lifetime_stats(account_id, token_id);
+        stats.total_deposited = U128(stats.total_deposited.0 + amount.0);
+        self.lifetime_stats
+            .insert((account_id.clone(), token_id.clone()), stats);
+    }
+
+    /// Records tokens that left the contract back to the account's wallet.
+    /// Should only be called once the outgoing transfer is known to have succeeded.
+    pub fn record_external_withdrawal(
+        &mut self,
+        account_id: &AccountId,
+        token_id: &AccountId,
+        amount: U128,
+    ) {
+        let mut stats = self.get_lifetime_stats(account_id, token_id);
+        stats.total_withdrawn = U128(stats.total_withdrawn.0 + amount.0);
+        self.lifetime_stats
+            .insert((account_id.clone(), token_id.clone()), stats);
+    }
 }
 
 pub trait HasDeposits {
@@ -79,12 +210,19 @@ impl HasDeposits for DepositManager {
 
     fn credit_total(&mut self, account_id: &AccountId, token_id: &AccountId, amount: U128) {
         if !self.deposits.contains_key(account_id) {
-            self.deposits
-                .insert(account_id.clone(), IterableMap::new(b"s"));
+            self.deposits.insert(
+                account_id.clone(),
+                IterableMap::new(StorageKey::user_deposits(account_id)),
+            );
         }
         let user_deposits = self.deposits.get_mut(account_id).unwrap();
         let current_balance = user_deposits.get(token_id).unwrap_or(&U128(0)).0;
         user_deposits.insert(token_id.clone(), U128(current_balance + amount.0));
+        self.record_holder(account_id, token_id);
+        self.record_activity(account_id);
+        let ledger_total = self.get_ledger_total(token_id).0;
+        self.ledger_totals
+            .insert(token_id.clone(), U128(ledger_total + amount.0));
     }
 
     fn debit_total(&mut self, account_id: &AccountId, token_id: &AccountId, amount: U128) {
@@ -97,16 +235,25 @@ impl HasDeposits for DepositManager {
             token_id.clone(),
             U128(current_balance.saturating_sub(amount.0)),
         );
+        self.record_activity(account_id);
+        // Only what was actually debited leaves the ledger total.
+        let debited = current_balance.min(amount.0);
+        let ledger_total = self.get_ledger_total(token_id).0;
+        self.ledger_totals
+            .insert(token_id.clone(), U128(ledger_total.saturating_sub(debited)));
     }
 
     fn credit_locked(&mut self, account_id: &AccountId, token_id: &AccountId, amount: U128) {
         if !self.locked_deposits.contains_key(account_id) {
-            self.locked_deposits
-                .insert(account_id.clone(), IterableMap::new(b"x"));
+            self.locked_deposits.insert(
+                account_id.clone(),
+                IterableMap::new(StorageKey::user_locked_deposits(account_id)),
+            );
         }
         let user_locked = self.locked_deposits.get_mut(account_id).unwrap();
         let current_locked = user_locked.get(token_id).unwrap_or(&U128(0)).0;
         user_locked.insert(token_id.clone(), U128(current_locked + amount.0));
+        self.record_activity(account_id);
     }
 
     fn debit_locked(&mut self, account_id: &AccountId, token_id: &AccountId, amount: U128) {
@@ -115,10 +262,21 @@ impl HasDeposits for DepositManager {
             .get_mut(account_id)
             .expect("No locked deposits for this user");
         let current_locked = user_locked.get(token_id).unwrap_or(&U128(0)).0;
+        // Escrows only unlock what they locked, so this would mean the ledger has drifted.
+        if amount.0 > current_locked {
+            log!(
+                "LOCKED_BALANCE_UNDERFLOW: account='{}', token='{}', locked='{}', unlocking='{}'",
+                account_id,
+                token_id,
+                current_locked,
+                amount.0
+            );
+        }
         user_locked.insert(
             token_id.clone(),
             U128(current_locked.saturating_sub(amount.0)),
         );
+        self.record_activity(account_id);
     }
 
     fn assert_available_for_escrow(
@@ -1,8 +1,70 @@
-use crate::timelocks::{TimelockDelays, Timelocks};
-use near_sdk::{json_types::Base58CryptoHash, near, AccountId, CryptoHash, NearToken};
+use crate::signatures::PayoutAuthorization;
+use crate::timelocks::{TimelockDelays, TimelockStage, Timelocks};
+use crate::utils::to_hex;
+use near_sdk::{
+    env,
+    json_types::{Base58CryptoHash, U128, U64},
+    near, require, AccountId, CryptoHash, NearToken, Timestamp,
+};
 
 pub type EscrowId = CryptoHash;
 
+/// Derives the id an escrow is stored under from its hashlock and side, so the source and
+/// destination escrows of one swap can share a hashlock in this contract.
+pub fn escrow_id(hashlock: &CryptoHash, is_source: bool) -> EscrowId {
+    env::sha256_array(&[&hashlock[..], &[is_source as u8]].concat())
+}
+
+/// Panic message for calls on an escrow that does not exist. The `ERR_ESCROW_NOT_FOUND` code
+/// lets clients tell a missing escrow apart from an invalid operation on an existing one.
+pub const ERR_ESCROW_NOT_FOUND: &str = "ERR_ESCROW_NOT_FOUND: Escrow not found";
+
+/// Maximum length in bytes of an escrow memo.
+pub const MAX_MEMO_LEN: usize = 256;
+/// Maximum length in bytes of a counterparty transaction reference.
+pub const MAX_COUNTERPARTY_TX_LEN: usize = 128;
+
+/// Asserts that an optional memo fits within `MAX_MEMO_LEN`.
+pub fn assert_valid_memo(memo: &Option<String>) {
+    if let Some(memo) = memo {
+        require!(memo.len() <= MAX_MEMO_LEN, "Memo is too long");
+    }
+}
+
+/// Asserts that an optional counterparty transaction reference fits within `MAX_COUNTERPARTY_TX_LEN`.
+pub fn assert_valid_counterparty_tx(counterparty_tx: &Option<String>) {
+    if let Some(counterparty_tx) = counterparty_tx {
+        require!(
+            counterparty_tx.len() <= MAX_COUNTERPARTY_TX_LEN,
+            "Counterparty transaction reference is too long"
+        );
+    }
+}
+
+/// Asserts that a payout recipient is not this contract, which could never spend the payout.
+pub fn assert_valid_payout_recipient(recipient: &Option<AccountId>) {
+    if let Some(recipient) = recipient {
+        require!(
+            *recipient != env::current_account_id(),
+            "Payout recipient cannot be this contract"
+        );
+    }
+}
+
+/// Asserts that an escrow's delegate, if any, is an account other than its taker and this contract.
+pub fn assert_valid_authorized_claimer(claimer: &Option<AccountId>, taker: &AccountId) {
+    if let Some(claimer) = claimer {
+        require!(
+            claimer != taker,
+            "Authorized claimer must differ from the taker"
+        );
+        require!(
+            *claimer != env::current_account_id(),
+            "Authorized claimer cannot be this contract"
+        );
+    }
+}
+
 #[near(serializers = [json, borsh])]
 #[derive(Clone)]
 pub enum Asset {
@@ -10,9 +72,57 @@ pub enum Asset {
 }
 
 impl Asset {
-    pub fn ft_token_id(&self) -> AccountId {
+    /// Returns the token contract for fungible-token assets, or `None` for assets that are not FTs.
+    pub fn ft_token_id(&self) -> Option<AccountId> {
         match self {
-            Asset::Ft(id) => id.clone(),
+            Asset::Ft(id) => Some(id.clone()),
+        }
+    }
+}
+
+/// Hash function that maps an escrow's secret to its hashlock.
+/// Keccak-256 lets the same secret lock escrows on EVM chains that only verify keccak hashlocks.
+#[near(serializers = [json, borsh])]
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+pub enum HashAlgo {
+    #[default]
+    Sha256,
+    Keccak256,
+}
+
+impl HashAlgo {
+    pub const ALL: [HashAlgo; 2] = [HashAlgo::Sha256, HashAlgo::Keccak256];
+
+    pub fn hash(&self, secret: &[u8]) -> CryptoHash {
+        match self {
+            HashAlgo::Sha256 => env::sha256_array(secret),
+            HashAlgo::Keccak256 => env::keccak256_array(secret),
+        }
+    }
+}
+
+/// How the bytes under the hashlock are built from what the withdrawer reveals.
+#[near(serializers = [json, borsh])]
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+pub enum PreimageScheme {
+    /// The hashlock is the hash of the bare secret.
+    #[default]
+    Secret,
+    /// The hashlock is the hash of `secret || salt`, for counterparty chains committing to structured preimages.
+    SecretWithSalt,
+}
+
+impl PreimageScheme {
+    /// Rebuilds the exact bytes that were hashed into the hashlock.
+    pub fn preimage(&self, mut secret: Vec<u8>, salt: Option<Vec<u8>>) -> Vec<u8> {
+        match (self, salt) {
+            (PreimageScheme::Secret, None) => secret,
+            (PreimageScheme::SecretWithSalt, Some(salt)) => {
+                secret.extend_from_slice(&salt);
+                secret
+            }
+            (PreimageScheme::Secret, Some(_)) => env::panic_str("This escrow does not take a salt"),
+            (PreimageScheme::SecretWithSalt, None) => env::panic_str("This escrow requires a salt"),
         }
     }
 }
@@ -21,14 +131,443 @@ impl Asset {
 #[derive(Clone)]
 pub struct Escrow {
     pub hashlock: CryptoHash,
+    pub hash_algo: HashAlgo,
+    pub preimage_scheme: PreimageScheme,
     pub maker: AccountId,
     pub taker: AccountId,
+    /// A delegate who may act in the private windows as if they were the taker.
+    pub authorized_claimer: Option<AccountId>,
+    /// The maker-signed account paid instead of the maker; `None` pays the maker.
+    pub payout_recipient: Option<AccountId>,
     pub asset: Asset,
     pub amount: NearToken,
     pub timelocks: Timelocks,
     pub safety_deposit: NearToken,
+    /// Token of a safety deposit locked on the taker's internal ledger; `None` for attached native NEAR.
+    pub safety_deposit_token: Option<AccountId>,
     pub claimed: bool,
+    /// The preimage revealed by the first `withdraw`, kept so a failed settlement can be retried
+    /// without the secret.
+    pub revealed_preimage: Option<Vec<u8>>,
+    /// The account that revealed the preimage, which receives the safety deposit on a retry.
+    pub revealed_by: Option<AccountId>,
+    /// Set once a native safety deposit has been paid out, so a retried settlement after a
+    /// failed transfer does not pay it again.
+    pub safety_deposit_returned: bool,
+    /// Set once the settlement callback has confirmed the transfers and applied ledger updates.
+    pub settled: bool,
     pub is_source: bool,
+    /// An opaque reference (e.g. an off-chain order id or counterparty tx hash) for reconciliation.
+    pub memo: Option<String>,
+    /// The counterparty-chain transaction (e.g. an EVM tx hash) that created the other leg.
+    /// Signed by the maker for source escrows; declared by the resolver for destination escrows.
+    pub counterparty_tx: Option<String>,
+    /// Sha256 of the signed order's message bytes, for source escrows, so auditors can tie the
+    /// escrow back to the maker's signature.
+    pub order_hash: Option<CryptoHash>,
+    /// Block timestamp of the escrow's creation or its latest top-up, withdrawal or cancellation.
+    pub last_activity: Timestamp,
+}
+
+/// JSON-friendly representation of an escrow.
+/// The hashlock is given both in base58 (NEAR tooling) and `0x`-prefixed hex (EVM tooling).
+#[near(serializers = [json])]
+#[derive(Clone)]
+pub struct EscrowView {
+    /// The id the escrow is stored under; pass it wherever a hashlock is ambiguous.
+    pub escrow_id: Base58CryptoHash,
+    pub hashlock_b58: Base58CryptoHash,
+    pub hashlock_hex: String,
+    pub hash_algo: HashAlgo,
+    pub preimage_scheme: PreimageScheme,
+    pub maker: AccountId,
+    pub taker: AccountId,
+    pub authorized_claimer: Option<AccountId>,
+    pub payout_recipient: Option<AccountId>,
+    pub asset: Asset,
+    pub amount: NearToken,
+    pub timelocks: Timelocks,
+    pub safety_deposit: NearToken,
+    pub safety_deposit_token: Option<AccountId>,
+    pub claimed: bool,
+    pub revealed_by: Option<AccountId>,
+    pub safety_deposit_returned: bool,
+    pub settled: bool,
+    pub is_source: bool,
+    pub memo: Option<String>,
+    pub counterparty_tx: Option<String>,
+    pub last_activity: U64,
+}
+
+impl From<&Escrow> for EscrowView {
+    fn from(escrow: &Escrow) -> Self {
+        Self {
+            escrow_id: escrow.id().into(),
+            hashlock_b58: escrow.hashlock.into(),
+            hashlock_hex: format!("0x{}", to_hex(&escrow.hashlock)),
+            hash_algo: escrow.hash_algo,
+            preimage_scheme: escrow.preimage_scheme,
+            maker: escrow.maker.clone(),
+            taker: escrow.taker.clone(),
+            authorized_claimer: escrow.authorized_claimer.clone(),
+            payout_recipient: escrow.payout_recipient.clone(),
+            asset: escrow.asset.clone(),
+            amount: escrow.amount,
+            timelocks: escrow.timelocks.clone(),
+            safety_deposit: escrow.safety_deposit,
+            safety_deposit_token: escrow.safety_deposit_token.clone(),
+            claimed: escrow.claimed,
+            revealed_by: escrow.revealed_by.clone(),
+            safety_deposit_returned: escrow.safety_deposit_returned,
+            settled: escrow.settled,
+            is_source: escrow.is_source,
+            memo: escrow.memo.clone(),
+            counterparty_tx: escrow.counterparty_tx.clone(),
+            last_activity: U64(escrow.last_activity),
+        }
+    }
+}
+
+impl Escrow {
+    /// The id this escrow is stored under.
+    pub fn id(&self) -> EscrowId {
+        escrow_id(&self.hashlock, self.is_source)
+    }
+
+    /// Whether `account_id` may use the taker's private windows: the taker or its delegate.
+    pub fn is_private_party(&self, account_id: &AccountId) -> bool {
+        *account_id == self.taker || self.authorized_claimer.as_ref() == Some(account_id)
+    }
+
+    /// The callers of the taker's private windows, as reported by `eligibility`.
+    fn private_callers(&self) -> EligibleCallers {
+        match &self.authorized_claimer {
+            Some(claimer) => EligibleCallers::TakerOrClaimer {
+                taker: self.taker.clone(),
+                claimer: claimer.clone(),
+            },
+            None => EligibleCallers::Taker(self.taker.clone()),
+        }
+    }
+
+    /// Who a withdrawal pays: the taker of a source escrow, or the maker (or its signed payout
+    /// recipient) of a destination escrow.
+    pub fn withdrawal_recipient(&self) -> AccountId {
+        if self.is_source {
+            self.taker.clone()
+        } else {
+            self.payout_recipient
+                .clone()
+                .unwrap_or_else(|| self.maker.clone())
+        }
+    }
+
+    /// Classifies the escrow's lifecycle state at `now`.
+    pub fn state(&self, now: Timestamp, public_grace: u64) -> EscrowState {
+        if self.claimed {
+            return EscrowState::Settled;
+        }
+        match self.timelocks.stage(self.is_source, now, public_grace) {
+            TimelockStage::Finality => EscrowState::Open,
+            TimelockStage::PrivateWithdrawal | TimelockStage::PublicWithdrawal => {
+                EscrowState::Withdrawable
+            }
+            TimelockStage::PrivateCancellation | TimelockStage::PublicCancellation => {
+                EscrowState::Cancellable
+            }
+        }
+    }
+
+    /// Determines which action is currently possible on this escrow and who may perform it.
+    pub fn eligibility(&self, now: Timestamp, public_grace: u64) -> ActionEligibility {
+        if self.claimed {
+            return ActionEligibility {
+                action: None,
+                callers: EligibleCallers::Nobody,
+                can_still_withdraw: false,
+            };
+        }
+        let stage = self.timelocks.stage(self.is_source, now, public_grace);
+        let (action, callers) = match stage {
+            TimelockStage::Finality => (None, EligibleCallers::Nobody),
+            TimelockStage::PrivateWithdrawal => {
+                (Some(EscrowAction::Withdraw), self.private_callers())
+            }
+            TimelockStage::PublicWithdrawal => {
+                (Some(EscrowAction::Withdraw), EligibleCallers::Anyone)
+            }
+            TimelockStage::PrivateCancellation => {
+                (Some(EscrowAction::Cancel), self.private_callers())
+            }
+            TimelockStage::PublicCancellation => {
+                (Some(EscrowAction::Cancel), EligibleCallers::Anyone)
+            }
+        };
+        ActionEligibility {
+            action,
+            callers,
+            can_still_withdraw: self.dst_withdrawal_outlasts_cancellation(stage, now),
+        }
+    }
+
+    /// Whether a destination escrow in its cancellation window can still be withdrawn, which it
+    /// can until the source cancellation deadline.
+    fn dst_withdrawal_outlasts_cancellation(&self, stage: TimelockStage, now: Timestamp) -> bool {
+        !self.is_source
+            && stage == TimelockStage::PublicCancellation
+            && now < self.timelocks.dst_withdrawal_closes_at()
+    }
+
+    /// Determines what `account_id` itself may do with this escrow at `now`, by the timelock
+    /// windows and whether it is the taker or its delegate. Withdrawing additionally requires the secret.
+    pub fn claim_eligibility(
+        &self,
+        account_id: &AccountId,
+        now: Timestamp,
+        public_grace: u64,
+    ) -> ClaimEligibility {
+        if self.claimed {
+            return ClaimEligibility::default();
+        }
+        let is_taker = self.is_private_party(account_id);
+        let stage = self.timelocks.stage(self.is_source, now, public_grace);
+        ClaimEligibility {
+            can_withdraw: stage == TimelockStage::PublicWithdrawal
+                || (is_taker && stage == TimelockStage::PrivateWithdrawal)
+                || self.dst_withdrawal_outlasts_cancellation(stage, now),
+            // Without a withdrawal window the taker may cancel right away.
+            can_cancel: stage == TimelockStage::PublicCancellation
+                || (is_taker
+                    && (stage == TimelockStage::PrivateCancellation
+                        || !self.timelocks.has_withdrawal_window(self.is_source))),
+        }
+    }
+
+    /// Estimates the reward a public caller would earn by settling this escrow at `now`.
+    pub fn public_action_reward(&self, now: Timestamp, public_grace: u64) -> RewardEstimate {
+        let ActionEligibility {
+            action, callers, ..
+        } = self.eligibility(now, public_grace);
+        let action = action.filter(|_| callers == EligibleCallers::Anyone);
+        RewardEstimate {
+            safety_deposit: if action.is_some() {
+                self.safety_deposit
+            } else {
+                NearToken::from_yoctonear(0)
+            },
+            action,
+            safety_deposit_token: self.safety_deposit_token.clone(),
+            bounty: NearToken::from_yoctonear(0),
+        }
+    }
+}
+
+/// The coarse lifecycle state of an escrow.
+#[near(serializers = [json])]
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum EscrowState {
+    /// Created, but no withdrawal window has opened yet.
+    Open,
+    Withdrawable,
+    Cancellable,
+    /// Claimed or cancelled.
+    Settled,
+}
+
+/// Funds `emergency_withdraw_to_owner` may sweep once they have been dormant long enough.
+#[near(serializers = [json])]
+#[derive(Clone)]
+pub enum DormantFunds {
+    /// An unsettled escrow, including its safety deposit.
+    Escrow { hashlock: Base58CryptoHash },
+    /// The available (unlocked) balance of one token on an account's ledger.
+    Deposit {
+        account_id: AccountId,
+        token_id: AccountId,
+    },
+}
+
+/// An invariant `audit_sweep` found broken.
+#[near(serializers = [json])]
+#[derive(Clone, PartialEq, Eq)]
+pub enum AuditViolation {
+    /// An account's locked ledger balance of a token differs from what its unsettled escrows
+    /// and FT safety deposits account for; `reconcile` repairs it.
+    LockedBalanceMismatch {
+        account_id: AccountId,
+        token_id: AccountId,
+        locked: U128,
+        expected: U128,
+    },
+    /// A claimed escrow whose settlement has not been confirmed.
+    UnsettledClaim { escrow_id: Base58CryptoHash },
+    /// A settled escrow that is not marked claimed.
+    SettledWithoutClaim { escrow_id: Base58CryptoHash },
+}
+
+/// The violations found in one page of escrows.
+#[near(serializers = [json])]
+#[derive(Clone, Default)]
+pub struct AuditReport {
+    /// Escrows checked; resume from `from_index + scanned` when it falls short of the limit.
+    pub scanned: u32,
+    pub violations: Vec<AuditViolation>,
+}
+
+/// Per-state tallies over a page of escrows.
+#[near(serializers = [json])]
+#[derive(Clone, Default)]
+pub struct StateCounts {
+    pub open: u32,
+    pub withdrawable: u32,
+    pub cancellable: u32,
+    pub settled: u32,
+}
+
+#[near(serializers = [json])]
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum EscrowAction {
+    Withdraw,
+    Cancel,
+}
+
+#[near(serializers = [json])]
+#[derive(Clone, PartialEq, Eq)]
+pub enum EligibleCallers {
+    Nobody,
+    Taker(AccountId),
+    /// The taker or the delegate it named as `authorized_claimer`.
+    TakerOrClaimer {
+        taker: AccountId,
+        claimer: AccountId,
+    },
+    Anyone,
+}
+
+/// The action currently possible on an escrow and the accounts allowed to perform it.
+#[near(serializers = [json])]
+#[derive(Clone)]
+pub struct ActionEligibility {
+    pub action: Option<EscrowAction>,
+    pub callers: EligibleCallers,
+    /// Whether anyone holding the secret may still withdraw while `action` is `Cancel`, as
+    /// destination escrows allow until the source cancellation deadline.
+    pub can_still_withdraw: bool,
+}
+
+/// What a specific account may do with an escrow right now.
+#[near(serializers = [json])]
+#[derive(Clone, Default)]
+pub struct ClaimEligibility {
+    /// Whether the account may withdraw, given the secret.
+    pub can_withdraw: bool,
+    pub can_cancel: bool,
+}
+
+/// What a public caller would earn by settling an escrow right now.
+#[near(serializers = [json])]
+#[derive(Clone)]
+pub struct RewardEstimate {
+    /// The public action available now; `None` outside the public windows.
+    pub action: Option<EscrowAction>,
+    pub safety_deposit: NearToken,
+    /// Token of an FT safety deposit, credited on the internal ledger; `None` for native NEAR.
+    pub safety_deposit_token: Option<AccountId>,
+    /// Any keeper bounty on top of the safety deposit. None is configured, so this is zero.
+    pub bounty: NearToken,
+}
+
+/// An escrow's asset cross-checked against the contract's real balance of it.
+#[near(serializers = [json])]
+#[derive(Clone)]
+pub struct EscrowAssetInfo {
+    pub asset: Asset,
+    pub amount: NearToken,
+    /// The contract's balance reported by the token's `ft_balance_of`; `None` if the query failed.
+    pub contract_balance: Option<U128>,
+    /// The balance the contract's accounting expects it to hold of the token.
+    pub tracked_balance: U128,
+}
+
+/// How the contract's NEAR balance is committed.
+#[near(serializers = [json])]
+#[derive(Clone)]
+pub struct NearBreakdown {
+    pub account_balance: NearToken,
+    /// NEAR staked for the contract's storage usage.
+    pub locked_for_storage: NearToken,
+    /// Native safety deposits held for unsettled escrows.
+    pub safety_deposit_pool: NearToken,
+    /// NEAR set aside for registering withdrawal recipients with their token.
+    pub registration_reserve: NearToken,
+    /// What remains once storage and safety deposits are covered.
+    pub free: NearToken,
+}
+
+/// A compact audit record of a settled escrow, kept after the escrow itself is no longer needed.
+#[near(serializers = [json, borsh])]
+#[derive(Clone)]
+pub struct SettlementRecord {
+    pub claimant: AccountId,
+    pub settled_at: Timestamp,
+    pub was_cancel: bool,
+    pub amount: NearToken,
+}
+
+/// One escrow in an account's history, with its settlement record once it has one.
+#[near(serializers = [json])]
+#[derive(Clone)]
+pub struct EscrowHistoryEntry {
+    pub escrow: EscrowView,
+    pub status: EscrowState,
+    /// `None` while the escrow is active, or after its record has been pruned.
+    pub settlement: Option<SettlementRecord>,
+}
+
+/// The intended effects of a `withdraw` or `cancel`, known when the call is made.
+/// Whether the transfers succeeded is reported later by the settlement events.
+#[near(serializers = [json])]
+#[derive(Clone)]
+pub struct SettlementReceipt {
+    pub hashlock: Base58CryptoHash,
+    pub action: EscrowAction,
+    /// Receiver of the escrowed funds (for source cancellations, the maker's internal ledger).
+    pub recipient: AccountId,
+    /// Amount sent to `recipient`, net of any protocol fee or cancellation penalty.
+    pub amount: U128,
+    pub safety_deposit_returned_to: AccountId,
+}
+
+/// A proposed protocol fee, which the owner can apply once `effective_at` has passed.
+#[near(serializers = [json, borsh])]
+#[derive(Clone)]
+pub struct PendingFeeChange {
+    pub fee_bps: u16,
+    /// Block timestamp (nanoseconds) from which the change may be applied.
+    pub effective_at: Timestamp,
+}
+
+/// Who receives the cancellation penalty diverted from a cancelled escrow's refund.
+#[near(serializers = [json, borsh])]
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+pub enum PenaltyRecipient {
+    /// The party that did not get the refund: the taker of a source escrow, the maker of a destination escrow.
+    #[default]
+    Counterparty,
+    /// The protocol fee recipient's internal ledger.
+    FeeRecipient,
+}
+
+/// Running counters for a resolver (the taker of its escrows), for off-chain reputation.
+#[near(serializers = [json, borsh])]
+#[derive(Clone, Default)]
+pub struct ResolverStats {
+    /// Escrows created with this resolver as the taker.
+    pub filled: u64,
+    /// Escrows settled by revealing the secret.
+    pub withdrawn: u64,
+    /// Escrows settled by cancellation.
+    pub cancelled: u64,
 }
 
 /// Defines the messages passed via `ft_transfer_call`.
@@ -41,6 +580,28 @@ pub enum FtMessage {
     CreateDestinationEscrow {
         hashlock: Base58CryptoHash,
         maker_id: AccountId,
-        timelocks: TimelockDelays,
+        /// `None` uses the contract's default timelocks.
+        timelocks: Option<TimelockDelays>,
+        /// A delegate who may act in the private windows as if they were the resolver.
+        authorized_claimer: Option<AccountId>,
+        /// The maker's signed instruction to pay the escrow out to another account.
+        payout_authorization: Option<PayoutAuthorization>,
+        /// The token the resolver means to escrow; when given it must be the token being transferred.
+        asset_id: Option<AccountId>,
+        memo: Option<String>,
+        /// The counterparty-chain transaction that created the other leg.
+        counterparty_tx: Option<String>,
+        /// Schedules the escrow's windows to start at this future timestamp instead of now.
+        activation_time: Option<Timestamp>,
+        /// The part of the attached NEAR kept as the safety deposit; the rest is refunded to the resolver.
+        safety_deposit_amount: Option<NearToken>,
+        /// Posts the safety deposit from the resolver's internal balance of this token instead of NEAR.
+        safety_deposit_token: Option<AccountId>,
+        #[serde(default)]
+        hash_algo: HashAlgo,
+        #[serde(default)]
+        preimage_scheme: PreimageScheme,
     },
+    /// Adds the transferred tokens to the sender's unclaimed destination escrow, before it becomes cancellable.
+    TopUpDestinationEscrow { hashlock: Base58CryptoHash },
 }
@@ -0,0 +1,170 @@
+//! The state layout of the last deployed build, read once by `Contract::migrate`.
+//!
+//! In that build every account's deposit sub-map shared the `b"s"` prefix and every locked
+//! sub-map the `b"x"` prefix. Each token's balance slot was therefore shared by all of its holders
+//! and holds their combined balance, so per-account totals cannot be read back from it.
+use crate::escrow::{Asset, Escrow, HashAlgo, PreimageScheme};
+use crate::timelocks::{TimeUnit, TimelockDelays, Timelocks};
+use near_sdk::store::{IterableMap, IterableSet};
+use near_sdk::{
+    borsh, env, json_types::U128, near, AccountId, CryptoHash, NearToken, PublicKey, Timestamp,
+};
+
+/// Prefix every legacy per-account deposit map was created with.
+pub const LEGACY_DEPOSITS_PREFIX: &[u8] = b"s";
+/// Prefix every legacy per-account locked map was created with.
+pub const LEGACY_LOCKED_PREFIX: &[u8] = b"x";
+
+#[near(serializers = [borsh])]
+pub struct LegacyContract {
+    pub owner_id: AccountId,
+    /// Keyed by bare hashlock.
+    pub escrows: IterableMap<CryptoHash, LegacyEscrow>,
+    pub deposits: LegacyDepositManager,
+    /// Nonces without their maker.
+    pub used_nonces: IterableSet<u128>,
+    pub registered_keys: IterableMap<AccountId, Vec<PublicKey>>,
+}
+
+#[near(serializers = [borsh])]
+pub struct LegacyDepositManager {
+    pub deposits: IterableMap<AccountId, IterableMap<AccountId, U128>>,
+    pub locked_deposits: IterableMap<AccountId, IterableMap<AccountId, U128>>,
+}
+
+#[near(serializers = [borsh])]
+#[derive(Clone)]
+pub struct LegacyEscrow {
+    pub hashlock: CryptoHash,
+    pub maker: AccountId,
+    pub taker: AccountId,
+    pub asset: Asset,
+    pub amount: NearToken,
+    pub timelocks: LegacyTimelocks,
+    pub safety_deposit: NearToken,
+    pub claimed: bool,
+    pub is_source: bool,
+}
+
+#[near(serializers = [borsh])]
+#[derive(Clone)]
+pub struct LegacyTimelocks {
+    pub created_at: Timestamp,
+    pub delays: LegacyTimelockDelays,
+}
+
+#[near(serializers = [borsh])]
+#[derive(Clone)]
+pub struct LegacyTimelockDelays {
+    pub src_withdrawal_delay: u64,
+    pub src_public_withdrawal_delay: u64,
+    pub src_cancellation_delay: u64,
+    pub src_public_cancellation_delay: u64,
+    pub dst_withdrawal_delay: u64,
+    pub dst_public_withdrawal_delay: u64,
+    pub dst_cancellation_delay: u64,
+}
+
+/// An account's total balance of a token, reconstructed off-chain (e.g. from the token transfer
+/// history), that `migrate` checks against the shared legacy slots before crediting it.
+#[near(serializers = [json])]
+#[derive(Clone)]
+pub struct LegacyBalance {
+    pub account_id: AccountId,
+    pub token_id: AccountId,
+    pub total: U128,
+}
+
+impl LegacyEscrow {
+    /// The escrow under the current layout. Legacy escrows were settled in the same call that
+    /// claimed them, so a claimed escrow is settled and its native safety deposit paid out.
+    pub fn into_escrow(self) -> Escrow {
+        let d = self.timelocks.delays;
+        Escrow {
+            hashlock: self.hashlock,
+            hash_algo: HashAlgo::Sha256,
+            preimage_scheme: PreimageScheme::default(),
+            maker: self.maker,
+            taker: self.taker,
+            authorized_claimer: None,
+            payout_recipient: None,
+            asset: self.asset,
+            amount: self.amount,
+            timelocks: Timelocks::new(
+                self.timelocks.created_at,
+                TimelockDelays {
+                    src_withdrawal_delay: d.src_withdrawal_delay,
+                    src_public_withdrawal_delay: d.src_public_withdrawal_delay,
+                    src_cancellation_delay: d.src_cancellation_delay,
+                    src_public_cancellation_delay: d.src_public_cancellation_delay,
+                    dst_withdrawal_delay: d.dst_withdrawal_delay,
+                    dst_public_withdrawal_delay: d.dst_public_withdrawal_delay,
+                    dst_cancellation_delay: d.dst_cancellation_delay,
+                    time_unit: TimeUnit::Seconds,
+                },
+            ),
+            safety_deposit: self.safety_deposit,
+            safety_deposit_token: None,
+            claimed: self.claimed,
+            revealed_preimage: None,
+            revealed_by: None,
+            safety_deposit_returned: self.claimed,
+            settled: self.claimed,
+            is_source: self.is_source,
+            memo: None,
+            counterparty_tx: None,
+            order_hash: None,
+            last_activity: env::block_timestamp(),
+        }
+    }
+}
+
+/// Reads the shared legacy balance slot of `token_id` under `prefix`: the combined balance of
+/// every account that ever held the token.
+pub fn read_shared_slot(prefix: &[u8], token_id: &AccountId) -> u128 {
+    env::storage_read(&shared_slot_key(prefix, token_id)).map_or(0, |value| {
+        let amount: [u8; 16] = value[..16]
+            .try_into()
+            .expect("Malformed legacy balance slot");
+        u128::from_le_bytes(amount)
+    })
+}
+
+/// Removes the shared legacy storage under `prefix`: the balance slots of `tokens` and the
+/// first `key_slots` entries of the shared key vector. Done by key, since clearing the aliased
+/// maps one by one would remove the same slots several times.
+pub fn remove_shared_slots(prefix: &[u8], tokens: &[AccountId], key_slots: u32) {
+    for token_id in tokens {
+        env::storage_remove(&shared_slot_key(prefix, token_id));
+    }
+    for index in 0..key_slots {
+        env::storage_remove(&[prefix, b"v", &index.to_le_bytes()].concat());
+    }
+}
+
+fn shared_slot_key(prefix: &[u8], token_id: &AccountId) -> Vec<u8> {
+    [
+        prefix,
+        b"m",
+        &borsh::to_vec(token_id).expect("AccountId is borsh-serializable"),
+    ]
+    .concat()
+}
+
+/// Every token key left in the shared key vector of `maps`, and how many slots that vector used.
+/// Later accounts overwrote earlier accounts' slots, so tokens may be missing from the result.
+pub fn shared_keys(
+    maps: &IterableMap<AccountId, IterableMap<AccountId, U128>>,
+) -> (Vec<AccountId>, u32) {
+    let mut tokens: Vec<AccountId> = Vec::new();
+    let mut slots = 0;
+    for user_map in maps.values() {
+        slots = slots.max(user_map.len());
+        for token_id in user_map.keys() {
+            if !tokens.contains(token_id) {
+                tokens.push(token_id.clone());
+            }
+        }
+    }
+    (tokens, slots)
+}
@@ -1,41 +1,149 @@
-use near_sdk::json_types::{Base58CryptoHash, U128};
+use near_sdk::base64::{engine::general_purpose::STANDARD, Engine as _};
+use near_sdk::json_types::{Base58CryptoHash, U128, U64};
 use near_sdk::store::{IterableMap, IterableSet};
 use near_sdk::{
-    base64, bs58, env, ext_contract, log, near, require, serde_json, AccountId, NearToken, Promise,
-    PromiseOrValue, PromiseResult, PublicKey,
+    assert_one_yocto, borsh, bs58, env, ext_contract, log, near, require, serde_json, AccountId,
+    CryptoHash, Gas, NearToken, Promise, PromiseOrValue, PromiseResult, PublicKey, Timestamp,
 };
 
+use std::collections::BTreeMap;
+
 // --- Module Declarations ---
 mod deposit;
 mod escrow;
+mod legacy;
 mod signatures;
+mod storage;
 mod timelocks;
 mod utils;
 
+pub use crate::escrow::Escrow;
+
 // --- Use Declarations ---
-use crate::deposit::{DepositManager, HasDeposits};
-use crate::escrow::{Asset, Escrow, EscrowId, FtMessage};
-use crate::signatures::{verify_maker_signature, SignedOrder};
-use crate::timelocks::Timelocks;
-use crate::utils::log_escrow_event;
+use crate::deposit::{DepositLifetimeStats, DepositManager, HasDeposits};
+use crate::escrow::{
+    assert_valid_authorized_claimer, assert_valid_counterparty_tx, assert_valid_memo,
+    assert_valid_payout_recipient, escrow_id, ActionEligibility, Asset, AuditReport,
+    AuditViolation, ClaimEligibility, DormantFunds, EscrowAction, EscrowAssetInfo,
+    EscrowHistoryEntry, EscrowId, EscrowState, EscrowView, FtMessage, HashAlgo, NearBreakdown,
+    PenaltyRecipient, PendingFeeChange, PreimageScheme, ResolverStats, RewardEstimate,
+    SettlementReceipt, SettlementRecord, StateCounts, ERR_ESCROW_NOT_FOUND,
+};
+use crate::legacy::{
+    read_shared_slot, remove_shared_slots, shared_keys, LegacyBalance, LegacyContract,
+    LegacyEscrow, LEGACY_DEPOSITS_PREFIX, LEGACY_LOCKED_PREFIX,
+};
+use crate::signatures::{
+    assert_ed25519_signature, implicit_account_public_key, payout_authorization_hash,
+    verify_maker_signature, FillOptions, KeyPermission, OpenOrders, OrderStatus,
+    PayoutAuthorization, PostedOrder, SignedOrder, MAX_TAKER_ALLOWLIST_LEN,
+};
+use crate::storage::StorageKey;
+use crate::timelocks::{
+    activation_timestamp, TimelockConstraints, TimelockDelays, TimelockStage, Timelocks,
+    MAX_ACTIVATION_HORIZON_SECS, NANOS_IN_SEC,
+};
+use crate::utils::{
+    decode_base64, ft_amount_to_token, log_escrow_event, log_nep297_event, token_to_ft_amount,
+    try_decode_base64,
+};
+
+// --- Gas Constants ---
+/// Gas for executing `withdraw`/`cancel` itself, before any promises are created.
+const GAS_FOR_SETTLEMENT_CALL: Gas = Gas::from_tgas(15);
+/// Gas for a NEP-141 `ft_transfer` to the settlement recipient.
+const GAS_FOR_FT_TRANSFER: Gas = Gas::from_tgas(10);
+/// Gas for the `on_escrow_settled` callback.
+const GAS_FOR_SETTLEMENT_CALLBACK: Gas = Gas::from_tgas(20);
+/// Gas for the NEP-145 `storage_deposit` registering a withdrawal recipient with its token.
+const GAS_FOR_STORAGE_DEPOSIT: Gas = Gas::from_tgas(10);
+/// Gas the settlement callback must have left to register the recipient and retry the transfer.
+const GAS_FOR_REGISTRATION_RETRY: Gas = Gas::from_tgas(
+    GAS_FOR_STORAGE_DEPOSIT.as_tgas()
+        + GAS_FOR_FT_TRANSFER.as_tgas()
+        + GAS_FOR_SETTLEMENT_CALLBACK.as_tgas(),
+);
+/// Gas for the optional `ft_metadata` probe of a destination escrow's token.
+const GAS_FOR_FT_METADATA: Gas = Gas::from_tgas(5);
+/// Gas for the `on_ft_metadata_verified` callback that finalizes the destination escrow.
+const GAS_FOR_METADATA_CALLBACK: Gas = Gas::from_tgas(15);
+/// Gas for the `ft_balance_of` query behind `get_escrow_asset_info`.
+const GAS_FOR_FT_BALANCE_OF: Gas = Gas::from_tgas(5);
+/// Gas for the `on_escrow_asset_balance` callback that assembles the asset report.
+const GAS_FOR_ASSET_INFO_CALLBACK: Gas = Gas::from_tgas(5);
+/// Minimum gas that must be attached to `withdraw`/`cancel` for the whole settlement chain to complete.
+const MIN_SETTLEMENT_GAS: Gas = Gas::from_tgas(
+    GAS_FOR_SETTLEMENT_CALL.as_tgas()
+        + GAS_FOR_FT_TRANSFER.as_tgas()
+        + GAS_FOR_SETTLEMENT_CALLBACK.as_tgas(),
+);
+
+/// Gas reserved for each escrow cancelled by `settle_all_ready`: its refund and settlement callback.
+const GAS_FOR_BATCHED_SETTLEMENT: Gas =
+    Gas::from_tgas(GAS_FOR_FT_TRANSFER.as_tgas() + GAS_FOR_SETTLEMENT_CALLBACK.as_tgas());
+/// Upper bound on the escrows scanned by a single `settle_all_ready` call.
+const MAX_SETTLE_BATCH: u32 = 20;
+/// Gas kept in reserve while `audit_sweep` checks another escrow.
+const GAS_FOR_AUDIT_STEP: Gas = Gas::from_tgas(10);
+/// Upper bound on the escrows scanned by a single `audit_sweep` call.
+const MAX_AUDIT_BATCH: u32 = 100;
+
+/// Time funds must sit untouched before the owner may sweep them (365 days). Not configurable.
+const DORMANCY_PERIOD_SECS: u64 = 365 * 24 * 60 * 60;
+
+/// Upper bound on the protocol fee, in basis points (1%).
+const MAX_FEE_BPS: u16 = 100;
+/// Upper bound on the delay between proposing and applying a fee change (30 days).
+const MAX_FEE_CHANGE_DELAY_SECS: u64 = 30 * 24 * 60 * 60;
+const BPS_DENOMINATOR: u128 = 10_000;
+/// Upper bound on the cancellation penalty, in basis points (10%).
+const MAX_CANCELLATION_PENALTY_BPS: u16 = 1_000;
+
+/// Upper bound on the owner-configurable safety deposit decay period (30 days).
+const MAX_SAFETY_DEPOSIT_DECAY_SECS: u64 = 30 * 24 * 60 * 60;
+/// Upper bound on the owner-configurable public grace period (7 days).
+const MAX_PUBLIC_GRACE_SECS: u64 = 7 * 24 * 60 * 60;
+/// Upper bound on the owner-configurable minimum reveal window (7 days).
+const MAX_REVEAL_GRACE_SECS: u64 = 7 * 24 * 60 * 60;
+
+/// Upper bound on the keys an account can register, keeping the per-account entry small.
+const MAX_KEYS_PER_ACCOUNT: usize = 32;
+/// Upper bound on the accounts provisioned by a single `admin_register_keys` call.
+const MAX_ADMIN_KEY_BATCH: usize = 50;
+/// Number of tokens with the most escrowed funds reported by `emit_metrics`.
+const MAX_METRICS_TOKENS: usize = 10;
+/// Most NEAR attached to a single automatic recipient registration, paid from the registration reserve.
+const MAX_AUTO_REGISTRATION_DEPOSIT: NearToken = NearToken::from_millinear(10);
 
 // --- External Contract Interfaces ---
 #[ext_contract(ext_fungible_token)]
 pub trait FungibleToken {
     fn ft_transfer(&mut self, receiver_id: AccountId, amount: U128, memo: Option<String>);
+    fn ft_metadata(&self) -> serde_json::Value;
+    fn ft_balance_of(&self, account_id: AccountId) -> U128;
+    fn storage_deposit(&mut self, account_id: Option<AccountId>, registration_only: Option<bool>);
 }
 
 #[ext_contract(ext_self)]
 pub trait SelfCallbacks {
     fn on_escrow_settled(
         &mut self,
-        hashlock: EscrowId,
+        escrow_id: EscrowId,
         maker_id: AccountId,
         taker_id: AccountId,
         is_source: bool,
         is_cancel: bool,
+        caller: AccountId,
+        safety_deposit_recipient: AccountId,
+        safety_deposit_reward: NearToken,
+        fee: U128,
+        penalty_recipient: Option<AccountId>,
+        registration_retry: bool,
     );
     fn on_deposit_withdrawn(&mut self, account_id: AccountId, token_id: AccountId, amount: U128);
+    fn on_cancel_withdraw_deposit(&mut self, escrow_id: EscrowId, amount: U128);
+    fn on_ft_metadata_verified(&mut self, escrow: Escrow) -> U128;
+    fn on_escrow_asset_balance(&mut self, asset: Asset, amount: NearToken) -> EscrowAssetInfo;
 }
 
 // --- Contract State ---
@@ -44,8 +152,64 @@ pub struct Contract {
     pub owner_id: AccountId,
     pub escrows: IterableMap<EscrowId, Escrow>,
     pub deposits: DepositManager,
-    pub used_nonces: IterableSet<u128>,
+    /// Consumed order nonces, namespaced per maker.
+    pub used_nonces: IterableSet<(AccountId, u128)>,
+    /// Highest consumed nonce of makers who opted into sequential nonces.
+    pub nonce_high_water: IterableMap<AccountId, u128>,
+    /// The source escrow each filled order created, keyed by (maker, nonce).
+    pub order_escrows: IterableMap<(AccountId, u128), EscrowId>,
+    /// Open orders makers posted on-chain, keyed by (maker, nonce), until filled or removed.
+    pub posted_orders: IterableMap<(AccountId, u128), PostedOrder>,
+    /// Number of distinct registered keys that must sign each order, for makers requiring more than one.
+    pub signature_thresholds: IterableMap<AccountId, u32>,
     pub registered_keys: IterableMap<AccountId, Vec<PublicKey>>,
+    /// Roles of registered keys that are not full-access keys.
+    pub key_permissions: IterableMap<(AccountId, PublicKey), KeyPermission>,
+    pub settlements: IterableMap<EscrowId, SettlementRecord>,
+    /// Extra seconds added to every public window, on top of the escrow's own delays.
+    pub public_grace: u64,
+    /// Relayers trusted to fast-cancel destination escrows whose source leg is dead.
+    pub relayers: IterableSet<AccountId>,
+    /// Running total of native NEAR safety deposits held for escrows that have not paid them out yet.
+    pub total_safety_deposits: NearToken,
+    /// NEAR set aside to register withdrawal recipients that the token rejected as unregistered.
+    pub registration_reserve: NearToken,
+    /// Protocol fee taken from each successful withdrawal, in basis points.
+    pub fee_bps: u16,
+    /// Seconds a proposed fee change must wait before it can be applied. It can only be raised.
+    pub fee_change_delay: u64,
+    pub pending_fee_change: Option<PendingFeeChange>,
+    /// Internal ledger account credited with protocol fees. No fees are taken while unset.
+    pub fee_recipient: Option<AccountId>,
+    /// When set, destination escrows are only created once the token answers `ft_metadata`.
+    pub verify_ft_metadata: bool,
+    pub resolver_stats: IterableMap<AccountId, ResolverStats>,
+    /// Amount withdrawn through each resolver's escrows, keyed by (resolver, token).
+    pub resolver_volume: IterableMap<(AccountId, AccountId), U128>,
+    /// Hash functions escrows may be created with on this deployment.
+    pub allowed_hash_algos: Vec<HashAlgo>,
+    /// Minimum yoctoNEAR of safety deposit per base unit of escrowed token, per token.
+    pub min_safety_deposit_per_unit: IterableMap<AccountId, U128>,
+    /// Smallest escrow amount accepted per token, so dust escrows are not worth their storage.
+    pub min_escrow_amount: IterableMap<AccountId, U128>,
+    /// Cap on the unsettled escrows a resolver may be the taker of at once; unlimited while unset.
+    pub max_active_escrows_per_resolver: Option<u64>,
+    /// Minimum seconds between the destination public withdrawal and the source cancellation,
+    /// leaving time to carry a revealed secret over to the source chain.
+    pub min_reveal_grace: u64,
+    /// Share of a cancelled escrow's refund diverted to `cancellation_penalty_recipient`, in basis points.
+    pub cancellation_penalty_bps: u16,
+    pub cancellation_penalty_recipient: PenaltyRecipient,
+    /// Seconds after the public withdrawal window opens over which a public withdrawer's share of
+    /// the safety deposit grows to all of it; the rest goes back to the taker. Unset pays it all.
+    pub safety_deposit_decay_period: Option<u64>,
+    /// Share of a cancelled escrow's safety deposit paid to a public caller, in basis points.
+    /// The rest goes back to the taker.
+    pub public_cancel_reward_bps: u16,
+    /// Amount held in unsettled destination escrows, per token. Source escrows stay on the ledger.
+    pub destination_escrow_totals: IterableMap<AccountId, U128>,
+    /// Delays used by orders and destination escrows that leave their timelocks out.
+    pub default_timelocks: Option<TimelockDelays>,
 }
 
 // Define the default, which automatically initializes the contract
@@ -53,10 +217,38 @@ impl Default for Contract {
     fn default() -> Self {
         Self {
             owner_id: env::predecessor_account_id(),
-            escrows: IterableMap::new(b"e"),
+            escrows: IterableMap::new(StorageKey::Escrows),
             deposits: DepositManager::new(),
-            used_nonces: IterableSet::new(b"u"),
-            registered_keys: IterableMap::new(b"k"),
+            used_nonces: IterableSet::new(StorageKey::UsedNonces),
+            nonce_high_water: IterableMap::new(StorageKey::NonceHighWater),
+            order_escrows: IterableMap::new(StorageKey::OrderEscrows),
+            posted_orders: IterableMap::new(StorageKey::PostedOrders),
+            signature_thresholds: IterableMap::new(StorageKey::SignatureThresholds),
+            registered_keys: IterableMap::new(StorageKey::RegisteredKeys),
+            key_permissions: IterableMap::new(StorageKey::KeyPermissions),
+            settlements: IterableMap::new(StorageKey::Settlements),
+            public_grace: 0,
+            relayers: IterableSet::new(StorageKey::Relayers),
+            total_safety_deposits: NearToken::from_yoctonear(0),
+            registration_reserve: NearToken::from_yoctonear(0),
+            fee_bps: 0,
+            fee_change_delay: 0,
+            pending_fee_change: None,
+            fee_recipient: None,
+            verify_ft_metadata: false,
+            resolver_stats: IterableMap::new(StorageKey::ResolverStats),
+            resolver_volume: IterableMap::new(StorageKey::ResolverVolume),
+            allowed_hash_algos: HashAlgo::ALL.to_vec(),
+            min_safety_deposit_per_unit: IterableMap::new(StorageKey::MinSafetyDepositPerUnit),
+            min_escrow_amount: IterableMap::new(StorageKey::MinEscrowAmount),
+            max_active_escrows_per_resolver: None,
+            min_reveal_grace: 0,
+            cancellation_penalty_bps: 0,
+            cancellation_penalty_recipient: PenaltyRecipient::Counterparty,
+            public_cancel_reward_bps: BPS_DENOMINATOR as u16,
+            safety_deposit_decay_period: None,
+            destination_escrow_totals: IterableMap::new(StorageKey::DestinationEscrowTotals),
+            default_timelocks: None,
         }
     }
 }
@@ -69,16 +261,494 @@ impl Contract {
         assert!(!env::state_exists(), "The contract is already initialized");
         Self {
             owner_id,
-            escrows: IterableMap::new(b"e"),
+            escrows: IterableMap::new(StorageKey::Escrows),
             deposits: DepositManager::new(),
-            used_nonces: IterableSet::new(b"u"),
-            registered_keys: IterableMap::new(b"k"),
+            used_nonces: IterableSet::new(StorageKey::UsedNonces),
+            nonce_high_water: IterableMap::new(StorageKey::NonceHighWater),
+            order_escrows: IterableMap::new(StorageKey::OrderEscrows),
+            posted_orders: IterableMap::new(StorageKey::PostedOrders),
+            signature_thresholds: IterableMap::new(StorageKey::SignatureThresholds),
+            registered_keys: IterableMap::new(StorageKey::RegisteredKeys),
+            key_permissions: IterableMap::new(StorageKey::KeyPermissions),
+            settlements: IterableMap::new(StorageKey::Settlements),
+            public_grace: 0,
+            relayers: IterableSet::new(StorageKey::Relayers),
+            total_safety_deposits: NearToken::from_yoctonear(0),
+            registration_reserve: NearToken::from_yoctonear(0),
+            fee_bps: 0,
+            fee_change_delay: 0,
+            pending_fee_change: None,
+            fee_recipient: None,
+            verify_ft_metadata: false,
+            resolver_stats: IterableMap::new(StorageKey::ResolverStats),
+            resolver_volume: IterableMap::new(StorageKey::ResolverVolume),
+            allowed_hash_algos: HashAlgo::ALL.to_vec(),
+            min_safety_deposit_per_unit: IterableMap::new(StorageKey::MinSafetyDepositPerUnit),
+            min_escrow_amount: IterableMap::new(StorageKey::MinEscrowAmount),
+            max_active_escrows_per_resolver: None,
+            min_reveal_grace: 0,
+            cancellation_penalty_bps: 0,
+            cancellation_penalty_recipient: PenaltyRecipient::Counterparty,
+            public_cancel_reward_bps: BPS_DENOMINATOR as u16,
+            safety_deposit_decay_period: None,
+            destination_escrow_totals: IterableMap::new(StorageKey::DestinationEscrowTotals),
+            default_timelocks: None,
+        }
+    }
+
+    /// Upgrades the state of the last deployed build to the current layout. Must be called once,
+    /// as the contract itself, right after deploying this build over it.
+    ///
+    /// Every legacy deposit map shared one storage prefix, so each token's balance slot holds the
+    /// combined balance of all its holders and per-account totals cannot be recovered on-chain.
+    /// They are taken from `balances` instead, which must add up to every shared slot exactly;
+    /// otherwise the upgrade is rejected. Locked balances are rebuilt from the unclaimed source
+    /// escrows. Legacy nonces are dropped: they carried no maker, and orders signed in the old
+    /// message format no longer verify. Lifetime deposit statistics start from zero.
+    #[private]
+    #[init(ignore_state)]
+    pub fn migrate(balances: Vec<LegacyBalance>) -> Self {
+        let mut legacy: LegacyContract =
+            env::state_read().expect("The contract is not initialized");
+        let mut contract = Self {
+            owner_id: legacy.owner_id.clone(),
+            ..Self::default()
+        };
+
+        let escrows: Vec<LegacyEscrow> = legacy.escrows.values().cloned().collect();
+        let mut locked: BTreeMap<(AccountId, AccountId), u128> = BTreeMap::new();
+        for escrow in escrows.iter().filter(|e| e.is_source && !e.claimed) {
+            let token_id = escrow.asset.ft_token_id().expect("Legacy escrows hold FTs");
+            *locked.entry((escrow.maker.clone(), token_id)).or_default() +=
+                token_to_ft_amount(escrow.amount).0;
+        }
+
+        // Every token still on the legacy ledger must be accounted for in full.
+        let (mut deposit_tokens, deposit_slots) = shared_keys(&legacy.deposits.deposits);
+        for balance in &balances {
+            require!(
+                legacy.deposits.deposits.contains_key(&balance.account_id),
+                format!("{} had no legacy deposits", balance.account_id)
+            );
+            if !deposit_tokens.contains(&balance.token_id) {
+                deposit_tokens.push(balance.token_id.clone());
+            }
+        }
+        for token_id in &deposit_tokens {
+            let claimed: u128 = balances
+                .iter()
+                .filter(|balance| balance.token_id == *token_id)
+                .map(|balance| balance.total.0)
+                .sum();
+            require!(
+                claimed == read_shared_slot(LEGACY_DEPOSITS_PREFIX, token_id),
+                format!(
+                    "Legacy balances of {} do not add up to its ledger slot",
+                    token_id
+                )
+            );
+        }
+        let (mut locked_tokens, locked_slots) = shared_keys(&legacy.deposits.locked_deposits);
+        for (_, token_id) in locked.keys() {
+            if !locked_tokens.contains(token_id) {
+                locked_tokens.push(token_id.clone());
+            }
+        }
+        for token_id in &locked_tokens {
+            let expected: u128 = locked
+                .iter()
+                .filter(|((_, locked_token), _)| locked_token == token_id)
+                .map(|(_, amount)| *amount)
+                .sum();
+            let recorded = read_shared_slot(LEGACY_LOCKED_PREFIX, token_id);
+            if expected != recorded {
+                log!(
+                    "LEGACY_LOCKED_MISMATCH: token='{}', recorded='{}', escrowed='{}'",
+                    token_id,
+                    recorded,
+                    expected
+                );
+            }
+        }
+
+        // The legacy maps are cleared by key and flushed before the new ledger reuses their prefixes.
+        legacy.escrows.clear();
+        legacy.escrows.flush();
+        legacy.deposits.deposits.clear();
+        legacy.deposits.deposits.flush();
+        legacy.deposits.locked_deposits.clear();
+        legacy.deposits.locked_deposits.flush();
+        legacy.used_nonces.clear();
+        legacy.used_nonces.flush();
+        remove_shared_slots(LEGACY_DEPOSITS_PREFIX, &deposit_tokens, deposit_slots);
+        remove_shared_slots(LEGACY_LOCKED_PREFIX, &locked_tokens, locked_slots);
+
+        for balance in balances {
+            contract
+                .deposits
+                .credit_total(&balance.account_id, &balance.token_id, balance.total);
+        }
+        for ((account_id, token_id), amount) in locked {
+            contract
+                .deposits
+                .credit_locked(&account_id, &token_id, U128(amount));
+            require!(
+                contract
+                    .deposits
+                    .get_total_balance(&account_id, &token_id)
+                    .0
+                    >= amount,
+                format!("Legacy balance of {} cannot cover its escrows", account_id)
+            );
+        }
+        for escrow in escrows {
+            let escrow = escrow.into_escrow();
+            if !escrow.claimed {
+                contract.total_safety_deposits = contract
+                    .total_safety_deposits
+                    .saturating_add(escrow.safety_deposit);
+                if !escrow.is_source {
+                    contract.adjust_destination_escrow_total(&escrow.asset, escrow.amount, true);
+                }
+            }
+            contract.escrows.insert(escrow.id(), escrow);
+        }
+        contract.registered_keys = legacy.registered_keys;
+        contract
+    }
+
+    fn assert_owner(&self) {
+        require!(
+            env::predecessor_account_id() == self.owner_id,
+            "Only the owner can call this method"
+        );
+    }
+
+    // --- Owner Configuration ---
+
+    /// Sets the grace period (in seconds) added before any public withdrawal or cancellation window.
+    pub fn set_public_grace(&mut self, grace_seconds: u64) {
+        self.assert_owner();
+        require!(
+            grace_seconds <= MAX_PUBLIC_GRACE_SECS,
+            "Public grace period is too long"
+        );
+        self.public_grace = grace_seconds;
+    }
+
+    pub fn get_public_grace(&self) -> u64 {
+        self.public_grace
+    }
+
+    /// Sets the minimum reveal window that new escrows' timelocks must leave between the
+    /// destination public withdrawal and the source cancellation. Existing escrows are not affected.
+    pub fn set_min_reveal_grace(&mut self, grace_seconds: u64) {
+        self.assert_owner();
+        require!(
+            grace_seconds <= MAX_REVEAL_GRACE_SECS,
+            "Reveal grace is too long"
+        );
+        self.min_reveal_grace = grace_seconds;
+    }
+
+    pub fn get_min_reveal_grace(&self) -> u64 {
+        self.min_reveal_grace
+    }
+
+    /// Sets the delays used when an order or destination escrow leaves its timelocks out.
+    /// Orders signed against the previous defaults stop verifying, since they committed to them.
+    pub fn set_default_timelocks(&mut self, timelocks: TimelockDelays) {
+        self.assert_owner();
+        timelocks.validate(env::block_timestamp(), self.min_reveal_grace);
+        self.default_timelocks = Some(timelocks);
+    }
+
+    pub fn get_default_timelocks(&self) -> Option<TimelockDelays> {
+        self.default_timelocks.clone()
+    }
+
+    /// Returns the owner-configured timelock rules new escrows are currently held to.
+    pub fn get_timelock_constraints(&self) -> TimelockConstraints {
+        TimelockConstraints {
+            min_reveal_grace: self.min_reveal_grace,
+            public_grace: self.public_grace,
+            max_activation_horizon: MAX_ACTIVATION_HORIZON_SECS,
+        }
+    }
+
+    /// Proposes a new protocol fee, replacing any pending proposal. It can be applied with
+    /// `apply_fee_change` once `fee_change_delay` has passed, so users see it coming.
+    pub fn propose_fee_change(&mut self, fee_bps: u16) {
+        self.assert_owner();
+        require!(fee_bps <= MAX_FEE_BPS, "Fee is too high");
+        let effective_at = env::block_timestamp() + self.fee_change_delay * NANOS_IN_SEC;
+        self.pending_fee_change = Some(PendingFeeChange {
+            fee_bps,
+            effective_at,
+        });
+        log!(
+            "FEE_CHANGE_PROPOSED: fee_bps='{}', effective_at='{}'",
+            fee_bps,
+            effective_at
+        );
+    }
+
+    /// Activates the pending fee change once its delay has passed.
+    pub fn apply_fee_change(&mut self) {
+        self.assert_owner();
+        let change = self
+            .pending_fee_change
+            .take()
+            .expect("No fee change is pending");
+        require!(
+            env::block_timestamp() >= change.effective_at,
+            "Fee change is not effective yet"
+        );
+        self.fee_bps = change.fee_bps;
+        log!("FEE_CHANGE_APPLIED: fee_bps='{}'", change.fee_bps);
+    }
+
+    pub fn get_pending_fee_change(&self) -> Option<PendingFeeChange> {
+        self.pending_fee_change.clone()
+    }
+
+    /// Raises the delay (in seconds) fee changes must wait. It can never be lowered, so users can
+    /// rely on the notice it gives; a pending proposal keeps the timestamp it was given.
+    pub fn set_fee_change_delay(&mut self, delay_seconds: u64) {
+        self.assert_owner();
+        require!(
+            delay_seconds >= self.fee_change_delay,
+            "Fee change delay can only be raised"
+        );
+        require!(
+            delay_seconds <= MAX_FEE_CHANGE_DELAY_SECS,
+            "Fee change delay is too long"
+        );
+        self.fee_change_delay = delay_seconds;
+    }
+
+    pub fn get_fee_change_delay(&self) -> u64 {
+        self.fee_change_delay
+    }
+
+    pub fn get_fee_bps(&self) -> u16 {
+        self.fee_bps
+    }
+
+    /// Sets the penalty taken from the refund of every cancelled fungible-token escrow and who
+    /// receives it. A zero penalty refunds cancellations in full.
+    pub fn set_cancellation_penalty(&mut self, penalty_bps: u16, recipient: PenaltyRecipient) {
+        self.assert_owner();
+        require!(
+            penalty_bps <= MAX_CANCELLATION_PENALTY_BPS,
+            "Cancellation penalty is too high"
+        );
+        self.cancellation_penalty_bps = penalty_bps;
+        self.cancellation_penalty_recipient = recipient;
+    }
+
+    pub fn get_cancellation_penalty(&self) -> (u16, PenaltyRecipient) {
+        (
+            self.cancellation_penalty_bps,
+            self.cancellation_penalty_recipient,
+        )
+    }
+
+    /// Sets the share of the safety deposit a public caller earns for cancelling, in basis points.
+    pub fn set_public_cancel_reward_bps(&mut self, reward_bps: u16) {
+        self.assert_owner();
+        require!(
+            u128::from(reward_bps) <= BPS_DENOMINATOR,
+            "Reward cannot exceed the safety deposit"
+        );
+        self.public_cancel_reward_bps = reward_bps;
+    }
+
+    pub fn get_public_cancel_reward_bps(&self) -> u16 {
+        self.public_cancel_reward_bps
+    }
+
+    /// Makes a public withdrawer's share of the safety deposit grow linearly from nothing, when the
+    /// public withdrawal window opens, to all of it `period_seconds` later. `None` pays it all at once.
+    pub fn set_safety_deposit_decay_period(&mut self, period_seconds: Option<u64>) {
+        self.assert_owner();
+        if let Some(period_seconds) = period_seconds {
+            require!(period_seconds > 0, "Decay period must be positive");
+            require!(
+                period_seconds <= MAX_SAFETY_DEPOSIT_DECAY_SECS,
+                "Decay period is too long"
+            );
         }
+        self.safety_deposit_decay_period = period_seconds;
+    }
+
+    pub fn get_safety_deposit_decay_period(&self) -> Option<u64> {
+        self.safety_deposit_decay_period
+    }
+
+    /// Restricts which hash functions new escrows may use, e.g. to sha256 only.
+    /// Existing escrows are unaffected.
+    pub fn set_allowed_hash_algos(&mut self, hash_algos: Vec<HashAlgo>) {
+        self.assert_owner();
+        require!(
+            !hash_algos.is_empty(),
+            "At least one hash algorithm must be allowed"
+        );
+        self.allowed_hash_algos = hash_algos;
+    }
+
+    pub fn get_allowed_hash_algos(&self) -> Vec<HashAlgo> {
+        self.allowed_hash_algos.clone()
+    }
+
+    /// Opt-in check that a destination escrow's asset is a real FT contract.
+    /// Costs an extra cross-contract call per escrow, so it is off by default.
+    pub fn set_verify_ft_metadata(&mut self, enabled: bool) {
+        self.assert_owner();
+        self.verify_ft_metadata = enabled;
+    }
+
+    pub fn get_verify_ft_metadata(&self) -> bool {
+        self.verify_ft_metadata
+    }
+
+    /// Sets the minimum NEAR safety deposit for escrows of `token_id`, in yoctoNEAR per base unit
+    /// of the token, as a nominal value in place of a price oracle. `None` removes the minimum.
+    pub fn set_min_safety_deposit_per_unit(&mut self, token_id: AccountId, min: Option<U128>) {
+        self.assert_owner();
+        match min {
+            Some(min) => self.min_safety_deposit_per_unit.insert(token_id, min),
+            None => self.min_safety_deposit_per_unit.remove(&token_id),
+        };
+    }
+
+    pub fn get_min_safety_deposit_per_unit(&self, token_id: AccountId) -> Option<U128> {
+        self.min_safety_deposit_per_unit.get(&token_id).copied()
+    }
+
+    /// Sets the smallest amount of `token_id` an escrow on either leg may hold, in the token's
+    /// base units, so the floor can follow the token's decimals and value. `None` removes it.
+    pub fn set_min_escrow_amount(&mut self, token_id: AccountId, min: Option<U128>) {
+        self.assert_owner();
+        match min {
+            Some(min) => self.min_escrow_amount.insert(token_id, min),
+            None => self.min_escrow_amount.remove(&token_id),
+        };
+    }
+
+    pub fn get_min_escrow_amount(&self, token_id: AccountId) -> Option<U128> {
+        self.min_escrow_amount.get(&token_id).copied()
+    }
+
+    /// Caps how many unsettled escrows a single resolver may take at once, limiting the exposure
+    /// to any one resolver. `None` removes the cap. Escrows already created are not affected.
+    pub fn set_max_active_per_resolver(&mut self, max_active: Option<u64>) {
+        self.assert_owner();
+        self.max_active_escrows_per_resolver = max_active;
     }
 
+    pub fn get_max_active_per_resolver(&self) -> Option<u64> {
+        self.max_active_escrows_per_resolver
+    }
+
+    /// Sets the account whose internal ledger balance accrues protocol fees.
+    /// The recipient withdraws them in bulk via `withdraw_deposit`.
+    /// It can be changed but not unset, so in-flight settlements always have a fee recipient;
+    /// set the fee to zero to stop charging fees.
+    pub fn set_fee_recipient(&mut self, fee_recipient: AccountId) {
+        self.assert_owner();
+        self.fee_recipient = Some(fee_recipient);
+    }
+
+    pub fn get_fee_recipient(&self) -> Option<AccountId> {
+        self.fee_recipient.clone()
+    }
+
+    pub fn add_relayer(&mut self, account_id: AccountId) {
+        self.assert_owner();
+        self.relayers.insert(account_id);
+    }
+
+    pub fn remove_relayer(&mut self, account_id: AccountId) {
+        self.assert_owner();
+        self.relayers.remove(&account_id);
+    }
+
+    pub fn is_relayer(&self, account_id: AccountId) -> bool {
+        self.relayers.contains(&account_id)
+    }
+
+    /// Registers order-signing keys for the signer. `permission` records the role of all of
+    /// them; without it they are treated as full-access keys.
     #[payable]
-    pub fn register_keys(&mut self, public_keys: Vec<PublicKey>) {
+    pub fn register_keys(
+        &mut self,
+        public_keys: Vec<PublicKey>,
+        permission: Option<KeyPermission>,
+    ) {
         let account_id = env::signer_account_id();
+        if let Some(KeyPermission::FunctionCall { receiver_id, .. }) = &permission {
+            require!(
+                *receiver_id == env::current_account_id(),
+                "Function-call keys must be scoped to this contract"
+            );
+        }
+        self.internal_register_keys(account_id.clone(), public_keys.clone());
+        for pk in public_keys {
+            let key = (account_id.clone(), pk);
+            match &permission {
+                Some(permission @ KeyPermission::FunctionCall { .. }) => {
+                    self.key_permissions.insert(key, permission.clone());
+                }
+                _ => {
+                    self.key_permissions.remove(&key);
+                }
+            }
+        }
+    }
+
+    /// Requires every order of the signer to carry signatures from `threshold` distinct registered
+    /// keys. A threshold of one, the default, restores single-signature orders.
+    pub fn set_signature_threshold(&mut self, threshold: u32) {
+        let maker_id = env::signer_account_id();
+        require!(threshold >= 1, "Signature threshold must be at least one");
+        require!(
+            threshold <= self.get_registered_key_count(maker_id.clone()),
+            "Signature threshold exceeds the registered keys"
+        );
+        if threshold == 1 {
+            self.signature_thresholds.remove(&maker_id);
+        } else {
+            self.signature_thresholds
+                .insert(maker_id.clone(), threshold);
+        }
+        log!(
+            "SIGNATURE_THRESHOLD_SET: maker='{}', threshold='{}'",
+            maker_id,
+            threshold
+        );
+    }
+
+    pub fn get_signature_threshold(&self, maker_id: AccountId) -> u32 {
+        self.signature_thresholds
+            .get(&maker_id)
+            .copied()
+            .unwrap_or(1)
+    }
+
+    /// Owner-assisted onboarding: registers keys on behalf of several accounts in one call.
+    pub fn admin_register_keys(&mut self, entries: Vec<(AccountId, Vec<PublicKey>)>) {
+        self.assert_owner();
+        require!(
+            entries.len() <= MAX_ADMIN_KEY_BATCH,
+            "Too many accounts in one batch"
+        );
+        for (account_id, public_keys) in entries {
+            self.internal_register_keys(account_id, public_keys);
+        }
+    }
+
+    fn internal_register_keys(&mut self, account_id: AccountId, public_keys: Vec<PublicKey>) {
         let mut keys = self
             .registered_keys
             .get(&account_id)
@@ -89,9 +759,46 @@ impl Contract {
                 keys.push(pk.clone());
             }
         }
+        require!(
+            keys.len() <= MAX_KEYS_PER_ACCOUNT,
+            "Too many keys registered for account"
+        );
         self.registered_keys.insert(account_id, keys);
     }
 
+    /// Revokes every key registered by the signer, e.g. after a device compromise.
+    #[payable]
+    pub fn remove_all_keys(&mut self) {
+        assert_one_yocto();
+        let account_id = env::signer_account_id();
+        if let Some(keys) = self.registered_keys.remove(&account_id) {
+            for pk in keys {
+                self.key_permissions.remove(&(account_id.clone(), pk));
+            }
+            log!("KEYS_CLEARED: account='{}'", account_id);
+        }
+    }
+
+    /// Returns the role of a registered key, or `None` if the account has not registered it.
+    pub fn get_key_permission(
+        &self,
+        account_id: AccountId,
+        public_key: PublicKey,
+    ) -> Option<KeyPermission> {
+        if !self
+            .get_registered_keys(account_id.clone())
+            .contains(&public_key)
+        {
+            return None;
+        }
+        Some(
+            self.key_permissions
+                .get(&(account_id, public_key))
+                .cloned()
+                .unwrap_or(KeyPermission::FullAccess),
+        )
+    }
+
     pub fn get_registered_keys(&self, account_id: AccountId) -> Vec<PublicKey> {
         self.registered_keys
             .get(&account_id)
@@ -99,50 +806,1420 @@ impl Contract {
             .unwrap_or_default()
     }
 
-    // --- Deposit Management ---
-    pub fn withdraw_deposit(&mut self, token_id: AccountId, amount: U128) -> Promise {
-        let account_id = env::predecessor_account_id();
-        self.deposits
-            .assert_available_for_withdrawal(&account_id, &token_id, amount);
-        self.deposits.debit_total(&account_id, &token_id, amount);
+    /// Retracts a signed order before any resolver fills it by consuming its nonce.
+    pub fn invalidate_nonce(&mut self, nonce: u128) {
+        let maker_id = env::signer_account_id();
+        require!(
+            self.used_nonces.insert((maker_id.clone(), nonce)),
+            "Nonce already used"
+        );
+        log!("NONCE_INVALIDATED: maker='{}', nonce='{}'", maker_id, nonce);
+    }
 
-        ext_fungible_token::ext(token_id.clone())
-            .with_attached_deposit(NearToken::from_yoctonear(1))
-            .with_static_gas(env::prepaid_gas().saturating_div(4))
-            .ft_transfer(
-                account_id.clone(),
-                amount,
-                Some("Deposit withdrawal".to_string()),
-            )
-            .then(
-                ext_self::ext(env::current_account_id())
-                    .with_static_gas(env::prepaid_gas().saturating_div(4))
-                    .on_deposit_withdrawn(account_id, token_id, amount),
-            )
+    /// Raises the signer's nonce high-water mark to `nonce`, opting into sequential nonces.
+    /// From then on only orders with a higher nonce can be filled, and every fill advances the
+    /// mark, so replay protection no longer stores each nonce. It also retracts all unfilled
+    /// orders at or below `nonce`.
+    pub fn bump_nonce(&mut self, nonce: u128) {
+        let maker_id = env::signer_account_id();
+        if let Some(high_water) = self.nonce_high_water.get(&maker_id) {
+            require!(nonce > *high_water, "Nonce must increase");
+        }
+        self.nonce_high_water.insert(maker_id.clone(), nonce);
+        log!("NONCE_BUMPED: maker='{}', nonce='{}'", maker_id, nonce);
     }
 
-    pub fn get_available_balance(&self, account_id: AccountId, token_id: AccountId) -> U128 {
-        self.deposits.get_available_balance(&account_id, &token_id)
+    /// Returns the maker's nonce high-water mark, or `None` if they use unordered nonces.
+    pub fn get_nonce_high_water(&self, maker_id: AccountId) -> Option<U128> {
+        self.nonce_high_water.get(&maker_id).copied().map(U128)
     }
 
-    // --- Core HTLC Logic ---
+    pub fn is_nonce_used(&self, maker_id: AccountId, nonce: u128) -> bool {
+        self.nonce_high_water
+            .get(&maker_id)
+            .is_some_and(|high_water| nonce <= *high_water)
+            || self.used_nonces.contains(&(maker_id, nonce))
+    }
 
-    /// Primary entry point for all Fungible Token interactions.
-    /// Can either be a deposit or the creation of a destination-side escrow.
+    /// Returns whether the maker's order with `nonce` is unused, invalidated, or filled, and in
+    /// the last case the state of the escrow it created. A filled order whose escrow is no longer
+    /// stored reports its consumed nonce as `Invalidated`.
+    pub fn get_order_status(&self, maker_id: AccountId, nonce: u128) -> OrderStatus {
+        if let Some(escrow) = self
+            .order_escrows
+            .get(&(maker_id.clone(), nonce))
+            .and_then(|escrow_id| self.escrows.get(escrow_id))
+        {
+            return OrderStatus::Filled {
+                hashlock: escrow.hashlock.into(),
+                state: escrow.state(env::block_timestamp(), self.public_grace),
+            };
+        }
+        if self.is_nonce_used(maker_id, nonce) {
+            OrderStatus::Invalidated
+        } else {
+            OrderStatus::Unused
+        }
+    }
+
+    /// Canonical bytes a maker signs for `order`, base64-encoded.
+    /// An order without timelocks is encoded with the current default timelocks filled in.
+    pub fn get_order_message_bytes(&self, order: SignedOrder) -> String {
+        STANDARD.encode(self.with_default_timelocks(order).to_message_bytes())
+    }
+
+    /// The sha256 of `get_order_message_bytes`, base64-encoded; this is what the ed25519 signature covers.
+    pub fn get_order_hash(&self, order: SignedOrder) -> String {
+        STANDARD.encode(env::sha256(
+            &self.with_default_timelocks(order).to_message_bytes(),
+        ))
+    }
+
+    /// The digest, base64-encoded, a maker signs to have the destination escrow under `hashlock`
+    /// pay out to `recipient` instead of to the maker.
+    pub fn get_payout_authorization_hash(
+        &self,
+        maker_id: AccountId,
+        hashlock: Base58CryptoHash,
+        recipient: AccountId,
+    ) -> String {
+        STANDARD.encode(payout_authorization_hash(
+            &maker_id,
+            &hashlock.into(),
+            &recipient,
+        ))
+    }
+
+    pub fn get_registered_key_count(&self, account_id: AccountId) -> u32 {
+        self.registered_keys
+            .get(&account_id)
+            .map_or(0, |keys| keys.len() as u32)
+    }
+
+    /// Lists accounts that have registered keys, one page at a time.
+    pub fn get_registered_accounts(&self, from_index: u32, limit: u32) -> Vec<AccountId> {
+        self.registered_keys
+            .keys()
+            .skip(from_index as usize)
+            .take(limit as usize)
+            .cloned()
+            .collect()
+    }
+
+    // --- Order Book ---
+    /// Posts the signer's open order on-chain for resolvers to find and fill with
+    /// `fill_posted_order`. Like key registration, it acts for the signer. The attached NEAR must
+    /// cover the order's storage; the rest is refunded, and the storage deposit is returned once
+    /// the order is filled, cancelled or pruned.
     #[payable]
-    pub fn ft_on_transfer(
+    pub fn post_order(&mut self, order: SignedOrder, signature: String, public_key: PublicKey) {
+        let maker_id = env::signer_account_id();
+        require!(
+            order.maker_id == maker_id,
+            "Only the maker can post its order"
+        );
+        let order = self.with_default_timelocks(order);
+        require!(
+            env::block_timestamp() <= order.deadline,
+            "Order deadline has passed"
+        );
+        require!(
+            !self.is_nonce_used(maker_id.clone(), order.nonce),
+            "Nonce already used"
+        );
+        let order_key = (maker_id.clone(), order.nonce);
+        require!(
+            !self.posted_orders.contains_key(&order_key),
+            "Order is already posted"
+        );
+        require!(
+            self.get_signature_threshold(maker_id.clone()) == 1,
+            "Orders needing several signatures cannot be posted"
+        );
+        self.assert_maker_signing_key(&maker_id, &public_key);
+        assert_ed25519_signature(
+            &decode_base64(&signature, "Invalid base64 signature"),
+            &env::sha256(&order.to_message_bytes()),
+            &public_key,
+        );
+
+        let storage_before = env::storage_usage();
+        self.posted_orders.insert(
+            order_key.clone(),
+            PostedOrder {
+                order,
+                signature,
+                public_key,
+                storage_deposit: NearToken::from_yoctonear(0),
+            },
+        );
+        self.posted_orders.flush();
+        let storage_deposit = env::storage_byte_cost().saturating_mul(u128::from(
+            env::storage_usage().saturating_sub(storage_before),
+        ));
+        let attached = env::attached_deposit();
+        require!(
+            attached >= storage_deposit,
+            format!(
+                "Attach at least {} yoctoNEAR to cover the order's storage",
+                storage_deposit.as_yoctonear()
+            )
+        );
+        self.posted_orders
+            .get_mut(&order_key)
+            .expect("Order was just posted")
+            .storage_deposit = storage_deposit;
+        let refund = attached.saturating_sub(storage_deposit);
+        if !refund.is_zero() {
+            Promise::new(maker_id.clone()).transfer(refund);
+        }
+        log!(
+            "ORDER_POSTED: maker='{}', nonce='{}'",
+            maker_id,
+            order_key.1
+        );
+    }
+
+    /// Removes the signer's posted order and retracts its nonce, so the order can no longer be
+    /// filled from the book or from a copy of it.
+    pub fn cancel_order(&mut self, nonce: u128) {
+        let maker_id = env::signer_account_id();
+        let order_key = (maker_id.clone(), nonce);
+        require!(
+            self.remove_posted_order(&order_key),
+            "No posted order with this nonce"
+        );
+        self.used_nonces.insert(order_key);
+        log!("ORDER_CANCELLED: maker='{}', nonce='{}'", maker_id, nonce);
+    }
+
+    /// Fills a posted order as its taker, like `initiate_source_escrow` with the posted signature.
+    #[payable]
+    pub fn fill_posted_order(
         &mut self,
-        sender_id: AccountId,
-        amount: U128,
-        msg: String,
-    ) -> PromiseOrValue<U128> {
-        let token_contract_id = env::predecessor_account_id();
-        let ft_message: FtMessage = serde_json::from_str(&msg).expect("Invalid FtMessage format");
+        maker_id: AccountId,
+        nonce: u128,
+        options: Option<FillOptions>,
+    ) {
+        let posted = self
+            .posted_orders
+            .get(&(maker_id, nonce))
+            .cloned()
+            .expect("No posted order with this nonce");
+        self.initiate_source_escrow(posted.order, posted.signature, posted.public_key, options);
+    }
+
+    /// Lists the posted orders among the next `limit` from `from_index` that can still be filled.
+    /// Like `prune_posted_orders`, it pages over every posted order; continue from `next_index`.
+    pub fn get_open_orders(&self, from_index: u32, limit: u32) -> OpenOrders {
+        let now = env::block_timestamp();
+        let orders = self
+            .posted_orders
+            .iter()
+            .skip(from_index as usize)
+            .take(limit as usize)
+            .filter(|((maker_id, nonce), posted)| {
+                now <= posted.order.deadline && !self.is_nonce_used(maker_id.clone(), *nonce)
+            })
+            .map(|(_, posted)| posted.clone())
+            .collect();
+        let next_index = from_index.saturating_add(limit);
+        OpenOrders {
+            orders,
+            next_index: (next_index < self.posted_orders.len()).then_some(next_index),
+        }
+    }
+
+    /// Removes posted orders among the next `limit` that expired or whose nonce was retracted,
+    /// refunding their storage to the makers. Anyone may call it; returns how many were removed.
+    pub fn prune_posted_orders(&mut self, from_index: u32, limit: u32) -> u32 {
+        let now = env::block_timestamp();
+        let stale: Vec<(AccountId, u128)> = self
+            .posted_orders
+            .iter()
+            .skip(from_index as usize)
+            .take(limit as usize)
+            .filter(|((maker_id, nonce), posted)| {
+                now > posted.order.deadline || self.is_nonce_used(maker_id.clone(), *nonce)
+            })
+            .map(|(order_key, _)| order_key.clone())
+            .collect();
+        for order_key in &stale {
+            self.remove_posted_order(order_key);
+        }
+        stale.len() as u32
+    }
+
+    // --- Deposit Management ---
+    /// Credits the attached NEAR to the caller's native ledger balance, e.g. to pre-fund safety deposits.
+    #[payable]
+    pub fn deposit_near(&mut self) {
+        let account_id = env::predecessor_account_id();
+        let amount = U128(env::attached_deposit().as_yoctonear());
+        require!(amount.0 > 0, "Attach NEAR to deposit");
+        let token_id = Self::native_token_id();
+        self.deposits.credit_total(&account_id, &token_id, amount);
+        self.deposits
+            .record_external_deposit(&account_id, &token_id, amount);
+        log!(
+            "DEPOSIT: account='{}', token='{}', amount='{}'",
+            account_id,
+            token_id,
+            amount.0
+        );
+    }
+
+    /// The token id native NEAR is held under in the deposit ledger: this contract's own account id,
+    /// which can never be a fungible token calling `ft_on_transfer`.
+    pub fn get_native_token_id(&self) -> AccountId {
+        Self::native_token_id()
+    }
+
+    fn native_token_id() -> AccountId {
+        env::current_account_id()
+    }
+
+    /// Withdraws available ledger funds to the caller; native NEAR is sent as a plain transfer.
+    pub fn withdraw_deposit(&mut self, token_id: AccountId, amount: U128) -> Promise {
+        let account_id = env::predecessor_account_id();
+        self.internal_withdraw_deposit(account_id, token_id, amount)
+    }
+
+    fn internal_withdraw_deposit(
+        &mut self,
+        account_id: AccountId,
+        token_id: AccountId,
+        amount: U128,
+    ) -> Promise {
+        self.deposits
+            .assert_available_for_withdrawal(&account_id, &token_id, amount);
+        self.deposits.debit_total(&account_id, &token_id, amount);
+
+        let transfer = if token_id == Self::native_token_id() {
+            Promise::new(account_id.clone()).transfer(NearToken::from_yoctonear(amount.0))
+        } else {
+            ext_fungible_token::ext(token_id.clone())
+                .with_attached_deposit(NearToken::from_yoctonear(1))
+                .with_static_gas(env::prepaid_gas().saturating_div(4))
+                .ft_transfer(
+                    account_id.clone(),
+                    amount,
+                    Some("Deposit withdrawal".to_string()),
+                )
+        };
+        transfer.then(
+            ext_self::ext(env::current_account_id())
+                .with_static_gas(env::prepaid_gas().saturating_div(4))
+                .on_deposit_withdrawn(account_id, token_id, amount),
+        )
+    }
+
+    pub fn get_available_balance(&self, account_id: AccountId, token_id: AccountId) -> U128 {
+        self.deposits.get_available_balance(&account_id, &token_id)
+    }
+
+    /// Lists accounts that hold deposits, one page at a time.
+    pub fn get_users_with_deposits(&self, from_index: u32, limit: u32) -> Vec<AccountId> {
+        self.deposits
+            .get_users_with_deposits(from_index as usize, limit as usize)
+    }
+
+    /// Returns the lifetime totals deposited into and withdrawn from the contract by an account.
+    pub fn get_deposit_lifetime_stats(
+        &self,
+        account_id: AccountId,
+        token_id: AccountId,
+    ) -> DepositLifetimeStats {
+        self.deposits.get_lifetime_stats(&account_id, &token_id)
+    }
+
+    /// Lists `(account, total, locked)` balances of one token, paginated over the accounts holding it.
+    /// Auditors can sum the totals and compare them with the contract's `ft_balance_of`.
+    pub fn get_token_balances(
+        &self,
+        token_id: AccountId,
+        from_index: u32,
+        limit: u32,
+    ) -> Vec<(AccountId, U128, U128)> {
+        self.deposits
+            .get_token_balances(&token_id, from_index as usize, limit as usize)
+    }
+
+    // --- Escrow Views ---
+
+    /// Looks up an escrow by its escrow id, or by its hashlock when only one side of the swap uses it.
+    pub fn get_escrow(&self, hashlock: Base58CryptoHash) -> Option<EscrowView> {
+        let escrow_id = self.resolve_escrow_id(hashlock);
+        self.escrows.get(&escrow_id).map(EscrowView::from)
+    }
+
+    /// Returns the base64 of the escrow's borsh encoding exactly as stored, for debugging and
+    /// off-chain replay. Decode it with the borsh schema of `Escrow`.
+    pub fn get_escrow_raw(&self, hashlock: Base58CryptoHash) -> Option<String> {
+        let escrow_id = self.resolve_escrow_id(hashlock);
+        self.escrows.get(&escrow_id).map(|escrow| {
+            STANDARD.encode(borsh::to_vec(escrow).expect("Escrow is borsh-serializable"))
+        })
+    }
+
+    /// The base64 sha256 of the order message a source escrow was created from, as returned by
+    /// `get_order_hash`, so the maker's signature can be re-verified. `None` for destination
+    /// escrows and unknown hashlocks.
+    pub fn get_escrow_order_hash(&self, hashlock: Base58CryptoHash) -> Option<String> {
+        let escrow_id = self.resolve_escrow_id(hashlock);
+        self.escrows
+            .get(&escrow_id)?
+            .order_hash
+            .map(|order_hash| STANDARD.encode(order_hash))
+    }
+
+    /// Looks up the unclaimed escrow locked by `secret` under `algo`, for relayers matching secrets
+    /// revealed on the counterparty chain. Returns `None` for invalid base64.
+    pub fn find_escrow_by_secret(&self, secret: String, algo: HashAlgo) -> Option<EscrowView> {
+        let secret_bytes = try_decode_base64(&secret)?;
+        let hashlock = algo.hash(&secret_bytes);
+        [false, true]
+            .into_iter()
+            .filter_map(|is_source| self.escrows.get(&escrow_id(&hashlock, is_source)))
+            .find(|escrow| escrow.hash_algo == algo && !escrow.claimed)
+            .map(EscrowView::from)
+    }
+
+    /// Computes the hashlock of a base64 `secret` under `algo`, exactly as escrows check it,
+    /// so clients can cross-check their own hashlock computation.
+    pub fn compute_hashlock(&self, secret: String, algo: HashAlgo) -> Base58CryptoHash {
+        let secret_bytes = decode_base64(&secret, "Invalid base64 secret");
+        algo.hash(&secret_bytes).into()
+    }
+
+    /// Returns the escrow's lifecycle state, or `None` for an unknown escrow.
+    pub fn get_escrow_state(&self, hashlock: Base58CryptoHash) -> Option<EscrowState> {
+        let escrow_id = self.resolve_escrow_id(hashlock);
+        self.escrows
+            .get(&escrow_id)
+            .map(|escrow| escrow.state(env::block_timestamp(), self.public_grace))
+    }
+
+    /// Current block timestamp in nanoseconds, so clients can anchor window checks to chain time.
+    pub fn now(&self) -> U64 {
+        U64(env::block_timestamp())
+    }
+
+    /// Seconds until the escrow's first actionable window opens, rounded toward negative infinity
+    /// so it is negative as soon as the window has been open for any part of a second.
+    /// Returns `None` for unknown escrows and escrows that are already claimed.
+    pub fn time_until_window(&self, hashlock: Base58CryptoHash) -> Option<i64> {
+        let escrow_id = self.resolve_escrow_id(hashlock);
+        let escrow = self.escrows.get(&escrow_id)?;
+        if escrow.claimed {
+            return None;
+        }
+        let opens_at = escrow.timelocks.first_window_opens_at(escrow.is_source) as i128;
+        let now = env::block_timestamp() as i128;
+        Some((opens_at - now).div_euclid(NANOS_IN_SEC as i128) as i64)
+    }
+
+    /// Tallies escrow states over one page of escrows.
+    /// Callers accumulate the counts across pages to cover the whole map.
+    pub fn count_escrows_by_state(&self, from_index: u32, limit: u32) -> StateCounts {
+        let now = env::block_timestamp();
+        let mut counts = StateCounts::default();
+        for escrow in self
+            .escrows
+            .values()
+            .skip(from_index as usize)
+            .take(limit as usize)
+        {
+            match escrow.state(now, self.public_grace) {
+                EscrowState::Open => counts.open += 1,
+                EscrowState::Withdrawable => counts.withdrawable += 1,
+                EscrowState::Cancellable => counts.cancellable += 1,
+                EscrowState::Settled => counts.settled += 1,
+            }
+        }
+        counts
+    }
+
+    /// Returns which action is currently possible on an escrow and who may perform it,
+    /// or `None` for an unknown escrow.
+    pub fn who_can_act_now(&self, hashlock: Base58CryptoHash) -> Option<ActionEligibility> {
+        let escrow_id = self.resolve_escrow_id(hashlock);
+        self.escrows
+            .get(&escrow_id)
+            .map(|escrow| escrow.eligibility(env::block_timestamp(), self.public_grace))
+    }
+
+    /// Returns whether `account_id` may currently withdraw (given the secret) or cancel an escrow.
+    /// Registered relayers may always fast-cancel unclaimed destination escrows.
+    /// Returns `None` for an unknown escrow.
+    pub fn is_escrow_claimable_by(
+        &self,
+        hashlock: Base58CryptoHash,
+        account_id: AccountId,
+    ) -> Option<ClaimEligibility> {
+        let escrow_id = self.resolve_escrow_id(hashlock);
+        let escrow = self.escrows.get(&escrow_id)?;
+        let mut eligibility =
+            escrow.claim_eligibility(&account_id, env::block_timestamp(), self.public_grace);
+        if !escrow.claimed && !escrow.is_source && self.relayers.contains(&account_id) {
+            eligibility.can_cancel = true;
+        }
+        Some(eligibility)
+    }
+
+    /// Returns what a public caller would earn by settling an escrow right now, so keeper bots
+    /// can weigh it against the gas cost. The reward is zero outside the public windows.
+    /// Returns `None` for an unknown escrow.
+    pub fn get_public_action_reward(&self, hashlock: Base58CryptoHash) -> Option<RewardEstimate> {
+        let escrow_id = self.resolve_escrow_id(hashlock);
+        self.escrows.get(&escrow_id).map(|escrow| {
+            let mut estimate =
+                escrow.public_action_reward(env::block_timestamp(), self.public_grace);
+            match estimate.action {
+                Some(EscrowAction::Cancel) => {
+                    estimate.safety_deposit = self.public_cancel_reward(escrow)
+                }
+                Some(EscrowAction::Withdraw) => {
+                    estimate.safety_deposit = self.public_withdrawal_reward(escrow)
+                }
+                None => {}
+            }
+            estimate
+        })
+    }
+
+    /// Returns the recommended gas to attach to `withdraw` or `cancel` for an escrow.
+    /// The estimate covers the call itself, the asset transfer and the settlement callback.
+    /// Returns `None` for an unknown escrow.
+    pub fn estimate_settlement_gas(&self, hashlock: Base58CryptoHash) -> Option<Gas> {
+        let escrow_id = self.resolve_escrow_id(hashlock);
+        let escrow = self.escrows.get(&escrow_id)?;
+        let transfer_gas = match escrow.asset {
+            Asset::Ft(_) => GAS_FOR_FT_TRANSFER,
+        };
+        Some(
+            GAS_FOR_SETTLEMENT_CALL
+                .saturating_add(transfer_gas)
+                .saturating_add(GAS_FOR_SETTLEMENT_CALLBACK),
+        )
+    }
+
+    // --- Reconciliation ---
+
+    /// Repairs the locked ledger balance backing a source escrow if it has drifted.
+    ///
+    /// The locked balance of a maker for a token must equal the sum of their source escrows
+    /// for that token that have not been settled yet (including claims still in flight).
+    /// Only the locked balance is adjusted, capped at the maker's total balance, so funds
+    /// are never created. Returns whether an adjustment was made; calling it again is a no-op.
+    pub fn reconcile(&mut self, hashlock: Base58CryptoHash) -> bool {
+        let escrow_id = self.resolve_escrow_id(hashlock);
+        let escrow = self
+            .escrows
+            .get(&escrow_id)
+            .cloned()
+            .expect(ERR_ESCROW_NOT_FOUND);
+        require!(
+            escrow.is_source,
+            "Only source escrows are backed by the deposit ledger"
+        );
+        let token_id = escrow
+            .asset
+            .ft_token_id()
+            .expect("Source escrow asset must be a fungible token");
+        self.reconcile_locked_balance(&escrow.maker, &token_id)
+    }
+
+    /// Repairs an account's locked balance of a token directly, for drift no escrow points at,
+    /// such as a lock left behind after every escrow backing it has settled. The balance is
+    /// recomputed the same way as in `reconcile`, freeing any phantom lock.
+    /// Only the owner or the account itself may call it. Returns whether an adjustment was made.
+    pub fn reconcile_locked(&mut self, account_id: AccountId, token_id: AccountId) -> bool {
+        let caller = env::predecessor_account_id();
+        require!(
+            caller == self.owner_id || caller == account_id,
+            "Only the owner or the account itself can reconcile its locked balance"
+        );
+        self.reconcile_locked_balance(&account_id, &token_id)
+    }
+
+    /// Checks the invariants of one page of escrows without changing anything, the read side of
+    /// `reconcile`: source escrows must be backed by their maker's locked ledger balance, and no
+    /// escrow may be claimed without settling or settled without being claimed. Owner only.
+    /// Stops early once too little gas remains; `scanned` tells where to resume.
+    pub fn audit_sweep(&self, from_index: u32, limit: u32) -> AuditReport {
+        self.assert_owner();
+        require!(limit <= MAX_AUDIT_BATCH, "Too many escrows in one batch");
+        let mut report = AuditReport::default();
+        let mut checked_balances: Vec<(AccountId, AccountId)> = Vec::new();
+        for (escrow_id, escrow) in self
+            .escrows
+            .iter()
+            .skip(from_index as usize)
+            .take(limit as usize)
+        {
+            let remaining_gas = env::prepaid_gas().saturating_sub(env::used_gas());
+            if remaining_gas < GAS_FOR_AUDIT_STEP {
+                break;
+            }
+            report.scanned += 1;
+            let escrow_id: Base58CryptoHash = (*escrow_id).into();
+            if escrow.claimed && !escrow.settled {
+                report
+                    .violations
+                    .push(AuditViolation::UnsettledClaim { escrow_id });
+            } else if escrow.settled && !escrow.claimed {
+                report
+                    .violations
+                    .push(AuditViolation::SettledWithoutClaim { escrow_id });
+            }
+
+            let Some(token_id) = escrow.asset.ft_token_id().filter(|_| escrow.is_source) else {
+                continue;
+            };
+            let key = (escrow.maker.clone(), token_id);
+            if checked_balances.contains(&key) {
+                continue;
+            }
+            let expected = self.expected_locked_balance(&key.0, &key.1);
+            let locked = self.deposits.get_locked_balance(&key.0, &key.1).0;
+            if locked != expected {
+                report
+                    .violations
+                    .push(AuditViolation::LockedBalanceMismatch {
+                        account_id: key.0.clone(),
+                        token_id: key.1.clone(),
+                        locked: U128(locked),
+                        expected: U128(expected),
+                    });
+            }
+            checked_balances.push(key);
+        }
+        report
+    }
+
+    /// Last-resort recovery of abandoned funds: moves an unsettled escrow, or an account's
+    /// available balance of a token, onto the owner's ledger once it has seen no activity for
+    /// `DORMANCY_PERIOD_SECS`. The period is deliberately long and cannot be configured. Owner only.
+    pub fn emergency_withdraw_to_owner(&mut self, target: DormantFunds) {
+        self.assert_owner();
+        let owner_id = self.owner_id.clone();
+        match target {
+            DormantFunds::Escrow { hashlock } => {
+                let escrow_id = self.resolve_escrow_id(hashlock);
+                let mut escrow = self
+                    .escrows
+                    .get(&escrow_id)
+                    .cloned()
+                    .expect(ERR_ESCROW_NOT_FOUND);
+                require!(!escrow.claimed, "Escrow already claimed");
+                Self::assert_dormant(escrow.last_activity);
+
+                if let Some(token_id) = escrow.asset.ft_token_id() {
+                    let amount = token_to_ft_amount(escrow.amount);
+                    if escrow.is_source {
+                        self.deposits.debit_locked(&escrow.maker, &token_id, amount);
+                        self.deposits.debit_total(&escrow.maker, &token_id, amount);
+                    } else {
+                        self.adjust_destination_escrow_total(&escrow.asset, escrow.amount, false);
+                    }
+                    self.deposits.credit_total(&owner_id, &token_id, amount);
+                }
+                match &escrow.safety_deposit_token {
+                    Some(token_id) => {
+                        let amount = token_to_ft_amount(escrow.safety_deposit);
+                        self.deposits.debit_locked(&escrow.taker, token_id, amount);
+                        self.deposits.debit_total(&escrow.taker, token_id, amount);
+                        self.deposits.credit_total(&owner_id, token_id, amount);
+                    }
+                    None if !escrow.safety_deposit_returned => {
+                        self.total_safety_deposits = self
+                            .total_safety_deposits
+                            .saturating_sub(escrow.safety_deposit);
+                        self.deposits.credit_total(
+                            &owner_id,
+                            &Self::native_token_id(),
+                            U128(escrow.safety_deposit.as_yoctonear()),
+                        );
+                    }
+                    None => {}
+                }
+
+                escrow.claimed = true;
+                escrow.settled = true;
+                escrow.safety_deposit_returned = true;
+                escrow.last_activity = env::block_timestamp();
+                // The swap never completed, so it is recorded as a cancellation by the owner.
+                self.update_resolver_stats(&escrow.taker, |stats| stats.cancelled += 1);
+                self.settlements.insert(
+                    escrow_id,
+                    SettlementRecord {
+                        claimant: owner_id.clone(),
+                        settled_at: env::block_timestamp(),
+                        was_cancel: true,
+                        amount: escrow.amount,
+                    },
+                );
+                log!(
+                    "EMERGENCY_SWEEP: hashlock='{}', amount='{}'",
+                    bs58::encode(&escrow.hashlock).into_string(),
+                    escrow.amount.as_yoctonear()
+                );
+                self.escrows.insert(escrow_id, escrow);
+            }
+            DormantFunds::Deposit {
+                account_id,
+                token_id,
+            } => {
+                let last_activity = self
+                    .deposits
+                    .get_last_activity(&account_id)
+                    .expect("No activity recorded for this account");
+                Self::assert_dormant(last_activity);
+                let amount = self.deposits.get_available_balance(&account_id, &token_id);
+                require!(amount.0 > 0, "Nothing to sweep");
+
+                self.deposits.debit_total(&account_id, &token_id, amount);
+                self.deposits.credit_total(&owner_id, &token_id, amount);
+                log!(
+                    "EMERGENCY_SWEEP: account='{}', token='{}', amount='{}'",
+                    account_id,
+                    token_id,
+                    amount.0
+                );
+            }
+        }
+    }
+
+    /// When any of the account's ledger balances last changed.
+    pub fn get_account_last_activity(&self, account_id: AccountId) -> Option<U64> {
+        self.deposits.get_last_activity(&account_id).map(U64)
+    }
+
+    /// Reports an escrow's asset and amount together with the contract's actual balance of the
+    /// token, queried with `ft_balance_of`, and the balance the contract's accounting says it
+    /// should hold (`get_tvl`). A real balance below the tracked one reveals a shortfall.
+    /// A call rather than a view, since views cannot make cross-contract calls.
+    pub fn get_escrow_asset_info(&mut self, hashlock: Base58CryptoHash) -> Promise {
+        let escrow_id = self.resolve_escrow_id(hashlock);
+        let escrow = self.escrows.get(&escrow_id).expect(ERR_ESCROW_NOT_FOUND);
+        let token_id = escrow
+            .asset
+            .ft_token_id()
+            .expect("Escrow asset must be a fungible token");
+        ext_fungible_token::ext(token_id)
+            .with_static_gas(GAS_FOR_FT_BALANCE_OF)
+            .ft_balance_of(env::current_account_id())
+            .then(
+                ext_self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_ASSET_INFO_CALLBACK)
+                    .on_escrow_asset_balance(escrow.asset.clone(), escrow.amount),
+            )
+    }
+
+    pub fn get_resolver_stats(&self, account_id: AccountId) -> ResolverStats {
+        self.resolver_stats
+            .get(&account_id)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    pub fn get_resolver_volume(&self, account_id: AccountId, token_id: AccountId) -> U128 {
+        self.resolver_volume
+            .get(&(account_id, token_id))
+            .copied()
+            .unwrap_or(U128(0))
+    }
+
+    /// Returns the total value locked in the contract for a token: every ledger balance (which
+    /// already includes funds locked in source escrows) plus unsettled destination escrows.
+    /// Both parts are running totals, so this is O(1).
+    pub fn get_tvl(&self, token_id: AccountId) -> U128 {
+        let escrowed = self
+            .destination_escrow_totals
+            .get(&token_id)
+            .map_or(0, |total| total.0);
+        U128(self.deposits.get_ledger_total(&token_id).0 + escrowed)
+    }
+
+    /// Returns the total NEAR currently held as safety deposits.
+    /// Operators can compare it against the account balance to detect accounting drift.
+    pub fn get_safety_deposit_pool(&self) -> NearToken {
+        self.total_safety_deposits
+    }
+
+    /// Splits the contract's NEAR balance into storage staking, the safety deposit pool, and the
+    /// free remainder, which is all that can be moved out without breaking either.
+    pub fn get_near_breakdown(&self) -> NearBreakdown {
+        let account_balance = env::account_balance();
+        let locked_for_storage =
+            env::storage_byte_cost().saturating_mul(env::storage_usage().into());
+        NearBreakdown {
+            account_balance,
+            locked_for_storage,
+            safety_deposit_pool: self.total_safety_deposits,
+            registration_reserve: self.registration_reserve,
+            free: account_balance
+                .saturating_sub(locked_for_storage)
+                .saturating_sub(self.total_safety_deposits)
+                .saturating_sub(self.registration_reserve),
+        }
+    }
+
+    /// Adds the attached NEAR to the reserve that pays for registering unregistered withdrawal
+    /// recipients with their token. Anyone may top it up.
+    #[payable]
+    pub fn fund_registration_reserve(&mut self) {
+        let amount = env::attached_deposit();
+        require!(amount.as_yoctonear() > 0, "Attach NEAR to fund the reserve");
+        self.registration_reserve = self.registration_reserve.saturating_add(amount);
+    }
+
+    pub fn get_registration_reserve(&self) -> NearToken {
+        self.registration_reserve
+    }
+
+    /// Logs a NEP-297 `metrics` event snapshotting the active escrows, the safety deposit pool,
+    /// and the amounts held in active escrows for the most-used tokens. Owner only.
+    /// It scans every escrow once, so indexers can snapshot the contract without paging views.
+    pub fn emit_metrics(&mut self) {
+        self.assert_owner();
+        let mut num_active_escrows: u64 = 0;
+        let mut locked_per_token: Vec<(AccountId, u128)> = Vec::new();
+        for escrow in self.escrows.values().filter(|escrow| !escrow.settled) {
+            num_active_escrows += 1;
+            if let Some(token_id) = escrow.asset.ft_token_id() {
+                let amount = token_to_ft_amount(escrow.amount).0;
+                match locked_per_token.iter_mut().find(|(id, _)| *id == token_id) {
+                    Some((_, total)) => *total += amount,
+                    None => locked_per_token.push((token_id, amount)),
+                }
+            }
+        }
+        locked_per_token.sort_by(|(a_id, a), (b_id, b)| b.cmp(a).then_with(|| a_id.cmp(b_id)));
+        locked_per_token.truncate(MAX_METRICS_TOKENS);
+
+        log_nep297_event(
+            "metrics",
+            serde_json::json!({
+                "num_active_escrows": num_active_escrows,
+                "safety_deposit_pool": self.total_safety_deposits,
+                "locked_per_token": locked_per_token
+                    .into_iter()
+                    .map(|(token_id, amount)| (token_id, U128(amount)))
+                    .collect::<Vec<_>>(),
+            }),
+        );
+    }
+
+    // --- Settlement Records ---
+
+    /// Returns the audit record of a settled escrow, if it has not been pruned.
+    pub fn get_settlement(&self, hashlock: Base58CryptoHash) -> Option<SettlementRecord> {
+        let escrow_id = self.resolve_escrow_id(hashlock);
+        self.settlements.get(&escrow_id).cloned()
+    }
+
+    /// Lists the escrows in one page of escrows where `account_id` is the maker or the taker,
+    /// active and settled alike, oldest first.
+    /// Callers page through the whole map and concatenate the results; entries created at the
+    /// same timestamp are ordered by hashlock, so the order is deterministic.
+    pub fn get_account_escrow_history(
+        &self,
+        account_id: AccountId,
+        from_index: u32,
+        limit: u32,
+    ) -> Vec<EscrowHistoryEntry> {
+        let now = env::block_timestamp();
+        let mut entries: Vec<EscrowHistoryEntry> = self
+            .escrows
+            .iter()
+            .skip(from_index as usize)
+            .take(limit as usize)
+            .filter(|(_, escrow)| escrow.maker == account_id || escrow.taker == account_id)
+            .map(|(escrow_id, escrow)| EscrowHistoryEntry {
+                escrow: escrow.into(),
+                status: escrow.state(now, self.public_grace),
+                settlement: self.settlements.get(escrow_id).cloned(),
+            })
+            .collect();
+        entries.sort_by(|a, b| {
+            a.escrow
+                .timelocks
+                .created_at
+                .cmp(&b.escrow.timelocks.created_at)
+                .then_with(|| a.escrow.hashlock_hex.cmp(&b.escrow.hashlock_hex))
+        });
+        entries
+    }
+
+    /// Removes settlement records to reclaim storage. Owner only.
+    pub fn prune_settlements(&mut self, hashlocks: Vec<Base58CryptoHash>) {
+        self.assert_owner();
+        for hashlock in hashlocks {
+            let escrow_id = self.resolve_escrow_id(hashlock);
+            self.settlements.remove(&escrow_id);
+        }
+    }
+
+    // --- Core HTLC Logic ---
+
+    /// Drops a posted order and refunds its storage deposit to the maker; `false` if none was posted.
+    fn remove_posted_order(&mut self, order_key: &(AccountId, u128)) -> bool {
+        match self.posted_orders.remove(order_key) {
+            Some(posted) => {
+                if !posted.storage_deposit.is_zero() {
+                    Promise::new(order_key.0.clone()).transfer(posted.storage_deposit);
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Requires `last_activity` to lie at least `DORMANCY_PERIOD_SECS` in the past.
+    fn assert_dormant(last_activity: Timestamp) {
+        require!(
+            env::block_timestamp()
+                >= last_activity.saturating_add(DORMANCY_PERIOD_SECS * NANOS_IN_SEC),
+            "Funds have not been dormant long enough"
+        );
+    }
+
+    /// Fails fast when too little gas is attached, instead of partway through the settlement chain.
+    fn assert_settlement_gas() {
+        require!(
+            env::prepaid_gas() >= MIN_SETTLEMENT_GAS,
+            format!(
+                "Not enough gas attached for settlement: at least {} TGas is required",
+                MIN_SETTLEMENT_GAS.as_tgas()
+            )
+        );
+    }
+
+    /// Computes the protocol fee for withdrawing an escrow. Only fungible-token escrows pay fees,
+    /// since fees accrue in the internal ledger.
+    fn protocol_fee(&self, escrow: &Escrow) -> U128 {
+        match (&self.fee_recipient, escrow.asset.ft_token_id()) {
+            (Some(_), Some(_)) => U128(
+                token_to_ft_amount(escrow.amount).0 * u128::from(self.fee_bps) / BPS_DENOMINATOR,
+            ),
+            _ => U128(0),
+        }
+    }
+
+    /// Returns the default timelocks, which must have been configured by the owner.
+    fn default_timelocks(&self) -> TimelockDelays {
+        self.default_timelocks
+            .clone()
+            .expect("No default timelocks are configured")
+    }
+
+    /// Fills in the default timelocks of an order that leaves them out.
+    /// The signature is checked against the filled-in order, so it commits to the concrete
+    /// delays: the maker signs the defaults as they stand at signing time.
+    fn with_default_timelocks(&self, mut order: SignedOrder) -> SignedOrder {
+        if order.timelocks.is_none() {
+            order.timelocks = Some(self.default_timelocks());
+        }
+        order
+    }
+
+    /// Resolves an escrow id, or a hashlock, to the id the escrow is stored under.
+    /// A hashlock shared by a source and a destination escrow is ambiguous; callers must pass
+    /// the escrow id of the one they mean.
+    fn resolve_escrow_id(&self, key: Base58CryptoHash) -> EscrowId {
+        let key: CryptoHash = key.into();
+        if self.escrows.contains_key(&key) {
+            return key;
+        }
+        let source = escrow_id(&key, true);
+        let destination = escrow_id(&key, false);
+        match (
+            self.escrows.contains_key(&source),
+            self.escrows.contains_key(&destination),
+        ) {
+            (true, true) => env::panic_str(
+                "Hashlock is shared by a source and a destination escrow; pass the escrow id",
+            ),
+            (true, false) => source,
+            _ => destination,
+        }
+    }
+
+    /// Rejects a new escrow for a resolver already at the active escrow cap.
+    /// Every filled escrow is eventually counted as withdrawn or cancelled, so the difference is
+    /// the number still unsettled.
+    fn check_resolver_capacity(&self, resolver_id: &AccountId) -> Result<(), &'static str> {
+        if let Some(max_active) = self.max_active_escrows_per_resolver {
+            let stats = self.get_resolver_stats(resolver_id.clone());
+            let active = stats
+                .filled
+                .saturating_sub(stats.withdrawn)
+                .saturating_sub(stats.cancelled);
+            if active >= max_active {
+                return Err("Resolver has too many active escrows");
+            }
+        }
+        Ok(())
+    }
+
+    /// Tells a resolver retrying an order it already filled where its escrow is, instead of
+    /// reporting a bare nonce reuse, so an uncertain first attempt can be confirmed as a success.
+    fn assert_not_filled_by(&self, params: &SignedOrder, resolver_id: &AccountId) {
+        if !self.is_nonce_used(params.maker_id.clone(), params.nonce) {
+            return;
+        }
+        let hashlock_bytes: CryptoHash = params.hashlock.into();
+        if let Some(escrow) = self.escrows.get(&escrow_id(&hashlock_bytes, true)) {
+            if escrow.maker == params.maker_id && escrow.taker == *resolver_id {
+                env::panic_str(&format!(
+                    "Order already filled by this resolver: hashlock='{}'",
+                    bs58::encode(&hashlock_bytes).into_string()
+                ));
+            }
+        }
+    }
+
+    /// The part of a withdrawn escrow's safety deposit paid to `caller`: all of it for the taker
+    /// or its delegate, the decayed share of it for anyone else.
+    fn withdrawal_reward(&self, escrow: &Escrow, caller: &AccountId) -> NearToken {
+        if escrow.is_private_party(caller) {
+            escrow.safety_deposit
+        } else {
+            self.public_withdrawal_reward(escrow)
+        }
+    }
+
+    /// Scales the safety deposit by how far into `safety_deposit_decay_period` the public
+    /// withdrawal window is.
+    fn public_withdrawal_reward(&self, escrow: &Escrow) -> NearToken {
+        let Some(period) = self.safety_deposit_decay_period else {
+            return escrow.safety_deposit;
+        };
+        let opened_at = escrow
+            .timelocks
+            .public_withdrawal_opens_at(escrow.is_source, self.public_grace);
+        let late = env::block_timestamp().saturating_sub(opened_at) / NANOS_IN_SEC;
+        NearToken::from_yoctonear(
+            escrow.safety_deposit.as_yoctonear() * u128::from(late.min(period))
+                / u128::from(period),
+        )
+    }
+
+    /// The part of a cancelled escrow's safety deposit paid to `recipient`: all of it for the
+    /// taker, `public_cancel_reward_bps` of it for anyone else.
+    fn cancel_reward(&self, escrow: &Escrow, recipient: &AccountId) -> NearToken {
+        if *recipient == escrow.taker {
+            escrow.safety_deposit
+        } else {
+            self.public_cancel_reward(escrow)
+        }
+    }
+
+    fn public_cancel_reward(&self, escrow: &Escrow) -> NearToken {
+        NearToken::from_yoctonear(
+            escrow.safety_deposit.as_yoctonear() * u128::from(self.public_cancel_reward_bps)
+                / BPS_DENOMINATOR,
+        )
+    }
+
+    /// Computes the penalty diverted from a cancelled escrow's refund and its recipient.
+    /// Like the protocol fee, it is only taken from fungible-token escrows and credited on the ledger.
+    fn cancellation_penalty(&self, escrow: &Escrow) -> (U128, Option<AccountId>) {
+        if self.cancellation_penalty_bps == 0 || escrow.asset.ft_token_id().is_none() {
+            return (U128(0), None);
+        }
+        let recipient = match self.cancellation_penalty_recipient {
+            PenaltyRecipient::Counterparty if escrow.is_source => Some(escrow.taker.clone()),
+            PenaltyRecipient::Counterparty => Some(escrow.maker.clone()),
+            PenaltyRecipient::FeeRecipient => self.fee_recipient.clone(),
+        };
+        match recipient {
+            Some(recipient) => (
+                U128(
+                    token_to_ft_amount(escrow.amount).0 * u128::from(self.cancellation_penalty_bps)
+                        / BPS_DENOMINATOR,
+                ),
+                Some(recipient),
+            ),
+            None => (U128(0), None),
+        }
+    }
+
+    /// Asserts that `public_key` may sign for `maker_id`.
+    /// Implicit-account makers may sign with the key encoded in their account id without registering it.
+    fn assert_maker_signing_key(&self, maker_id: &AccountId, public_key: &PublicKey) {
+        let maker_keys = self.get_registered_keys(maker_id.clone());
+        let is_implicit_key = implicit_account_public_key(maker_id).as_ref() == Some(public_key);
+        require!(
+            is_implicit_key || maker_keys.contains(public_key),
+            "Public key not registered for maker"
+        );
+        if let Some(permission) = self
+            .key_permissions
+            .get(&(maker_id.clone(), public_key.clone()))
+        {
+            require!(
+                permission.may_sign_orders(&env::current_account_id()),
+                "Key is not permitted to sign orders"
+            );
+        }
+    }
+
+    /// Verifies a maker's payout authorization for the escrow under `hashlock` and returns the
+    /// recipient it names.
+    fn verify_payout_authorization(
+        &self,
+        maker_id: &AccountId,
+        hashlock: &CryptoHash,
+        authorization: PayoutAuthorization,
+    ) -> AccountId {
+        assert_valid_payout_recipient(&Some(authorization.recipient.clone()));
+        self.assert_maker_signing_key(maker_id, &authorization.public_key);
+        let signature_bytes = decode_base64(&authorization.signature, "Invalid base64 signature");
+        assert_ed25519_signature(
+            &signature_bytes,
+            &payout_authorization_hash(maker_id, hashlock, &authorization.recipient),
+            &authorization.public_key,
+        );
+        authorization.recipient
+    }
+
+    /// Sets the locked balance of `account_id` in `token_id` to `expected_locked_balance`.
+    fn reconcile_locked_balance(&mut self, account_id: &AccountId, token_id: &AccountId) -> bool {
+        let expected = self.expected_locked_balance(account_id, token_id);
+        let locked = self.deposits.get_locked_balance(account_id, token_id).0;
+
+        if locked == expected {
+            return false;
+        }
+        if locked > expected {
+            self.deposits
+                .debit_locked(account_id, token_id, U128(locked - expected));
+        } else {
+            self.deposits
+                .credit_locked(account_id, token_id, U128(expected - locked));
+        }
+        log!(
+            "LEDGER_RECONCILED: account='{}', token='{}', locked_before='{}', locked_after='{}'",
+            account_id,
+            token_id,
+            locked,
+            expected
+        );
+        true
+    }
+
+    /// The locked balance `account_id` should hold of `token_id`: its unsettled source escrows
+    /// (including claims still in flight) plus the FT safety deposits it posted as a resolver,
+    /// capped at its total balance.
+    fn expected_locked_balance(&self, account_id: &AccountId, token_id: &AccountId) -> u128 {
+        let outstanding_escrows: u128 = self
+            .escrows
+            .values()
+            .filter(|e| {
+                e.is_source
+                    && !e.settled
+                    && e.maker == *account_id
+                    && e.asset.ft_token_id().as_ref() == Some(token_id)
+            })
+            .map(|e| token_to_ft_amount(e.amount).0)
+            .sum();
+        // FT safety deposits the account posted as a resolver are locked on the same ledger.
+        let outstanding_safety_deposits: u128 = self
+            .escrows
+            .values()
+            .filter(|e| {
+                !e.settled
+                    && e.taker == *account_id
+                    && e.safety_deposit_token.as_ref() == Some(token_id)
+            })
+            .map(|e| token_to_ft_amount(e.safety_deposit).0)
+            .sum();
+        let total = self.deposits.get_total_balance(account_id, token_id).0;
+        (outstanding_escrows + outstanding_safety_deposits).min(total)
+    }
+
+    fn check_hash_algo_allowed(&self, hash_algo: HashAlgo) -> Result<(), &'static str> {
+        if !self.allowed_hash_algos.contains(&hash_algo) {
+            return Err("Hash algorithm is not allowed");
+        }
+        Ok(())
+    }
+
+    /// Rejects an escrow below the token's configured minimum amount.
+    fn check_escrow_amount_sufficient(
+        &self,
+        token_id: &AccountId,
+        amount: U128,
+    ) -> Result<(), &'static str> {
+        if let Some(min) = self.min_escrow_amount.get(token_id) {
+            if amount.0 < min.0 {
+                return Err("Escrow amount is below the token's minimum");
+            }
+        }
+        Ok(())
+    }
+
+    /// Enforces the token's configured minimum safety deposit. It is priced in NEAR,
+    /// so tokens with a minimum only accept native NEAR safety deposits.
+    fn check_safety_deposit_sufficient(
+        &self,
+        token_id: &AccountId,
+        amount: U128,
+        safety_deposit: NearToken,
+        is_native: bool,
+    ) -> Result<(), &'static str> {
+        let Some(min_per_unit) = self.min_safety_deposit_per_unit.get(token_id) else {
+            return Ok(());
+        };
+        if !is_native {
+            return Err("This token requires a native NEAR safety deposit");
+        }
+        let required = amount
+            .0
+            .checked_mul(min_per_unit.0)
+            .ok_or("Safety deposit requirement overflow")?;
+        if safety_deposit.as_yoctonear() < required {
+            return Err("Safety deposit is too small for the escrow amount");
+        }
+        Ok(())
+    }
+
+    /// The configurable checks every new escrow must pass: hash algorithm, resolver capacity,
+    /// minimum amount and minimum safety deposit. Kept non-panicking so a callback can reject the
+    /// escrow and refund it when the configuration changed while its promise was in flight.
+    fn check_fill(
+        &self,
+        resolver_id: &AccountId,
+        hash_algo: HashAlgo,
+        token_id: &AccountId,
+        amount: U128,
+        safety_deposit: NearToken,
+        is_native: bool,
+    ) -> Result<(), &'static str> {
+        self.check_hash_algo_allowed(hash_algo)?;
+        self.check_resolver_capacity(resolver_id)?;
+        self.check_escrow_amount_sufficient(token_id, amount)?;
+        self.check_safety_deposit_sufficient(token_id, amount, safety_deposit, is_native)
+    }
+
+    fn insert_destination_escrow(&mut self, escrow: Escrow) {
+        log_escrow_event(
+            "INITIATED_DESTINATION",
+            &escrow.hashlock,
+            &escrow.taker,
+            escrow.amount,
+            escrow.memo.as_deref(),
+        );
+        if escrow.safety_deposit_token.is_none() {
+            self.total_safety_deposits = self
+                .total_safety_deposits
+                .saturating_add(escrow.safety_deposit);
+        }
+        self.update_resolver_stats(&escrow.taker, |stats| stats.filled += 1);
+        self.adjust_destination_escrow_total(&escrow.asset, escrow.amount, true);
+        self.escrows.insert(escrow.id(), escrow);
+    }
+
+    /// Adjusts the running total of a token held in unsettled destination escrows.
+    fn adjust_destination_escrow_total(&mut self, asset: &Asset, amount: NearToken, add: bool) {
+        if let Some(token_id) = asset.ft_token_id() {
+            let total = self
+                .destination_escrow_totals
+                .get(&token_id)
+                .map_or(0, |total| total.0);
+            let total = if add {
+                total + token_to_ft_amount(amount).0
+            } else {
+                total.saturating_sub(token_to_ft_amount(amount).0)
+            };
+            self.destination_escrow_totals.insert(token_id, U128(total));
+        }
+    }
+
+    fn update_resolver_stats(
+        &mut self,
+        resolver_id: &AccountId,
+        update: impl FnOnce(&mut ResolverStats),
+    ) {
+        let mut stats = self.get_resolver_stats(resolver_id.clone());
+        update(&mut stats);
+        self.resolver_stats.insert(resolver_id.clone(), stats);
+    }
+
+    /// Splits the attached NEAR into the safety deposit and an excess refunded to `refund_to` right away.
+    /// Without an explicit amount, the whole attached deposit is the safety deposit.
+    fn take_safety_deposit(
+        safety_deposit_amount: Option<NearToken>,
+        refund_to: &AccountId,
+    ) -> NearToken {
+        let attached = env::attached_deposit();
+        let safety_deposit = safety_deposit_amount.unwrap_or(attached);
+        require!(
+            safety_deposit.as_yoctonear() > 0,
+            "A native NEAR safety deposit must be attached"
+        );
+        require!(
+            safety_deposit <= attached,
+            "Attached deposit is less than the safety deposit"
+        );
+        let excess = attached.saturating_sub(safety_deposit);
+        if excess.as_yoctonear() > 0 {
+            Promise::new(refund_to.clone()).transfer(excess);
+        }
+        safety_deposit
+    }
+
+    /// Locks an FT safety deposit on the resolver's internal ledger. Any attached NEAR is refunded.
+    fn lock_ft_safety_deposit(
+        &mut self,
+        resolver_id: &AccountId,
+        token_id: &AccountId,
+        safety_deposit_amount: Option<NearToken>,
+    ) -> NearToken {
+        let safety_deposit =
+            safety_deposit_amount.expect("An FT safety deposit requires safety_deposit_amount");
+        require!(
+            safety_deposit.as_yoctonear() > 0,
+            "Safety deposit must be positive"
+        );
+        let amount = token_to_ft_amount(safety_deposit);
+        self.deposits
+            .assert_available_for_escrow(resolver_id, token_id, amount);
+        self.deposits.credit_locked(resolver_id, token_id, amount);
+        let attached = env::attached_deposit();
+        if attached.as_yoctonear() > 0 {
+            Promise::new(resolver_id.clone()).transfer(attached);
+        }
+        safety_deposit
+    }
+
+    /// Pays out an escrow's safety deposit and removes it from the running pool.
+    /// FT safety deposits stay locked until `on_escrow_settled` moves them through the ledger.
+    /// A safety deposit already paid by an earlier, failed settlement attempt is not paid again.
+    /// Only `amount` is sent; `on_escrow_settled` credits the rest to the taker's native ledger.
+    fn pay_safety_deposit(
+        &mut self,
+        receiver_id: AccountId,
+        escrow: &Escrow,
+        amount: NearToken,
+    ) -> Promise {
+        if escrow.safety_deposit_token.is_some() || escrow.safety_deposit_returned {
+            return Promise::new(env::current_account_id());
+        }
+        self.total_safety_deposits = self
+            .total_safety_deposits
+            .saturating_sub(escrow.safety_deposit);
+        if amount.is_zero() {
+            return Promise::new(env::current_account_id());
+        }
+        Promise::new(receiver_id).transfer(amount)
+    }
+
+    /// Registers a failed FT withdrawal's recipient with the token, paid from the registration
+    /// reserve, then retries the transfer once. Callbacks cannot see why a transfer failed, so any
+    /// failed FT withdrawal is retried; `registration_only` leaves registered accounts untouched.
+    /// Whatever the token refunds of the attached deposit stays in the contract's free balance.
+    fn retry_with_registration(
+        &mut self,
+        escrow_id: EscrowId,
+        escrow: &Escrow,
+        caller: AccountId,
+        safety_deposit_reward: NearToken,
+        fee: U128,
+    ) -> bool {
+        let Some(token_id) = escrow.asset.ft_token_id() else {
+            return false;
+        };
+        if self.registration_reserve < MAX_AUTO_REGISTRATION_DEPOSIT
+            || env::prepaid_gas().saturating_sub(env::used_gas()) < GAS_FOR_REGISTRATION_RETRY
+        {
+            return false;
+        }
+        self.registration_reserve = self
+            .registration_reserve
+            .saturating_sub(MAX_AUTO_REGISTRATION_DEPOSIT);
+
+        let recipient = escrow.withdrawal_recipient();
+        let payout = escrow.amount.saturating_sub(ft_amount_to_token(fee));
+        log!(
+            "RECIPIENT_REGISTRATION_RETRY: hashlock='{}', account='{}', token='{}'",
+            bs58::encode(&escrow.hashlock).into_string(),
+            recipient,
+            token_id
+        );
+        ext_fungible_token::ext(token_id)
+            .with_attached_deposit(MAX_AUTO_REGISTRATION_DEPOSIT)
+            .with_static_gas(GAS_FOR_STORAGE_DEPOSIT)
+            .storage_deposit(Some(recipient.clone()), Some(true))
+            .then(Self::transfer_asset(
+                &escrow.asset,
+                recipient,
+                payout,
+                "1inch Fusion+ Swap",
+            ))
+            // The safety deposit was settled by the first attempt; keep its result slot filled.
+            .and(Promise::new(env::current_account_id()))
+            .then(
+                ext_self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_SETTLEMENT_CALLBACK)
+                    .on_escrow_settled(
+                        escrow_id,
+                        escrow.maker.clone(),
+                        escrow.taker.clone(),
+                        escrow.is_source,
+                        false,
+                        caller.clone(),
+                        caller,
+                        safety_deposit_reward,
+                        fee,
+                        None,
+                        true,
+                    ),
+            );
+        true
+    }
+
+    /// Sends an escrowed asset to `receiver_id`.
+    fn transfer_asset(
+        asset: &Asset,
+        receiver_id: AccountId,
+        amount: NearToken,
+        memo: &str,
+    ) -> Promise {
+        match asset.ft_token_id() {
+            Some(token_id) => ext_fungible_token::ext(token_id)
+                .with_attached_deposit(NearToken::from_yoctonear(1))
+                .with_static_gas(GAS_FOR_FT_TRANSFER)
+                .ft_transfer(
+                    receiver_id,
+                    token_to_ft_amount(amount),
+                    Some(memo.to_string()),
+                ),
+            None => Promise::new(receiver_id).transfer(amount),
+        }
+    }
+
+    /// Primary entry point for all Fungible Token interactions.
+    /// Can either be a deposit or the creation of a destination-side escrow.
+    #[payable]
+    pub fn ft_on_transfer(
+        &mut self,
+        sender_id: AccountId,
+        amount: U128,
+        msg: String,
+    ) -> PromiseOrValue<U128> {
+        let token_contract_id = env::predecessor_account_id();
+        let ft_message: FtMessage = serde_json::from_str(&msg).expect("Invalid FtMessage format");
 
         match ft_message {
             FtMessage::Deposit => {
                 self.deposits
                     .credit_total(&sender_id, &token_contract_id, amount);
+                self.deposits
+                    .record_external_deposit(&sender_id, &token_contract_id, amount);
                 log!(
                     "DEPOSIT: account='{}', token='{}', amount='{}'",
                     sender_id,
@@ -154,73 +2231,234 @@ impl Contract {
                 hashlock,
                 maker_id,
                 timelocks,
+                authorized_claimer,
+                payout_authorization,
+                asset_id,
+                memo,
+                counterparty_tx,
+                activation_time,
+                safety_deposit_amount,
+                safety_deposit_token,
+                hash_algo,
+                preimage_scheme,
             } => {
+                if let Some(asset_id) = &asset_id {
+                    require!(
+                        *asset_id == token_contract_id,
+                        "Declared asset does not match the transferred token"
+                    );
+                }
                 let resolver_id = sender_id;
-                let safety_deposit = env::attached_deposit();
+                // The attached NEAR is attributed to the resolver, who also receives the safety deposit back.
+                let safety_deposit = match &safety_deposit_token {
+                    Some(token_id) => {
+                        self.lock_ft_safety_deposit(&resolver_id, token_id, safety_deposit_amount)
+                    }
+                    None => Self::take_safety_deposit(safety_deposit_amount, &resolver_id),
+                };
+                require!(maker_id != resolver_id, "Maker and taker must differ");
+                assert_valid_authorized_claimer(&authorized_claimer, &resolver_id);
+
+                let hashlock_bytes: CryptoHash = hashlock.into();
                 require!(
-                    safety_deposit.as_yoctonear() > 0,
-                    "A native NEAR safety deposit must be attached"
+                    !self
+                        .escrows
+                        .contains_key(&escrow_id(&hashlock_bytes, false)),
+                    "Escrow already exists"
                 );
+                let payout_recipient = payout_authorization.map(|authorization| {
+                    self.verify_payout_authorization(&maker_id, &hashlock_bytes, authorization)
+                });
+                self.check_fill(
+                    &resolver_id,
+                    hash_algo,
+                    &token_contract_id,
+                    amount,
+                    safety_deposit,
+                    safety_deposit_token.is_none(),
+                )
+                .unwrap_or_else(|error| env::panic_str(error));
+                let created_at = activation_timestamp(activation_time);
+                let timelocks = timelocks.unwrap_or_else(|| self.default_timelocks());
+                timelocks.validate(created_at, self.min_reveal_grace);
+                assert_valid_memo(&memo);
+                assert_valid_counterparty_tx(&counterparty_tx);
 
-                let hashlock_bytes: EscrowId = hashlock.into();
+                let escrow = Escrow {
+                    hashlock: hashlock_bytes,
+                    hash_algo,
+                    preimage_scheme,
+                    maker: maker_id,
+                    taker: resolver_id,
+                    authorized_claimer,
+                    payout_recipient,
+                    asset: Asset::Ft(token_contract_id.clone()),
+                    amount: ft_amount_to_token(amount),
+                    safety_deposit,
+                    safety_deposit_token,
+                    is_source: false,
+                    timelocks: Timelocks::new(created_at, timelocks),
+                    claimed: false,
+                    revealed_preimage: None,
+                    revealed_by: None,
+                    safety_deposit_returned: false,
+                    settled: false,
+                    memo,
+                    counterparty_tx,
+                    order_hash: None,
+                    last_activity: env::block_timestamp(),
+                };
+                if self.verify_ft_metadata {
+                    return PromiseOrValue::Promise(
+                        ext_fungible_token::ext(token_contract_id)
+                            .with_static_gas(GAS_FOR_FT_METADATA)
+                            .ft_metadata()
+                            .then(
+                                ext_self::ext(env::current_account_id())
+                                    .with_static_gas(GAS_FOR_METADATA_CALLBACK)
+                                    .on_ft_metadata_verified(escrow),
+                            ),
+                    );
+                }
+                self.insert_destination_escrow(escrow);
+            }
+            FtMessage::TopUpDestinationEscrow { hashlock } => {
+                let hashlock_bytes: CryptoHash = hashlock.into();
+                let destination_id = escrow_id(&hashlock_bytes, false);
+                let mut escrow = self
+                    .escrows
+                    .get(&destination_id)
+                    .cloned()
+                    .expect(ERR_ESCROW_NOT_FOUND);
                 require!(
-                    !self.escrows.contains_key(&hashlock_bytes),
-                    "Escrow already exists"
+                    !escrow.is_source,
+                    "Only destination escrows can be topped up"
+                );
+                require!(!escrow.claimed, "Escrow already claimed");
+                require!(
+                    escrow.taker == sender_id,
+                    "Only the taker can top up the escrow"
+                );
+                require!(
+                    escrow.asset.ft_token_id().as_ref() == Some(&token_contract_id),
+                    "Top-up token does not match the escrow asset"
+                );
+                require!(
+                    escrow
+                        .timelocks
+                        .stage(false, env::block_timestamp(), self.public_grace)
+                        != TimelockStage::PublicCancellation,
+                    "Escrow is already cancellable"
                 );
-                timelocks.validate();
 
-                let escrow = Escrow {
-                    hashlock: hashlock_bytes,
-                    maker: maker_id,
-                    taker: resolver_id.clone(),
-                    asset: Asset::Ft(token_contract_id),
-                    amount: NearToken::from_yoctonear(amount.0),
-                    safety_deposit,
-                    is_source: false,
-                    timelocks: Timelocks::new(env::block_timestamp(), timelocks),
-                    claimed: false,
-                };
-                self.escrows.insert(hashlock_bytes, escrow);
+                escrow.amount = escrow.amount.saturating_add(ft_amount_to_token(amount));
+                escrow.last_activity = env::block_timestamp();
+                self.adjust_destination_escrow_total(
+                    &escrow.asset,
+                    ft_amount_to_token(amount),
+                    true,
+                );
                 log_escrow_event(
-                    "INITIATED_DESTINATION",
+                    "TOPPED_UP",
                     &hashlock_bytes,
-                    &resolver_id,
-                    NearToken::from_yoctonear(amount.0),
+                    &sender_id,
+                    ft_amount_to_token(amount),
+                    escrow.memo.as_deref(),
                 );
+                self.escrows.insert(destination_id, escrow);
             }
         }
         PromiseOrValue::Value(U128(0))
     }
 
     /// Executed by a Resolver to create a source-side (NEAR -> Other) escrow from a Maker's signed intent.
+    /// Memo, batch proof, scheduling, safety deposit, delegate and co-signatures are set through
+    /// `options`; see `FillOptions`.
     #[payable]
     pub fn initiate_source_escrow(
         &mut self,
         params: SignedOrder,
         signature: String,
         public_key: PublicKey,
+        options: Option<FillOptions>,
     ) {
+        let FillOptions {
+            memo,
+            batch_proof,
+            activation_time,
+            safety_deposit_amount,
+            safety_deposit_token,
+            authorized_claimer,
+            co_signatures,
+        } = options.unwrap_or_default();
+        let signatures: Vec<(PublicKey, Vec<u8>)> = std::iter::once((public_key, signature))
+            .chain(co_signatures.unwrap_or_default())
+            .map(|(public_key, signature)| {
+                let signature_bytes = decode_base64(&signature, "Invalid base64 signature");
+                (public_key, signature_bytes)
+            })
+            .collect();
+        let params = self.with_default_timelocks(params);
         let resolver_id = env::predecessor_account_id();
-        let safety_deposit = env::attached_deposit();
-        require!(
-            safety_deposit.as_yoctonear() > 0,
-            "A native NEAR safety deposit must be attached"
-        );
+        let safety_deposit = match &safety_deposit_token {
+            Some(token_id) => {
+                self.lock_ft_safety_deposit(&resolver_id, token_id, safety_deposit_amount)
+            }
+            None => Self::take_safety_deposit(safety_deposit_amount, &resolver_id),
+        };
+        let native_safety_deposit = safety_deposit_token.is_none();
 
-        // Verify signature and order integrity
-        let maker_keys = self.get_registered_keys(params.maker_id.clone());
         require!(
-            maker_keys.contains(&public_key),
-            "Public key not registered for maker"
+            params.maker_id != resolver_id,
+            "Maker and taker must differ"
         );
-        let signature_bytes = base64::decode(&signature).expect("Invalid signature format");
+        assert_valid_authorized_claimer(&authorized_claimer, &resolver_id);
+        if let Some(taker_id) = &params.taker_id {
+            require!(
+                *taker_id == resolver_id,
+                "Order is bound to a different taker"
+            );
+        } else if !params.taker_allowlist.is_empty() {
+            require!(
+                params.taker_allowlist.len() <= MAX_TAKER_ALLOWLIST_LEN,
+                "Taker allowlist is too long"
+            );
+            require!(
+                params.taker_allowlist.contains(&resolver_id),
+                "Resolver is not in the order's taker allowlist"
+            );
+        }
+
+        self.assert_not_filled_by(&params, &resolver_id);
+
+        // Verify signature and order integrity.
+        for (public_key, _) in &signatures {
+            self.assert_maker_signing_key(&params.maker_id, public_key);
+        }
+        assert_valid_payout_recipient(&params.payout_recipient);
         verify_maker_signature(
             &params,
-            &signature_bytes,
-            &public_key,
+            batch_proof.as_ref(),
+            &signatures,
+            self.get_signature_threshold(params.maker_id.clone()),
             &mut self.used_nonces,
+            &mut self.nonce_high_water,
         );
-        params.timelocks.validate();
+        self.check_fill(
+            &resolver_id,
+            params.hash_algo,
+            &params.asset_id,
+            params.amount,
+            safety_deposit,
+            native_safety_deposit,
+        )
+        .unwrap_or_else(|error| env::panic_str(error));
+        let created_at = activation_timestamp(activation_time);
+        params
+            .timelocks()
+            .validate(created_at, self.min_reveal_grace);
+        assert_valid_memo(&memo);
+        assert_valid_counterparty_tx(&params.counterparty_tx);
 
         // Verify maker has sufficient available funds
         let amount_u128 = params.amount;
@@ -231,141 +2469,441 @@ impl Contract {
         self.deposits
             .credit_locked(&params.maker_id, &params.asset_id, amount_u128);
 
-        // Create the escrow
-        let hashlock_bytes: EscrowId = params.hashlock.into();
+        // Create the escrow. For open orders, consuming the nonce above in the same call makes the
+        // first resolver the taker; any later attempt to fill the order fails on the used nonce.
+        let hashlock_bytes: CryptoHash = params.hashlock.into();
+        require!(
+            !self.escrows.contains_key(&escrow_id(&hashlock_bytes, true)),
+            "Escrow already exists"
+        );
+        let timelocks = Timelocks::new(created_at, params.timelocks().clone());
+        let order_key = (params.maker_id.clone(), params.nonce);
+        let order_hash = env::sha256_array(&params.to_message_bytes());
         let escrow = Escrow {
             hashlock: hashlock_bytes,
+            hash_algo: params.hash_algo,
+            preimage_scheme: params.preimage_scheme,
             maker: params.maker_id,
             taker: resolver_id.clone(),
+            authorized_claimer,
+            payout_recipient: params.payout_recipient,
             asset: Asset::Ft(params.asset_id),
-            amount: NearToken::from_yoctonear(params.amount.0),
+            amount: ft_amount_to_token(params.amount),
             safety_deposit,
+            safety_deposit_token,
             is_source: true,
-            timelocks: Timelocks::new(env::block_timestamp(), params.timelocks),
+            timelocks,
             claimed: false,
+            revealed_preimage: None,
+            revealed_by: None,
+            safety_deposit_returned: false,
+            settled: false,
+            memo,
+            counterparty_tx: params.counterparty_tx,
+            order_hash: Some(order_hash),
+            last_activity: env::block_timestamp(),
         };
-        self.escrows.insert(hashlock_bytes, escrow);
         log_escrow_event(
             "INITIATED_SOURCE",
             &hashlock_bytes,
             &resolver_id,
-            NearToken::from_yoctonear(params.amount.0),
+            ft_amount_to_token(params.amount),
+            escrow.memo.as_deref(),
         );
+        self.remove_posted_order(&order_key);
+        self.order_escrows.insert(order_key, escrow.id());
+        self.escrows.insert(escrow.id(), escrow);
+        if native_safety_deposit {
+            self.total_safety_deposits = self.total_safety_deposits.saturating_add(safety_deposit);
+        }
+        self.update_resolver_stats(&resolver_id, |stats| stats.filled += 1);
+    }
+
+    /// Claims the funds from an escrow by revealing the secret, plus the salt for salted preimages.
+    /// The escrow is looked up under every hash function, matching only escrows created with it.
+    /// When both sides of a swap share the hashlock, the first unclaimed one is settled,
+    /// the destination escrow before the source escrow.
+    pub fn withdraw(&mut self, secret: String, salt: Option<String>) -> SettlementReceipt {
+        let secret_bytes = decode_base64(&secret, "Invalid base64 secret");
+        let salt_bytes = salt.map(|salt| decode_base64(&salt, "Invalid base64 salt"));
+        let scheme = if salt_bytes.is_some() {
+            PreimageScheme::SecretWithSalt
+        } else {
+            PreimageScheme::Secret
+        };
+        let preimage = scheme.preimage(secret_bytes, salt_bytes);
+        let candidates: Vec<EscrowId> = HashAlgo::ALL
+            .iter()
+            .flat_map(|algo| {
+                let hashlock = algo.hash(&preimage);
+                [false, true]
+                    .into_iter()
+                    .map(move |is_source| (algo, escrow_id(&hashlock, is_source)))
+            })
+            .filter(|(algo, id)| {
+                self.escrows.get(id).is_some_and(|escrow| {
+                    escrow.hash_algo == **algo && escrow.preimage_scheme == scheme
+                })
+            })
+            .map(|(_, id)| id)
+            .collect();
+        let escrow_id = candidates
+            .iter()
+            .find(|id| self.escrows.get(*id).is_some_and(|escrow| !escrow.claimed))
+            .or(candidates.first())
+            .copied()
+            .expect(ERR_ESCROW_NOT_FOUND);
+        self.internal_withdraw(escrow_id, preimage).0
     }
 
-    /// Claims the funds from an escrow by revealing the secret.
-    pub fn withdraw(&mut self, secret: String) -> Promise {
-        let secret_bytes = base64::decode(secret).expect("Invalid base64 secret");
-        let hashlock_bytes: EscrowId = env::sha256_array(&secret_bytes);
+    /// Claims the funds from the escrow at `hashlock`, which the revealed preimage must hash to.
+    /// Equivalent to `withdraw`, but lets the caller name the escrow it intends to settle.
+    pub fn withdraw_escrow(
+        &mut self,
+        hashlock: Base58CryptoHash,
+        secret: String,
+        salt: Option<String>,
+    ) -> SettlementReceipt {
+        let secret_bytes = decode_base64(&secret, "Invalid base64 secret");
+        let salt_bytes = salt.map(|salt| decode_base64(&salt, "Invalid base64 salt"));
+        let escrow_id = self.resolve_escrow_id(hashlock);
+        let escrow = self.escrows.get(&escrow_id).expect(ERR_ESCROW_NOT_FOUND);
+        let preimage = escrow.preimage_scheme.preimage(secret_bytes, salt_bytes);
+        require!(
+            escrow.hash_algo.hash(&preimage) == escrow.hashlock,
+            "Secret does not match hashlock"
+        );
+        self.internal_withdraw(escrow_id, preimage).0
+    }
 
-        let escrow = self
+    /// Records the revealed preimage and pays the escrow out, once the caller's right to
+    /// withdraw is checked. Returns the intended effects along with the settlement promise.
+    fn internal_withdraw(
+        &mut self,
+        escrow_id: EscrowId,
+        preimage: Vec<u8>,
+    ) -> (SettlementReceipt, Promise) {
+        Self::assert_settlement_gas();
+        let mut escrow = self
             .escrows
-            .get(&hashlock_bytes)
+            .get(&escrow_id)
             .cloned()
-            .expect("Escrow not found");
+            .expect(ERR_ESCROW_NOT_FOUND);
         require!(!escrow.claimed, "Escrow already claimed");
 
         // Validate timelocks
-        let is_public_caller = env::predecessor_account_id() != escrow.taker;
+        let is_public_caller = !escrow.is_private_party(&env::predecessor_account_id());
         if escrow.is_source {
             escrow
                 .timelocks
-                .assert_src_withdrawal_window(is_public_caller);
+                .assert_src_withdrawal_window(is_public_caller, self.public_grace);
         } else {
             escrow
                 .timelocks
-                .assert_dst_withdrawal_window(is_public_caller);
+                .assert_dst_withdrawal_window(is_public_caller, self.public_grace);
+        }
+
+        // The first revealer keeps the safety deposit of any later retry.
+        let caller = env::predecessor_account_id();
+        if escrow.revealed_by.is_none() {
+            escrow.revealed_preimage = Some(preimage);
+            escrow.revealed_by = Some(caller.clone());
         }
+        self.settle_withdrawal(escrow_id, escrow, caller)
+    }
+
+    /// Re-sends the transfers of a withdrawal whose settlement failed, without the secret, once
+    /// an earlier `withdraw` revealed it. Anyone may call it, in any window; the safety deposit
+    /// still goes to the account that revealed the secret.
+    pub fn retry_settlement(&mut self, hashlock: Base58CryptoHash) -> SettlementReceipt {
+        Self::assert_settlement_gas();
+        let escrow_id = self.resolve_escrow_id(hashlock);
+        let escrow = self
+            .escrows
+            .get(&escrow_id)
+            .cloned()
+            .expect(ERR_ESCROW_NOT_FOUND);
+        require!(!escrow.claimed, "Escrow already claimed");
+        let revealed_by = escrow
+            .revealed_by
+            .clone()
+            .expect("The secret of this escrow has not been revealed");
+        self.settle_withdrawal(escrow_id, escrow, revealed_by).0
+    }
 
+    /// Marks a revealed escrow as claimed and schedules its payout, with the safety deposit
+    /// going to `caller`.
+    fn settle_withdrawal(
+        &mut self,
+        escrow_id: EscrowId,
+        escrow: Escrow,
+        caller: AccountId,
+    ) -> (SettlementReceipt, Promise) {
         // Update escrow as claimed
         let mut updated_escrow = escrow.clone();
         updated_escrow.claimed = true;
-        self.escrows.insert(hashlock_bytes, updated_escrow);
+        updated_escrow.last_activity = env::block_timestamp();
+        self.escrows.insert(escrow_id, updated_escrow);
 
-        let caller = env::predecessor_account_id();
-        let (recipient, asset_token_id) = if escrow.is_source {
-            // Source (NEAR->Other): Taker/Resolver claims the NEAR funds
-            (escrow.taker.clone(), escrow.asset.ft_token_id())
-        } else {
-            // Destination (Other->NEAR): Maker claims the NEAR funds
-            (escrow.maker.clone(), escrow.asset.ft_token_id())
-        };
+        let recipient = escrow.withdrawal_recipient();
 
-        let main_transfer = ext_fungible_token::ext(asset_token_id)
-            .with_attached_deposit(NearToken::from_yoctonear(1))
-            .ft_transfer(
-                recipient,
-                U128(escrow.amount.as_yoctonear()),
-                Some("1inch Fusion+ Swap".to_string()),
-            );
+        // The protocol fee stays in the contract and is credited to the fee recipient's ledger on settlement.
+        let fee = self.protocol_fee(&escrow);
+        let payout = escrow.amount.saturating_sub(ft_amount_to_token(fee));
+        let receipt = SettlementReceipt {
+            hashlock: escrow.hashlock.into(),
+            action: EscrowAction::Withdraw,
+            recipient: recipient.clone(),
+            amount: token_to_ft_amount(payout),
+            safety_deposit_returned_to: caller.clone(),
+        };
+        let main_transfer =
+            Self::transfer_asset(&escrow.asset, recipient, payout, "1inch Fusion+ Swap");
 
-        let safety_deposit_transfer = Promise::new(caller.clone()).transfer(escrow.safety_deposit);
+        let safety_deposit_reward = self.withdrawal_reward(&escrow, &caller);
+        let safety_deposit_transfer =
+            self.pay_safety_deposit(caller.clone(), &escrow, safety_deposit_reward);
 
-        log_escrow_event("CLAIMED", &hashlock_bytes, &caller, escrow.amount);
+        log_escrow_event(
+            "CLAIMED",
+            &escrow.hashlock,
+            &caller,
+            escrow.amount,
+            escrow.memo.as_deref(),
+        );
 
-        main_transfer.and(safety_deposit_transfer).then(
-            ext_self::ext(env::current_account_id()).on_escrow_settled(
-                hashlock_bytes,
-                escrow.maker,
-                escrow.taker,
-                escrow.is_source,
-                false,
-            ),
-        )
+        let settlement = main_transfer.and(safety_deposit_transfer).then(
+            ext_self::ext(env::current_account_id())
+                .with_static_gas(GAS_FOR_SETTLEMENT_CALLBACK)
+                .on_escrow_settled(
+                    escrow_id,
+                    escrow.maker,
+                    escrow.taker,
+                    escrow.is_source,
+                    false,
+                    caller.clone(),
+                    caller,
+                    safety_deposit_reward,
+                    fee,
+                    None,
+                    false,
+                ),
+        );
+        (receipt, settlement)
     }
 
     /// Cancels an expired escrow, returning funds to the original depositor.
-    pub fn cancel(&mut self, hashlock: Base58CryptoHash) -> Promise {
-        let hashlock_bytes: EscrowId = hashlock.into();
+    pub fn cancel(&mut self, hashlock: Base58CryptoHash) -> SettlementReceipt {
+        let escrow_id = self.resolve_escrow_id(hashlock);
+        self.checked_cancel(escrow_id).0
+    }
+
+    /// Cancels an escrow once the caller's cancellation window is open.
+    fn checked_cancel(&mut self, escrow_id: EscrowId) -> (SettlementReceipt, Promise) {
         let escrow = self
             .escrows
-            .get(&hashlock_bytes)
+            .get(&escrow_id)
             .cloned()
-            .expect("Escrow not found");
+            .expect(ERR_ESCROW_NOT_FOUND);
         require!(!escrow.claimed, "Escrow already claimed");
 
         // Validate timelocks
-        let is_public_caller = env::predecessor_account_id() != escrow.taker;
-        if escrow.is_source {
+        let is_public_caller = !escrow.is_private_party(&env::predecessor_account_id());
+        if !is_public_caller && !escrow.timelocks.has_withdrawal_window(escrow.is_source) {
+            // No withdrawal could ever succeed, so the taker may cancel right away.
+            log!(
+                "NO_WITHDRAWAL_WINDOW: hashlock='{}'",
+                bs58::encode(&escrow.hashlock).into_string()
+            );
+        } else if escrow.is_source {
             escrow
                 .timelocks
-                .assert_src_cancellation_window(is_public_caller)
+                .assert_src_cancellation_window(is_public_caller, self.public_grace)
         } else {
             escrow.timelocks.assert_dst_cancellation_window()
         }
 
+        let caller = env::predecessor_account_id();
+        self.internal_cancel(escrow_id, escrow, caller)
+    }
+
+    /// Cancels a destination escrow before its cancellation window opens.
+    /// Callable only by an owner-registered relayer attesting that the source leg is dead;
+    /// the relayer is trusted, and the safety deposit goes back to the taker rather than the relayer.
+    pub fn relayer_cancel(&mut self, hashlock: Base58CryptoHash) -> SettlementReceipt {
+        require!(
+            self.relayers.contains(&env::predecessor_account_id()),
+            "Only registered relayers can fast-cancel"
+        );
+        let escrow_id = self.resolve_escrow_id(hashlock);
+        let escrow = self
+            .escrows
+            .get(&escrow_id)
+            .cloned()
+            .expect(ERR_ESCROW_NOT_FOUND);
+        require!(!escrow.claimed, "Escrow already claimed");
+        require!(
+            !escrow.is_source,
+            "Only destination escrows can be fast-cancelled"
+        );
+
+        let safety_deposit_recipient = escrow.taker.clone();
+        self.internal_cancel(escrow_id, escrow, safety_deposit_recipient)
+            .0
+    }
+
+    /// Cancels every escrow in a page of escrows whose public cancellation window is open,
+    /// paying their safety deposits to the caller.
+    /// Escrows that are claimed or not yet cancellable are skipped. Each cancellation settles
+    /// through its own `on_escrow_settled`, so a failed refund only reverts that escrow.
+    /// Stops early once too little gas remains to schedule another settlement.
+    pub fn settle_all_ready(&mut self, from_index: u32, limit: u32) -> Vec<SettlementReceipt> {
+        require!(limit <= MAX_SETTLE_BATCH, "Too many escrows in one batch");
+        Self::assert_settlement_gas();
+
+        let now = env::block_timestamp();
+        let ready: Vec<(EscrowId, Escrow)> = self
+            .escrows
+            .iter()
+            .skip(from_index as usize)
+            .take(limit as usize)
+            .filter(|(_, escrow)| {
+                !escrow.claimed
+                    && escrow
+                        .timelocks
+                        .stage(escrow.is_source, now, self.public_grace)
+                        == TimelockStage::PublicCancellation
+            })
+            .map(|(escrow_id, escrow)| (*escrow_id, escrow.clone()))
+            .collect();
+
+        let caller = env::predecessor_account_id();
+        let mut receipts = Vec::with_capacity(ready.len());
+        for (escrow_id, escrow) in ready {
+            let remaining_gas = env::prepaid_gas().saturating_sub(env::used_gas());
+            if remaining_gas < GAS_FOR_BATCHED_SETTLEMENT {
+                break;
+            }
+            receipts.push(self.internal_cancel(escrow_id, escrow, caller.clone()).0);
+        }
+        receipts
+    }
+
+    /// Marks an escrow as claimed and schedules its refund, once the caller's right to cancel is checked.
+    fn internal_cancel(
+        &mut self,
+        escrow_id: EscrowId,
+        escrow: Escrow,
+        safety_deposit_recipient: AccountId,
+    ) -> (SettlementReceipt, Promise) {
+        Self::assert_settlement_gas();
+
         // Update escrow as claimed
         let mut updated_escrow = escrow.clone();
         updated_escrow.claimed = true;
-        self.escrows.insert(hashlock_bytes, updated_escrow);
+        updated_escrow.last_activity = env::block_timestamp();
+        self.escrows.insert(escrow_id, updated_escrow);
 
         let caller = env::predecessor_account_id();
+        // In the private window the safety deposit goes back to the taker who posted it;
+        // a public caller earns its configured share of it.
+        let safety_deposit_recipient = if escrow.is_private_party(&safety_deposit_recipient) {
+            escrow.taker.clone()
+        } else {
+            safety_deposit_recipient
+        };
+        let safety_deposit_reward = self.cancel_reward(&escrow, &safety_deposit_recipient);
+        // The penalty stays in the contract and is credited to its recipient's ledger on settlement.
+        let (penalty, penalty_recipient) = self.cancellation_penalty(&escrow);
+        let refund = escrow.amount.saturating_sub(ft_amount_to_token(penalty));
         let main_promise = if escrow.is_source {
             // Source (NEAR->Other): Refund is internal. Just update the ledger. No transfer.
             // The ledger update happens in `on_escrow_settled`.
             Promise::new(env::current_account_id())
         } else {
             // Destination (Other->NEAR): Taker/Resolver gets their funds back.
-            ext_fungible_token::ext(escrow.asset.ft_token_id())
-                .with_attached_deposit(NearToken::from_yoctonear(1))
-                .ft_transfer(
-                    escrow.taker.clone(),
-                    U128(escrow.amount.as_yoctonear()),
-                    Some("1inch Fusion+ Cancel".to_string()),
-                )
+            Self::transfer_asset(
+                &escrow.asset,
+                escrow.taker.clone(),
+                refund,
+                "1inch Fusion+ Cancel",
+            )
         };
 
-        let safety_deposit_transfer = Promise::new(caller.clone()).transfer(escrow.safety_deposit);
-        log_escrow_event("CANCELED", &hashlock_bytes, &caller, escrow.amount);
+        // Source refunds stay on the maker's ledger; destination refunds go back to the taker.
+        let receipt = SettlementReceipt {
+            hashlock: escrow.hashlock.into(),
+            action: EscrowAction::Cancel,
+            recipient: if escrow.is_source {
+                escrow.maker.clone()
+            } else {
+                escrow.taker.clone()
+            },
+            amount: token_to_ft_amount(refund),
+            safety_deposit_returned_to: safety_deposit_recipient.clone(),
+        };
+        let safety_deposit_transfer = self.pay_safety_deposit(
+            safety_deposit_recipient.clone(),
+            &escrow,
+            safety_deposit_reward,
+        );
+        log_escrow_event(
+            "CANCELED",
+            &escrow.hashlock,
+            &caller,
+            escrow.amount,
+            escrow.memo.as_deref(),
+        );
 
-        main_promise.and(safety_deposit_transfer).then(
-            ext_self::ext(env::current_account_id()).on_escrow_settled(
-                hashlock_bytes,
-                escrow.maker,
-                escrow.taker,
-                escrow.is_source,
-                true,
-            ),
+        let settlement = main_promise.and(safety_deposit_transfer).then(
+            ext_self::ext(env::current_account_id())
+                .with_static_gas(GAS_FOR_SETTLEMENT_CALLBACK)
+                .on_escrow_settled(
+                    escrow_id,
+                    escrow.maker,
+                    escrow.taker,
+                    escrow.is_source,
+                    true,
+                    caller,
+                    safety_deposit_recipient,
+                    safety_deposit_reward,
+                    penalty,
+                    penalty_recipient,
+                    false,
+                ),
+        );
+        (receipt, settlement)
+    }
+
+    /// Cancels a source escrow on behalf of its maker and withdraws the unlocked funds to their wallet.
+    /// The maker is not the taker, so this is only possible once the public cancellation window opens.
+    pub fn cancel_and_withdraw_deposit(&mut self, hashlock: Base58CryptoHash) -> Promise {
+        let escrow_id = self.resolve_escrow_id(hashlock);
+        let escrow = self
+            .escrows
+            .get(&escrow_id)
+            .cloned()
+            .expect(ERR_ESCROW_NOT_FOUND);
+        require!(
+            escrow.is_source,
+            "Only source escrows lock funds in the deposit ledger"
+        );
+        require!(
+            env::predecessor_account_id() == escrow.maker,
+            "Only the maker can withdraw the cancelled funds"
+        );
+
+        // Any cancellation penalty stays on the ledger; only the refund can be withdrawn.
+        let (penalty, _) = self.cancellation_penalty(&escrow);
+        let refund = U128(
+            token_to_ft_amount(escrow.amount)
+                .0
+                .saturating_sub(penalty.0),
+        );
+
+        // The funds are only unlocked by `on_escrow_settled`, so the withdrawal must follow it.
+        self.checked_cancel(escrow_id).1.then(
+            ext_self::ext(env::current_account_id()).on_cancel_withdraw_deposit(escrow_id, refund),
         )
     }
 
@@ -373,44 +2911,180 @@ impl Contract {
     #[private]
     pub fn on_escrow_settled(
         &mut self,
-        hashlock: EscrowId,
+        escrow_id: EscrowId,
         maker_id: AccountId,
         taker_id: AccountId,
         is_source: bool,
         is_cancel: bool,
+        caller: AccountId,
+        safety_deposit_recipient: AccountId,
+        safety_deposit_reward: NearToken,
+        fee: U128,
+        penalty_recipient: Option<AccountId>,
+        registration_retry: bool,
     ) {
         let escrow = self
             .escrows
-            .get(&hashlock)
+            .get(&escrow_id)
             .cloned()
             .expect("Escrow not found in callback");
+        // Ledger effects must apply exactly once, even if the callback is ever executed again.
+        if escrow.settled {
+            log!(
+                "ESCROW_ALREADY_SETTLED: hashlock='{}'",
+                bs58::encode(&escrow.hashlock).into_string()
+            );
+            return;
+        }
+
+        // A native safety deposit that could not be sent (e.g. the recipient account was deleted)
+        // bounced back to this contract; credit it to the recipient's native ledger balance.
+        let safety_deposit_failed = !matches!(env::promise_result(1), PromiseResult::Successful(_));
+        if safety_deposit_failed && escrow.safety_deposit_token.is_none() {
+            let amount = U128(safety_deposit_reward.as_yoctonear());
+            self.deposits
+                .credit_total(&safety_deposit_recipient, &Self::native_token_id(), amount);
+            log!(
+                "SAFETY_DEPOSIT_CREDITED: hashlock='{}', account='{}', amount='{}'",
+                bs58::encode(&escrow.hashlock).into_string(),
+                safety_deposit_recipient,
+                amount.0
+            );
+        }
+        // The taker's share of a native safety deposit paid out by this attempt is kept on its
+        // native ledger, whether or not the main transfer went through.
+        if escrow.safety_deposit_token.is_none() && !escrow.safety_deposit_returned {
+            let remainder = escrow.safety_deposit.saturating_sub(safety_deposit_reward);
+            if !remainder.is_zero() {
+                self.deposits.credit_total(
+                    &taker_id,
+                    &Self::native_token_id(),
+                    U128(remainder.as_yoctonear()),
+                );
+            }
+        }
+
+        let mut transfer_succeeded = matches!(env::promise_result(0), PromiseResult::Successful(_));
+        if !transfer_succeeded && is_cancel && !is_source {
+            if let Some(token_id) = escrow.asset.ft_token_id() {
+                // The taker could not receive the refund (e.g. not registered with the FT contract).
+                // Credit it to their internal ledger instead, withdrawable later via `withdraw_deposit`.
+                let amount = U128(token_to_ft_amount(escrow.amount).0 - fee.0);
+                self.deposits.credit_total(&taker_id, &token_id, amount);
+                log!(
+                    "CANCEL_REFUND_CREDITED: hashlock='{}', account='{}', token='{}', amount='{}'",
+                    bs58::encode(&escrow.hashlock).into_string(),
+                    taker_id,
+                    token_id,
+                    amount.0
+                );
+                transfer_succeeded = true;
+            }
+        }
+
+        if !transfer_succeeded && !is_cancel && !registration_retry {
+            // The payout is still claimed while the registration and the retried transfer are in flight.
+            if self.retry_with_registration(
+                escrow_id,
+                &escrow,
+                caller.clone(),
+                safety_deposit_reward,
+                fee,
+            ) {
+                if escrow.safety_deposit_token.is_none() {
+                    let mut escrow = escrow;
+                    escrow.safety_deposit_returned = true;
+                    self.escrows.insert(escrow_id, escrow);
+                }
+                return;
+            }
+        }
 
-        if let PromiseResult::Successful(_) = env::promise_result(0) {
+        if transfer_succeeded {
             if is_source {
-                let amount = U128(escrow.amount.as_yoctonear());
-                let token_id = escrow.asset.ft_token_id();
-                if is_cancel {
-                    // Source cancellation: funds returned to maker's available pool.
-                    self.deposits.debit_locked(&maker_id, &token_id, amount);
+                // Only fungible-token source escrows are backed by the internal ledger.
+                if let Some(token_id) = escrow.asset.ft_token_id() {
+                    let amount = token_to_ft_amount(escrow.amount);
+                    if is_cancel {
+                        // Source cancellation: funds returned to maker's available pool, less any penalty.
+                        self.deposits.debit_locked(&maker_id, &token_id, amount);
+                        if fee.0 > 0 {
+                            self.deposits.debit_total(&maker_id, &token_id, fee);
+                        }
+                    } else {
+                        // Source successful claim: funds are gone. Debit both ledgers.
+                        self.deposits.debit_locked(&maker_id, &token_id, amount);
+                        self.deposits.debit_total(&maker_id, &token_id, amount);
+                    }
+                }
+            }
+            // Destination funds were never on the ledger; they just leave the escrowed total.
+            if !is_source {
+                self.adjust_destination_escrow_total(&escrow.asset, escrow.amount, false);
+            }
+            // `fee` is the protocol fee of a withdrawal or the penalty of a cancellation.
+            if fee.0 > 0 {
+                let fee_recipient = if is_cancel {
+                    penalty_recipient
                 } else {
-                    // Source successful claim: funds are gone. Debit both ledgers.
-                    self.deposits.debit_locked(&maker_id, &token_id, amount);
-                    self.deposits.debit_total(&maker_id, &token_id, amount);
+                    self.fee_recipient.clone()
+                };
+                if let (Some(fee_recipient), Some(token_id)) =
+                    (fee_recipient, escrow.asset.ft_token_id())
+                {
+                    self.deposits.credit_total(&fee_recipient, &token_id, fee);
+                }
+            }
+            if is_cancel {
+                self.update_resolver_stats(&taker_id, |stats| stats.cancelled += 1);
+            } else {
+                self.update_resolver_stats(&taker_id, |stats| stats.withdrawn += 1);
+                if let Some(token_id) = escrow.asset.ft_token_id() {
+                    let key = (taker_id.clone(), token_id);
+                    let volume = self.resolver_volume.get(&key).map_or(0, |v| v.0);
+                    self.resolver_volume
+                        .insert(key, U128(volume + token_to_ft_amount(escrow.amount).0));
                 }
             }
-            // For destination escrows, no ledger update is needed as funds were never in the internal ledger.
+            // An FT safety deposit is unlocked and its reward moves to the recipient.
+            if let Some(token_id) = &escrow.safety_deposit_token {
+                let reward = token_to_ft_amount(safety_deposit_reward);
+                self.deposits.debit_locked(
+                    &taker_id,
+                    token_id,
+                    token_to_ft_amount(escrow.safety_deposit),
+                );
+                self.deposits.debit_total(&taker_id, token_id, reward);
+                self.deposits
+                    .credit_total(&safety_deposit_recipient, token_id, reward);
+            }
+            let mut settled_escrow = escrow.clone();
+            settled_escrow.settled = true;
+            settled_escrow.safety_deposit_returned = true;
+            self.escrows.insert(escrow_id, settled_escrow);
+            self.settlements.insert(
+                escrow_id,
+                SettlementRecord {
+                    claimant: caller,
+                    settled_at: env::block_timestamp(),
+                    was_cancel: is_cancel,
+                    amount: escrow.amount,
+                },
+            );
             log!(
                 "ESCROW_SETTLED: hashlock='{}'",
-                bs58::encode(&hashlock).into_string()
+                bs58::encode(&escrow.hashlock).into_string()
             );
         } else {
             // A transfer failed. Revert the `claimed` status to allow another attempt.
-            if let Some(mut escrow) = self.escrows.get(&hashlock).cloned() {
+            // A native safety deposit was still paid out or credited above, so it is not paid twice.
+            if let Some(mut escrow) = self.escrows.get(&escrow_id).cloned() {
                 escrow.claimed = false;
-                self.escrows.insert(hashlock, escrow);
+                escrow.safety_deposit_returned |= escrow.safety_deposit_token.is_none();
+                self.escrows.insert(escrow_id, escrow);
                 log!(
                     "ESCROW_SETTLEMENT_FAILED: Reverted claimed status for hashlock='{}'",
-                    bs58::encode(&hashlock).into_string()
+                    bs58::encode(&escrow.hashlock).into_string()
                 );
             }
         }
@@ -433,6 +3107,458 @@ impl Contract {
                 token_id,
                 amount.0
             );
+        } else {
+            self.deposits
+                .record_external_withdrawal(&account_id, &token_id, amount);
+        }
+    }
+
+    /// Finalizes a destination escrow once its token answered `ft_metadata`, re-running the fill
+    /// checks since the hashlock, the resolver's capacity or the configuration may have changed
+    /// while the call was in flight. Otherwise the tokens are refunded through
+    /// `ft_resolve_transfer` and the safety deposit is returned.
+    #[private]
+    pub fn on_ft_metadata_verified(&mut self, escrow: Escrow) -> U128 {
+        let is_ft = matches!(env::promise_result(0), PromiseResult::Successful(_));
+        let rejection = if !is_ft {
+            Some("Token did not answer ft_metadata")
+        } else if self.escrows.contains_key(&escrow.id()) {
+            Some("Escrow already exists")
+        } else {
+            let token_id = escrow
+                .asset
+                .ft_token_id()
+                .expect("Destination escrows hold FTs");
+            self.check_fill(
+                &escrow.taker,
+                escrow.hash_algo,
+                &token_id,
+                token_to_ft_amount(escrow.amount),
+                escrow.safety_deposit,
+                escrow.safety_deposit_token.is_none(),
+            )
+            .err()
+        };
+        if let Some(reason) = rejection {
+            log!(
+                "DESTINATION_REJECTED: hashlock='{}', token_verified='{}', reason='{}'",
+                bs58::encode(&escrow.hashlock).into_string(),
+                is_ft,
+                reason
+            );
+            match &escrow.safety_deposit_token {
+                Some(token_id) => self.deposits.debit_locked(
+                    &escrow.taker,
+                    token_id,
+                    token_to_ft_amount(escrow.safety_deposit),
+                ),
+                None => {
+                    Promise::new(escrow.taker.clone()).transfer(escrow.safety_deposit);
+                }
+            }
+            return token_to_ft_amount(escrow.amount);
+        }
+        self.insert_destination_escrow(escrow);
+        U128(0)
+    }
+
+    /// Combines an escrow's asset with the token's answer to `ft_balance_of`.
+    #[private]
+    pub fn on_escrow_asset_balance(
+        &mut self,
+        #[callback_result] balance: Result<U128, near_sdk::PromiseError>,
+        asset: Asset,
+        amount: NearToken,
+    ) -> EscrowAssetInfo {
+        let tracked_balance = asset
+            .ft_token_id()
+            .map_or(U128(0), |token_id| self.get_tvl(token_id));
+        EscrowAssetInfo {
+            asset,
+            amount,
+            contract_balance: balance.ok(),
+            tracked_balance,
+        }
+    }
+
+    #[private]
+    pub fn on_cancel_withdraw_deposit(
+        &mut self,
+        escrow_id: EscrowId,
+        amount: U128,
+    ) -> PromiseOrValue<()> {
+        let escrow = self
+            .escrows
+            .get(&escrow_id)
+            .cloned()
+            .expect("Escrow not found in callback");
+
+        // A failed settlement reverts `claimed`, in which case the funds are still locked.
+        if !escrow.claimed {
+            log!(
+                "CANCEL_WITHDRAWAL_SKIPPED: Settlement failed for hashlock='{}'",
+                bs58::encode(&escrow.hashlock).into_string()
+            );
+            return PromiseOrValue::Value(());
+        }
+
+        let token_id = escrow
+            .asset
+            .ft_token_id()
+            .expect("Source escrow asset must be a fungible token");
+        PromiseOrValue::Promise(self.internal_withdraw_deposit(escrow.maker, token_id, amount))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::legacy::{LegacyDepositManager, LegacyTimelockDelays, LegacyTimelocks};
+    use crate::timelocks::TimeUnit;
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    const NOW: Timestamp = 1_000 * NANOS_IN_SEC;
+
+    fn set_context(predecessor: AccountId, promise_results: Vec<PromiseResult>) {
+        testing_env!(
+            VMContextBuilder::new()
+                .current_account_id("escrow.near".parse().unwrap())
+                .predecessor_account_id(predecessor)
+                .block_timestamp(NOW)
+                .prepaid_gas(Gas::from_tgas(300))
+                .build(),
+            near_sdk::test_vm_config(),
+            near_sdk::RuntimeFeesConfig::test(),
+            Default::default(),
+            promise_results
+        );
+    }
+
+    fn token() -> AccountId {
+        "token.near".parse().unwrap()
+    }
+
+    /// An escrow of 100 tokens between maker `accounts(0)` and taker `accounts(1)`, built
+    /// directly so its timelocks skip `validate`.
+    fn insert_escrow(
+        contract: &mut Contract,
+        delays: TimelockDelays,
+        is_source: bool,
+    ) -> Base58CryptoHash {
+        let escrow = Escrow {
+            hashlock: env::sha256_array(b"secret"),
+            hash_algo: HashAlgo::Sha256,
+            preimage_scheme: PreimageScheme::default(),
+            maker: accounts(0),
+            taker: accounts(1),
+            authorized_claimer: None,
+            payout_recipient: None,
+            asset: Asset::Ft(token()),
+            amount: NearToken::from_yoctonear(100),
+            timelocks: Timelocks::new(NOW, delays),
+            safety_deposit: NearToken::from_millinear(100),
+            safety_deposit_token: None,
+            claimed: false,
+            revealed_preimage: None,
+            revealed_by: None,
+            safety_deposit_returned: false,
+            settled: false,
+            is_source,
+            memo: None,
+            counterparty_tx: None,
+            order_hash: None,
+            last_activity: NOW,
+        };
+        let hashlock = escrow.hashlock.into();
+        contract.escrows.insert(escrow.id(), escrow);
+        hashlock
+    }
+
+    /// Default delays with the given destination withdrawal delay; 240 opens the withdrawal
+    /// window exactly when cancellation does.
+    fn delays(dst_withdrawal_delay: u64) -> TimelockDelays {
+        TimelockDelays {
+            src_withdrawal_delay: 0,
+            src_public_withdrawal_delay: 300,
+            src_cancellation_delay: 600,
+            src_public_cancellation_delay: 900,
+            dst_withdrawal_delay,
+            dst_public_withdrawal_delay: 240,
+            dst_cancellation_delay: 240,
+            time_unit: TimeUnit::Seconds,
+        }
+    }
+
+    #[test]
+    fn taker_cancels_an_escrow_without_withdrawal_window_right_away() {
+        set_context(accounts(1), vec![]);
+        let mut contract = Contract::new(accounts(2));
+        let hashlock = insert_escrow(&mut contract, delays(240), false);
+
+        contract.cancel(hashlock);
+
+        let escrow_id = contract.resolve_escrow_id(hashlock);
+        assert!(contract.escrows.get(&escrow_id).unwrap().claimed);
+    }
+
+    #[test]
+    #[should_panic(expected = "Cancellation period (dst) has not started")]
+    fn public_caller_still_waits_for_the_cancellation_window() {
+        set_context(accounts(1), vec![]);
+        let mut contract = Contract::new(accounts(2));
+        let hashlock = insert_escrow(&mut contract, delays(240), false);
+
+        set_context(accounts(3), vec![]);
+        contract.cancel(hashlock);
+    }
+
+    #[test]
+    fn repeated_settlement_debits_the_ledger_once() {
+        set_context(
+            "escrow.near".parse().unwrap(),
+            vec![
+                PromiseResult::Successful(vec![]),
+                PromiseResult::Successful(vec![]),
+            ],
+        );
+        let mut contract = Contract::new(accounts(2));
+        let hashlock = insert_escrow(&mut contract, delays(0), true);
+        let escrow_id = contract.resolve_escrow_id(hashlock);
+        contract.escrows.get_mut(&escrow_id).unwrap().claimed = true;
+        // 50 more stay locked for another escrow, so a second debit would show.
+        contract
+            .deposits
+            .credit_total(&accounts(0), &token(), U128(150));
+        contract
+            .deposits
+            .credit_locked(&accounts(0), &token(), U128(150));
+
+        for _ in 0..2 {
+            contract.on_escrow_settled(
+                escrow_id,
+                accounts(0),
+                accounts(1),
+                true,
+                false,
+                accounts(1),
+                accounts(1),
+                NearToken::from_yoctonear(0),
+                U128(0),
+                None,
+                false,
+            );
+        }
+
+        assert_eq!(
+            contract.deposits.get_total_balance(&accounts(0), &token()),
+            U128(50)
+        );
+        assert_eq!(
+            contract.deposits.get_locked_balance(&accounts(0), &token()),
+            U128(50)
+        );
+        assert!(contract.escrows.get(&escrow_id).unwrap().settled);
+    }
+
+    #[test]
+    fn time_until_window_is_negative_once_the_window_is_open() {
+        set_context(accounts(1), vec![]);
+        let mut contract = Contract::new(accounts(2));
+        let hashlock = insert_escrow(&mut contract, delays(0), false);
+        let escrow_id = contract.resolve_escrow_id(hashlock);
+        contract
+            .escrows
+            .get_mut(&escrow_id)
+            .unwrap()
+            .timelocks
+            .created_at = NOW - NANOS_IN_SEC / 2;
+
+        assert_eq!(contract.time_until_window(hashlock), Some(-1));
+    }
+
+    #[test]
+    fn metadata_callback_rejects_a_resolver_that_filled_up_meanwhile() {
+        set_context(
+            "escrow.near".parse().unwrap(),
+            vec![PromiseResult::Successful(vec![])],
+        );
+        let mut contract = Contract::new(accounts(2));
+        let hashlock = insert_escrow(&mut contract, delays(0), false);
+        let escrow_id = contract.resolve_escrow_id(hashlock);
+        let escrow = contract.escrows.remove(&escrow_id).unwrap();
+        // The resolver reached its cap while `ft_metadata` was in flight.
+        contract.max_active_escrows_per_resolver = Some(1);
+        contract.update_resolver_stats(&accounts(1), |stats| stats.filled += 1);
+
+        assert_eq!(contract.on_ft_metadata_verified(escrow), U128(100));
+        assert!(!contract.escrows.contains_key(&escrow_id));
+    }
+
+    /// Credits `amount` the way the legacy build did: every account's map on the `b"s"` prefix,
+    /// flushed as if each credit were its own transaction.
+    fn legacy_credit(
+        maps: &mut IterableMap<AccountId, IterableMap<AccountId, U128>>,
+        prefix: &[u8],
+        account_id: &str,
+        token_id: &str,
+        amount: u128,
+    ) {
+        let account_id: AccountId = account_id.parse().unwrap();
+        let token_id: AccountId = token_id.parse().unwrap();
+        if !maps.contains_key(&account_id) {
+            maps.insert(account_id.clone(), IterableMap::new(prefix));
+        }
+        let user_map = maps.get_mut(&account_id).unwrap();
+        let current = user_map.get(&token_id).map_or(0, |balance| balance.0);
+        user_map.insert(token_id, U128(current + amount));
+        user_map.flush();
+        maps.flush();
+    }
+
+    fn legacy_balance(account_id: &str, token_id: &str, total: u128) -> LegacyBalance {
+        LegacyBalance {
+            account_id: account_id.parse().unwrap(),
+            token_id: token_id.parse().unwrap(),
+            total: U128(total),
+        }
+    }
+
+    /// Legacy state where alice and bob both hold `t.near`, carol holds `u.near` and dave
+    /// holds `v.near`, with 40 of alice's tokens locked in a source escrow.
+    fn write_legacy_state() -> CryptoHash {
+        let mut deposits = IterableMap::new(b"d");
+        legacy_credit(
+            &mut deposits,
+            LEGACY_DEPOSITS_PREFIX,
+            "alice.near",
+            "t.near",
+            100,
+        );
+        legacy_credit(
+            &mut deposits,
+            LEGACY_DEPOSITS_PREFIX,
+            "bob.near",
+            "t.near",
+            50,
+        );
+        legacy_credit(
+            &mut deposits,
+            LEGACY_DEPOSITS_PREFIX,
+            "carol.near",
+            "u.near",
+            30,
+        );
+        legacy_credit(
+            &mut deposits,
+            LEGACY_DEPOSITS_PREFIX,
+            "dave.near",
+            "v.near",
+            70,
+        );
+        let mut locked_deposits = IterableMap::new(b"l");
+        legacy_credit(
+            &mut locked_deposits,
+            LEGACY_LOCKED_PREFIX,
+            "alice.near",
+            "t.near",
+            40,
+        );
+
+        let hashlock = env::sha256_array(b"secret");
+        let mut escrows = IterableMap::new(b"e");
+        escrows.insert(
+            hashlock,
+            LegacyEscrow {
+                hashlock,
+                maker: "alice.near".parse().unwrap(),
+                taker: accounts(1),
+                asset: Asset::Ft("t.near".parse().unwrap()),
+                amount: NearToken::from_yoctonear(40),
+                timelocks: LegacyTimelocks {
+                    created_at: NOW,
+                    delays: LegacyTimelockDelays {
+                        src_withdrawal_delay: 0,
+                        src_public_withdrawal_delay: 300,
+                        src_cancellation_delay: 600,
+                        src_public_cancellation_delay: 900,
+                        dst_withdrawal_delay: 0,
+                        dst_public_withdrawal_delay: 240,
+                        dst_cancellation_delay: 480,
+                    },
+                },
+                safety_deposit: NearToken::from_millinear(100),
+                claimed: false,
+                is_source: true,
+            },
+        );
+
+        let legacy = LegacyContract {
+            owner_id: accounts(2),
+            escrows,
+            deposits: LegacyDepositManager {
+                deposits,
+                locked_deposits,
+            },
+            used_nonces: IterableSet::new(b"u"),
+            registered_keys: IterableMap::new(b"k"),
+        };
+        env::state_write(&legacy);
+        drop(legacy);
+        hashlock
+    }
+
+    #[test]
+    fn migration_keeps_every_balance_held_under_the_shared_prefixes() {
+        set_context("escrow.near".parse().unwrap(), vec![]);
+        let hashlock = write_legacy_state();
+
+        let contract = Contract::migrate(vec![
+            legacy_balance("alice.near", "t.near", 100),
+            legacy_balance("bob.near", "t.near", 50),
+            legacy_balance("carol.near", "u.near", 30),
+            legacy_balance("dave.near", "v.near", 70),
+        ]);
+
+        for (account_id, token_id, total) in [
+            ("alice.near", "t.near", 100),
+            ("bob.near", "t.near", 50),
+            ("carol.near", "u.near", 30),
+            ("dave.near", "v.near", 70),
+        ] {
+            let balance = contract
+                .deposits
+                .get_total_balance(&account_id.parse().unwrap(), &token_id.parse().unwrap());
+            assert_eq!(balance, U128(total), "{} {}", account_id, token_id);
         }
+        assert_eq!(
+            contract
+                .deposits
+                .get_locked_balance(&"alice.near".parse().unwrap(), &"t.near".parse().unwrap()),
+            U128(40)
+        );
+        assert_eq!(contract.owner_id, accounts(2));
+        assert!(contract.escrows.contains_key(&escrow_id(&hashlock, true)));
+        assert_eq!(
+            contract.total_safety_deposits,
+            NearToken::from_millinear(100)
+        );
+        assert_eq!(
+            read_shared_slot(LEGACY_DEPOSITS_PREFIX, &"t.near".parse().unwrap()),
+            0
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Legacy balances of v.near do not add up to its ledger slot")]
+    fn migration_rejects_a_snapshot_missing_a_holder() {
+        set_context("escrow.near".parse().unwrap(), vec![]);
+        write_legacy_state();
+
+        Contract::migrate(vec![
+            legacy_balance("alice.near", "t.near", 100),
+            legacy_balance("bob.near", "t.near", 50),
+            legacy_balance("carol.near", "u.near", 30),
+        ]);
     }
 }
@@ -1,19 +1,88 @@
+use super::escrow::{EscrowState, HashAlgo, PreimageScheme};
 use super::timelocks::TimelockDelays;
 use near_sdk::{
-    borsh::BorshSerialize, env, json_types::U128, near, require, store::IterableSet, AccountId,
-    PublicKey,
+    borsh::BorshSerialize,
+    env,
+    json_types::{Base58CryptoHash, U128},
+    near, require,
+    store::{IterableMap, IterableSet},
+    AccountId, CryptoHash, CurveType, NearToken, PublicKey, Timestamp,
 };
 
+/// Domain prefix for signatures over a batch of orders, so they can never be replayed as a single order.
+const ORDER_BATCH_DOMAIN: &[u8] = b"fusion-near:order-batch:";
+/// Domain prefix for payout authorizations, so they can never be replayed as an order.
+const PAYOUT_AUTHORIZATION_DOMAIN: &[u8] = b"fusion-near:payout-recipient:";
+/// Maximum depth of a batch Merkle proof (up to 2^20 orders per batch).
+const MAX_BATCH_PROOF_LEN: usize = 20;
+/// Maximum number of resolvers a maker can allowlist on a single order.
+pub const MAX_TAKER_ALLOWLIST_LEN: usize = 16;
+
+/// The access-key role a maker records for a registered order-signing key.
+/// Orders are always verified with ed25519; the role only limits which keys may authorize them.
+#[near(serializers = [json, borsh])]
+#[derive(Clone, PartialEq, Eq)]
+pub enum KeyPermission {
+    FullAccess,
+    /// A function-call key, which may only sign orders when scoped to this contract and,
+    /// if it lists methods, to `initiate_source_escrow`.
+    FunctionCall {
+        receiver_id: AccountId,
+        method_names: Vec<String>,
+    },
+}
+
+impl KeyPermission {
+    /// Whether a key with this role may authorize orders filled through `contract_id`.
+    pub fn may_sign_orders(&self, contract_id: &AccountId) -> bool {
+        match self {
+            KeyPermission::FullAccess => true,
+            KeyPermission::FunctionCall {
+                receiver_id,
+                method_names,
+            } => {
+                receiver_id == contract_id
+                    && (method_names.is_empty()
+                        || method_names.iter().any(|m| m == "initiate_source_escrow"))
+            }
+        }
+    }
+}
+
 /// The core off-chain order signed by the maker for a source-side (NEAR -> Other) swap.
 #[near(serializers = [json, borsh])]
 #[derive(Clone)]
 pub struct SignedOrder {
     pub nonce: u128,
     pub maker_id: AccountId,
+    /// The only resolver allowed to fill the order, or `None` for an open order
+    /// that the first resolver to act on-chain fills.
+    pub taker_id: Option<AccountId>,
+    /// Resolvers allowed to fill the order when non-empty; ignored for a bound `taker_id`.
+    #[serde(default)]
+    pub taker_allowlist: Vec<AccountId>,
     pub asset_id: AccountId,
     pub amount: U128,
     pub hashlock: near_sdk::json_types::Base58CryptoHash,
-    pub timelocks: TimelockDelays,
+    /// `None` uses the contract's default timelocks, which are filled in before the order is
+    /// hashed, so the signature still covers the concrete delays.
+    pub timelocks: Option<TimelockDelays>,
+    /// Block timestamp (nanoseconds) after which the order can no longer be filled.
+    pub deadline: Timestamp,
+    /// Hash function of `hashlock`, signed so a resolver cannot switch it.
+    #[serde(default)]
+    pub hash_algo: HashAlgo,
+    /// How `hashlock`'s preimage is built from the revealed secret.
+    #[serde(default)]
+    pub preimage_scheme: PreimageScheme,
+    /// Where the maker's side of the swap is to be paid, if not to `maker_id`. Recorded on the
+    /// source escrow for the counterparty leg.
+    #[serde(default)]
+    pub payout_recipient: Option<AccountId>,
+    /// The counterparty-chain transaction of the other leg, so the source escrow can only be
+    /// paired with it.
+    #[serde(default)]
+    pub counterparty_tx: Option<String>,
 }
 
 impl SignedOrder {
@@ -23,20 +92,142 @@ impl SignedOrder {
         self.serialize(&mut buffer).expect("Serialization failed");
         buffer
     }
+
+    /// The order's timelocks, once the contract has filled in any defaults.
+    pub fn timelocks(&self) -> &TimelockDelays {
+        self.timelocks
+            .as_ref()
+            .expect("Order timelocks are not resolved")
+    }
 }
 
-/// Verifies that the predecessor (resolver) has a valid signature from the maker.
-pub fn verify_maker_signature(
-    params: &SignedOrder,
+/// A maker's signed open order, posted on-chain for resolvers to discover and fill.
+#[near(serializers = [json, borsh])]
+#[derive(Clone)]
+pub struct PostedOrder {
+    pub order: SignedOrder,
+    /// Base64 ed25519 signature of the order by `public_key`.
+    pub signature: String,
+    pub public_key: PublicKey,
+    /// NEAR the maker paid for the order's storage, refunded once the order is removed.
+    pub storage_deposit: NearToken,
+}
+
+/// One page of `get_open_orders`.
+#[near(serializers = [json])]
+#[derive(Clone)]
+pub struct OpenOrders {
+    /// The orders on the page that can still be filled; fewer than the limit when some of the
+    /// page's orders expired or were retracted.
+    pub orders: Vec<PostedOrder>,
+    /// Where the next page starts; `None` once every posted order has been listed.
+    pub next_index: Option<u32>,
+}
+
+/// Where an order stands, looked up by its maker and nonce.
+#[near(serializers = [json])]
+#[derive(Clone)]
+pub enum OrderStatus {
+    /// The nonce is unused, so the order can still be filled.
+    Unused,
+    /// The maker retracted the nonce, by invalidating it or bumping past it, before any fill.
+    Invalidated,
+    /// A resolver filled the order, creating the source escrow locked by `hashlock`.
+    Filled {
+        hashlock: Base58CryptoHash,
+        state: EscrowState,
+    },
+}
+
+/// Optional settings of a resolver's fill, each left out to take its default.
+#[near(serializers = [json])]
+#[serde(default)]
+#[derive(Clone, Default)]
+pub struct FillOptions {
+    /// An opaque reference (e.g. an off-chain order id) stored on the escrow.
+    pub memo: Option<String>,
+    /// Set when the signature covers a batch of orders, one of which is being filled.
+    pub batch_proof: Option<OrderBatchProof>,
+    /// Schedules the escrow's windows to start at this future timestamp.
+    pub activation_time: Option<Timestamp>,
+    /// The safety deposit; any NEAR attached beyond it is refunded to the resolver.
+    pub safety_deposit_amount: Option<NearToken>,
+    /// Locks the safety deposit from the resolver's internal balance of this token instead.
+    pub safety_deposit_token: Option<AccountId>,
+    /// A delegate who may act in the private windows on the resolver's behalf.
+    pub authorized_claimer: Option<AccountId>,
+    /// `(public_key, signature)` pairs from further registered keys, for makers with a
+    /// signature threshold.
+    pub co_signatures: Option<Vec<(PublicKey, String)>>,
+}
+
+/// Proof that an order belongs to a batch whose Merkle root was signed by the maker.
+/// Leaves are `sha256(order.to_message_bytes())`; each parent is the sha256 of its two children in sorted order.
+#[near(serializers = [json])]
+#[derive(Clone)]
+pub struct OrderBatchProof {
+    pub merkle_root: Base58CryptoHash,
+    /// Sibling hashes from the order's leaf up to the root.
+    pub proof: Vec<Base58CryptoHash>,
+}
+
+impl OrderBatchProof {
+    /// Asserts that `leaf` is included under the signed root.
+    fn assert_includes(&self, leaf: CryptoHash) {
+        require!(
+            self.proof.len() <= MAX_BATCH_PROOF_LEN,
+            "Batch proof is too long"
+        );
+        let computed_root = self.proof.iter().fold(leaf, |node, sibling| {
+            let sibling: CryptoHash = (*sibling).into();
+            let (left, right) = if node <= sibling {
+                (node, sibling)
+            } else {
+                (sibling, node)
+            };
+            env::sha256_array(&[left, right].concat())
+        });
+        let merkle_root: CryptoHash = self.merkle_root.into();
+        require!(
+            computed_root == merkle_root,
+            "Order is not part of the signed batch"
+        );
+    }
+
+    /// The digest the maker signs for the whole batch.
+    fn message_hash(&self) -> Vec<u8> {
+        let merkle_root: CryptoHash = self.merkle_root.into();
+        env::sha256(&[ORDER_BATCH_DOMAIN, &merkle_root[..]].concat())
+    }
+}
+
+/// A maker's signed instruction to pay a destination escrow out to another account.
+#[near(serializers = [json])]
+#[derive(Clone)]
+pub struct PayoutAuthorization {
+    pub recipient: AccountId,
+    pub public_key: PublicKey,
+    /// Base64 ed25519 signature over `payout_authorization_hash`.
+    pub signature: String,
+}
+
+/// The digest a maker signs to have the escrow locked under `hashlock` pay out to `recipient`.
+pub fn payout_authorization_hash(
+    maker_id: &AccountId,
+    hashlock: &CryptoHash,
+    recipient: &AccountId,
+) -> Vec<u8> {
+    let payload =
+        near_sdk::borsh::to_vec(&(maker_id, hashlock, recipient)).expect("Serialization failed");
+    env::sha256(&[PAYOUT_AUTHORIZATION_DOMAIN, &payload[..]].concat())
+}
+
+/// Asserts that `signature_bytes` is an ed25519 signature of `message_hash` by `public_key`.
+pub fn assert_ed25519_signature(
     signature_bytes: &[u8],
+    message_hash: &[u8],
     public_key: &PublicKey,
-    used_nonces: &mut IterableSet<u128>,
 ) {
-    require!(!used_nonces.contains(&params.nonce), "Nonce already used");
-
-    let message_bytes = params.to_message_bytes();
-    let message_hash = env::sha256(&message_bytes);
-
     let signature: [u8; 64] = signature_bytes
         .try_into()
         .expect("Signature must be 64 bytes");
@@ -45,9 +236,76 @@ pub fn verify_maker_signature(
     let public_key_arr: [u8; 32] = pk_bytes[1..].try_into().expect("Invalid public key format");
 
     require!(
-        env::ed25519_verify(&signature, &message_hash, &public_key_arr),
+        env::ed25519_verify(&signature, message_hash, &public_key_arr),
         "Signature verification failed"
     );
+}
+
+/// Verifies that the predecessor (resolver) holds at least `threshold` valid signatures from
+/// distinct maker keys, each given as a key and its signature bytes.
+/// With a `batch_proof`, the signatures cover the batch root and the order must be one of its leaves.
+/// Each order in a batch carries its own nonce, so a leaf can only ever be filled once.
+///
+/// Makers with a nonce high-water mark use sequential nonces: an order must carry a nonce above
+/// the mark, which then advances to it instead of growing `used_nonces`.
+pub fn verify_maker_signature(
+    params: &SignedOrder,
+    batch_proof: Option<&OrderBatchProof>,
+    signatures: &[(PublicKey, Vec<u8>)],
+    threshold: u32,
+    used_nonces: &mut IterableSet<(AccountId, u128)>,
+    nonce_high_water: &mut IterableMap<AccountId, u128>,
+) {
+    require!(
+        env::block_timestamp() <= params.deadline,
+        "Order deadline has passed"
+    );
+    let nonce_key = (params.maker_id.clone(), params.nonce);
+    require!(!used_nonces.contains(&nonce_key), "Nonce already used");
+    let high_water = nonce_high_water.get(&params.maker_id).copied();
+    if let Some(high_water) = high_water {
+        require!(params.nonce > high_water, "Nonce already used");
+    }
+
+    let message_bytes = params.to_message_bytes();
+    let message_hash = match batch_proof {
+        Some(batch_proof) => {
+            batch_proof.assert_includes(env::sha256_array(&message_bytes));
+            batch_proof.message_hash()
+        }
+        None => env::sha256(&message_bytes),
+    };
+
+    require!(
+        signatures.len() >= threshold as usize,
+        "Not enough maker signatures"
+    );
+    for (index, (public_key, signature_bytes)) in signatures.iter().enumerate() {
+        require!(
+            signatures[..index].iter().all(|(key, _)| key != public_key),
+            "Each signature must come from a different key"
+        );
+        assert_ed25519_signature(signature_bytes, &message_hash, public_key);
+    }
 
-    used_nonces.insert(params.nonce);
+    if high_water.is_some() {
+        nonce_high_water.insert(params.maker_id.clone(), params.nonce);
+    } else {
+        used_nonces.insert(nonce_key);
+    }
+}
+
+/// Derives the ed25519 public key of an implicit account, whose id is the hex-encoded key itself.
+/// Returns `None` for named accounts.
+pub fn implicit_account_public_key(account_id: &AccountId) -> Option<PublicKey> {
+    let id = account_id.as_str();
+    if id.len() != 64 || !id.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f')) {
+        return None;
+    }
+    let key_bytes = (0..id.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&id[i..i + 2], 16))
+        .collect::<Result<Vec<u8>, _>>()
+        .ok()?;
+    PublicKey::from_parts(CurveType::ED25519, key_bytes).ok()
 }
@@ -0,0 +1,84 @@
+use near_sdk::{env, AccountId, CryptoHash, IntoStorageKey};
+
+/// Storage prefixes of every persistent collection, kept in one place so none can be reused.
+///
+/// Top-level collections keep the single-byte prefixes they were deployed with, so upgrading
+/// does not move their data. Per-account and per-token sub-collections get a prefix derived from
+/// the account id, so no two of them can share storage.
+pub enum StorageKey {
+    Escrows,
+    UsedNonces,
+    NonceHighWater,
+    RegisteredKeys,
+    KeyPermissions,
+    Settlements,
+    Relayers,
+    ResolverStats,
+    ResolverVolume,
+    MinSafetyDepositPerUnit,
+    MinEscrowAmount,
+    OrderEscrows,
+    PostedOrders,
+    SignatureThresholds,
+    Deposits,
+    LockedDeposits,
+    DepositLifetimeStats,
+    LedgerTotals,
+    DestinationEscrowTotals,
+    AccountActivity,
+    TokenHolders,
+    UserDeposits { account_hash: CryptoHash },
+    UserLockedDeposits { account_hash: CryptoHash },
+    TokenHolderSet { token_hash: CryptoHash },
+}
+
+impl StorageKey {
+    pub fn user_deposits(account_id: &AccountId) -> Self {
+        StorageKey::UserDeposits {
+            account_hash: env::sha256_array(account_id.as_bytes()),
+        }
+    }
+
+    pub fn user_locked_deposits(account_id: &AccountId) -> Self {
+        StorageKey::UserLockedDeposits {
+            account_hash: env::sha256_array(account_id.as_bytes()),
+        }
+    }
+
+    pub fn token_holders(token_id: &AccountId) -> Self {
+        StorageKey::TokenHolderSet {
+            token_hash: env::sha256_array(token_id.as_bytes()),
+        }
+    }
+}
+
+impl IntoStorageKey for StorageKey {
+    fn into_storage_key(self) -> Vec<u8> {
+        match self {
+            StorageKey::Escrows => b"e".to_vec(),
+            StorageKey::UsedNonces => b"u".to_vec(),
+            StorageKey::NonceHighWater => b"n".to_vec(),
+            StorageKey::RegisteredKeys => b"k".to_vec(),
+            StorageKey::KeyPermissions => b"p".to_vec(),
+            StorageKey::Settlements => b"r".to_vec(),
+            StorageKey::Relayers => b"y".to_vec(),
+            StorageKey::ResolverStats => b"v".to_vec(),
+            StorageKey::ResolverVolume => b"w".to_vec(),
+            StorageKey::MinSafetyDepositPerUnit => b"m".to_vec(),
+            StorageKey::MinEscrowAmount => b"a".to_vec(),
+            StorageKey::OrderEscrows => b"o".to_vec(),
+            StorageKey::PostedOrders => b"b".to_vec(),
+            StorageKey::SignatureThresholds => b"t".to_vec(),
+            StorageKey::Deposits => b"d".to_vec(),
+            StorageKey::LockedDeposits => b"l".to_vec(),
+            StorageKey::DepositLifetimeStats => b"f".to_vec(),
+            StorageKey::LedgerTotals => b"g".to_vec(),
+            StorageKey::DestinationEscrowTotals => b"h".to_vec(),
+            StorageKey::AccountActivity => b"i".to_vec(),
+            StorageKey::TokenHolders => b"j".to_vec(),
+            StorageKey::UserDeposits { account_hash } => [&b"S"[..], &account_hash].concat(),
+            StorageKey::UserLockedDeposits { account_hash } => [&b"X"[..], &account_hash].concat(),
+            StorageKey::TokenHolderSet { token_hash } => [&b"H"[..], &token_hash].concat(),
+        }
+    }
+}
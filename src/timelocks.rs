@@ -1,8 +1,50 @@
 use near_sdk::{env, near, require, Timestamp};
 
-const NANOS_IN_SEC: u64 = 1_000_000_000;
+pub const NANOS_IN_SEC: u64 = 1_000_000_000;
 
-/// Defines the delays in seconds for all critical stages of a swap, relative to its creation time.
+/// How far in the future an escrow's activation may be scheduled (30 days).
+pub const MAX_ACTIVATION_HORIZON_SECS: u64 = 30 * 24 * 60 * 60;
+
+/// Resolves the timestamp an escrow's windows are computed from.
+/// Without an `activation_time` that is the current block; a scheduled activation must not be
+/// in the past and must fall within `MAX_ACTIVATION_HORIZON_SECS`.
+pub fn activation_timestamp(activation_time: Option<Timestamp>) -> Timestamp {
+    let now = env::block_timestamp();
+    match activation_time {
+        None => now,
+        Some(activation_time) => {
+            require!(activation_time >= now, "Activation time is in the past");
+            require!(
+                activation_time - now <= MAX_ACTIVATION_HORIZON_SECS * NANOS_IN_SEC,
+                "Activation time is too far in the future"
+            );
+            activation_time
+        }
+    }
+}
+
+/// The unit a `TimelockDelays` counts its delays in.
+/// Milliseconds give finer windows for fast counterparty chains.
+#[near(serializers = [json, borsh])]
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+pub enum TimeUnit {
+    #[default]
+    Seconds,
+    Milliseconds,
+}
+
+impl TimeUnit {
+    /// Nanoseconds in one unit.
+    pub fn nanos(self) -> u64 {
+        match self {
+            TimeUnit::Seconds => NANOS_IN_SEC,
+            TimeUnit::Milliseconds => NANOS_IN_SEC / 1_000,
+        }
+    }
+}
+
+/// Defines the delays for all critical stages of a swap, relative to its creation time.
+/// Delays count in `time_unit`, seconds unless the order or default says otherwise.
 #[near(serializers = [json, borsh])]
 #[derive(Clone)]
 pub struct TimelockDelays {
@@ -16,6 +58,38 @@ pub struct TimelockDelays {
     pub dst_withdrawal_delay: u64,
     pub dst_public_withdrawal_delay: u64,
     pub dst_cancellation_delay: u64,
+
+    #[serde(default)]
+    pub time_unit: TimeUnit,
+}
+
+/// The deployment's current timelock rules beyond the ordering `validate` always enforces,
+/// so clients can check delays locally before signing an order.
+#[near(serializers = [json])]
+#[derive(Clone)]
+pub struct TimelockConstraints {
+    /// Minimum seconds between `dst_public_withdrawal_delay` and `src_cancellation_delay`.
+    pub min_reveal_grace: u64,
+    /// Seconds added to every public window on top of the escrow's own delay.
+    pub public_grace: u64,
+    /// How far in the future, in seconds, a scheduled `activation_time` may lie.
+    pub max_activation_horizon: u64,
+}
+
+/// The stage an escrow's timelocks are in at a given moment.
+#[near(serializers = [json])]
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum TimelockStage {
+    /// No withdrawal or cancellation is possible yet.
+    Finality,
+    /// Only the taker may withdraw.
+    PrivateWithdrawal,
+    /// Anyone holding the secret may withdraw.
+    PublicWithdrawal,
+    /// Only the taker may cancel.
+    PrivateCancellation,
+    /// Anyone may cancel.
+    PublicCancellation,
 }
 
 /// A runtime object that combines creation time with delay configuration to manage swap stages.
@@ -33,54 +107,139 @@ impl Timelocks {
 
     // --- HELPER METHODS ---
 
+    /// Returns the absolute timestamp at which a delay (in `time_unit`) elapses.
+    /// Panics rather than wrapping, so an oversized delay can never open a window early.
+    ///
+    /// Every window opens inclusively at its deadline (`now >= deadline`). Source withdrawal closes
+    /// exclusively once cancellation opens (`now < deadline`), so no instant belongs to both;
+    /// destination withdrawal also stays open past its own cancellation deadline, closing
+    /// exclusively at the source cancellation deadline unless a cancellation settles it first.
+    /// A zero delay is therefore open in the creation block itself, where `now == created_at`,
+    /// while any positive delay is not.
+    fn deadline(&self, delay: u64) -> Timestamp {
+        self.delays
+            .delay_to_nanos(delay)
+            .and_then(|nanos| self.created_at.checked_add(nanos))
+            .unwrap_or_else(|| env::panic_str("Timelock overflow"))
+    }
+
+    /// Returns the absolute timestamp at which a public window opens, including the global grace.
+    /// The grace is always in seconds, whatever unit the escrow's delays use.
+    fn public_deadline(&self, delay: u64, public_grace: u64) -> Timestamp {
+        public_grace
+            .checked_mul(NANOS_IN_SEC)
+            .and_then(|grace| self.deadline(delay).checked_add(grace))
+            .unwrap_or_else(|| env::panic_str("Timelock overflow"))
+    }
+
+    /// Classifies the stage of a source or destination escrow at `now`.
+    /// Destination escrows have no private cancellation stage: anyone may cancel them.
+    pub fn stage(&self, is_source: bool, now: Timestamp, public_grace: u64) -> TimelockStage {
+        let d = &self.delays;
+        if is_source {
+            if now >= self.public_deadline(d.src_public_cancellation_delay, public_grace) {
+                TimelockStage::PublicCancellation
+            } else if now >= self.deadline(d.src_cancellation_delay) {
+                TimelockStage::PrivateCancellation
+            } else if now >= self.public_deadline(d.src_public_withdrawal_delay, public_grace) {
+                TimelockStage::PublicWithdrawal
+            } else if now >= self.deadline(d.src_withdrawal_delay) {
+                TimelockStage::PrivateWithdrawal
+            } else {
+                TimelockStage::Finality
+            }
+        } else if now >= self.deadline(d.dst_cancellation_delay) {
+            TimelockStage::PublicCancellation
+        } else if now >= self.public_deadline(d.dst_public_withdrawal_delay, public_grace) {
+            TimelockStage::PublicWithdrawal
+        } else if now >= self.deadline(d.dst_withdrawal_delay) {
+            TimelockStage::PrivateWithdrawal
+        } else {
+            TimelockStage::Finality
+        }
+    }
+
+    /// Returns when the escrow's first actionable window (the private withdrawal) opens.
+    pub fn first_window_opens_at(&self, is_source: bool) -> Timestamp {
+        if is_source {
+            self.deadline(self.delays.src_withdrawal_delay)
+        } else {
+            self.deadline(self.delays.dst_withdrawal_delay)
+        }
+    }
+
+    /// Returns when anyone holding the secret may start withdrawing, including the global grace.
+    pub fn public_withdrawal_opens_at(&self, is_source: bool, public_grace: u64) -> Timestamp {
+        if is_source {
+            self.public_deadline(self.delays.src_public_withdrawal_delay, public_grace)
+        } else {
+            self.public_deadline(self.delays.dst_public_withdrawal_delay, public_grace)
+        }
+    }
+
+    /// Whether the private withdrawal window opens before cancellation does.
+    /// `validate` guarantees this for new escrows; it is re-checked as a fallback so funds are never trapped.
+    pub fn has_withdrawal_window(&self, is_source: bool) -> bool {
+        let d = &self.delays;
+        if is_source {
+            d.src_withdrawal_delay < d.src_cancellation_delay
+        } else {
+            d.dst_withdrawal_delay < d.dst_cancellation_delay
+        }
+    }
+
+    /// Returns when destination withdrawal closes: the source cancellation deadline, after which
+    /// the source escrow may already have been refunded to the maker.
+    pub fn dst_withdrawal_closes_at(&self) -> Timestamp {
+        self.deadline(self.delays.src_cancellation_delay)
+    }
+
     /// Asserts the current time is valid for a `withdrawal` (claim) on the destination chain.
-    pub fn assert_dst_withdrawal_window(&self, is_public_caller: bool) {
+    /// A valid secret still pays the maker after the cancellation window opens, until someone
+    /// cancels the escrow or the source cancellation deadline passes, so an abandoning resolver
+    /// cannot strand a maker who already holds the secret.
+    pub fn assert_dst_withdrawal_window(&self, is_public_caller: bool, public_grace: u64) {
         let now = env::block_timestamp();
 
         if is_public_caller {
             let public_withdrawal_start =
-                self.created_at + self.delays.dst_public_withdrawal_delay * NANOS_IN_SEC;
+                self.public_deadline(self.delays.dst_public_withdrawal_delay, public_grace);
             require!(
                 now >= public_withdrawal_start,
                 "Public withdrawal period (dst) has not started"
             );
         } else {
-            let withdrawal_start =
-                self.created_at + self.delays.dst_withdrawal_delay * NANOS_IN_SEC;
+            let withdrawal_start = self.deadline(self.delays.dst_withdrawal_delay);
             require!(
                 now >= withdrawal_start,
                 "Private withdrawal period (dst) has not started"
             );
         }
-        let cancellation_start =
-            self.created_at + self.delays.dst_cancellation_delay * NANOS_IN_SEC;
         require!(
-            now < cancellation_start,
-            "Cancellation period (dst) has started"
+            now < self.dst_withdrawal_closes_at(),
+            "Withdrawal period (dst) has ended"
         );
     }
 
     /// Asserts the current time is valid for a `withdrawal` (claim) on the source chain.
-    pub fn assert_src_withdrawal_window(&self, is_public_caller: bool) {
+    pub fn assert_src_withdrawal_window(&self, is_public_caller: bool, public_grace: u64) {
         let now = env::block_timestamp();
 
         if is_public_caller {
             let public_withdrawal_start =
-                self.created_at + self.delays.src_public_withdrawal_delay * NANOS_IN_SEC;
+                self.public_deadline(self.delays.src_public_withdrawal_delay, public_grace);
             require!(
                 now >= public_withdrawal_start,
                 "Public withdrawal period (src) has not started"
             );
         } else {
-            let withdrawal_start =
-                self.created_at + self.delays.src_withdrawal_delay * NANOS_IN_SEC;
+            let withdrawal_start = self.deadline(self.delays.src_withdrawal_delay);
             require!(
                 now >= withdrawal_start,
                 "Private withdrawal period (src) has not started"
             );
         }
-        let cancellation_start =
-            self.created_at + self.delays.src_cancellation_delay * NANOS_IN_SEC;
+        let cancellation_start = self.deadline(self.delays.src_cancellation_delay);
         require!(
             now < cancellation_start,
             "Cancellation period (src) has started"
@@ -90,8 +249,7 @@ impl Timelocks {
     /// Asserts the current time is valid for a `cancellation` (refund) on the destination chain.
     pub fn assert_dst_cancellation_window(&self) {
         let now = env::block_timestamp();
-        let cancellation_start =
-            self.created_at + self.delays.dst_cancellation_delay * NANOS_IN_SEC;
+        let cancellation_start = self.deadline(self.delays.dst_cancellation_delay);
         require!(
             now >= cancellation_start,
             "Cancellation period (dst) has not started"
@@ -99,19 +257,18 @@ impl Timelocks {
     }
 
     /// Asserts the current time is valid for a `cancellation` (refund) on the source chain.
-    pub fn assert_src_cancellation_window(&self, is_public_caller: bool) {
+    pub fn assert_src_cancellation_window(&self, is_public_caller: bool, public_grace: u64) {
         let now = env::block_timestamp();
 
         if is_public_caller {
             let public_cancellation_start =
-                self.created_at + self.delays.src_public_cancellation_delay * NANOS_IN_SEC;
+                self.public_deadline(self.delays.src_public_cancellation_delay, public_grace);
             require!(
                 now >= public_cancellation_start,
                 "Public cancellation period (src) has not started"
             );
         } else {
-            let cancellation_start =
-                self.created_at + self.delays.src_cancellation_delay * NANOS_IN_SEC;
+            let cancellation_start = self.deadline(self.delays.src_cancellation_delay);
             require!(
                 now >= cancellation_start,
                 "Private cancellation period (src) has not started"
@@ -121,10 +278,35 @@ impl Timelocks {
 }
 
 impl TimelockDelays {
+    /// Converts a delay in `time_unit` to nanoseconds, returning `None` on overflow.
+    fn delay_to_nanos(&self, delay: u64) -> Option<u64> {
+        delay.checked_mul(self.time_unit.nanos())
+    }
+
     /// Validates the internal consistency of the delay settings.
     /// This prevents the creation of swaps with illogical time windows.
-    /// It must be called before an escrow is created.
-    pub fn validate(&self) {
+    /// It must be called before an escrow is created, with the timestamp its windows start from
+    /// and the minimum reveal window (in seconds) the deployment requires.
+    pub fn validate(&self, created_at: Timestamp, min_reveal_grace: u64) {
+        // --- Range Validation ---
+        // Every deadline must be representable when added to the creation time.
+        for delay in [
+            self.src_withdrawal_delay,
+            self.src_public_withdrawal_delay,
+            self.src_cancellation_delay,
+            self.src_public_cancellation_delay,
+            self.dst_withdrawal_delay,
+            self.dst_public_withdrawal_delay,
+            self.dst_cancellation_delay,
+        ] {
+            require!(
+                self.delay_to_nanos(delay)
+                    .and_then(|nanos| created_at.checked_add(nanos))
+                    .is_some(),
+                "Timelock delay is too large"
+            );
+        }
+
         // --- Source Chain Validation ---
         // The private withdrawal period must start before the public one.
         require!(
@@ -161,5 +343,15 @@ impl TimelockDelays {
             self.dst_cancellation_delay <= self.src_cancellation_delay,
             "X-CHAIN: Destination cancellation must not be after source cancellation"
         );
+        // Once the secret can be revealed publicly on the destination chain, the resolver must
+        // still have time to use it on the source chain before the source can be cancelled.
+        let reveal_window = self
+            .src_cancellation_delay
+            .saturating_sub(self.dst_public_withdrawal_delay);
+        require!(
+            u128::from(reveal_window) * u128::from(self.time_unit.nanos())
+                >= u128::from(min_reveal_grace) * u128::from(NANOS_IN_SEC),
+            "X-CHAIN: Reveal window before source cancellation is too short"
+        );
     }
 }
@@ -1,12 +1,88 @@
-use near_sdk::{bs58, env, AccountId, CryptoHash, NearToken};
+use near_sdk::base64::{engine::general_purpose::STANDARD, Engine as _};
+use near_sdk::json_types::U128;
+use near_sdk::{bs58, env, serde_json, AccountId, CryptoHash, NearToken};
+
+/// NEP-297 standard name for this contract's structured events.
+const EVENT_STANDARD: &str = "cross-chain-swap";
+const EVENT_VERSION: &str = "1.0.0";
 
 // Helper for consistent logging
-pub fn log_escrow_event(event: &str, hashlock: &CryptoHash, actor: &AccountId, amount: NearToken) {
+pub fn log_escrow_event(
+    event: &str,
+    hashlock: &CryptoHash,
+    actor: &AccountId,
+    amount: NearToken,
+    memo: Option<&str>,
+) {
+    let memo_suffix = memo
+        .map(|memo| format!(", memo='{}'", memo))
+        .unwrap_or_default();
     env::log_str(&format!(
-        "ESCROW_{}: hashlock='{}', actor='{}', amount='{}'",
+        "ESCROW_{}: hashlock='{}', actor='{}', amount='{}'{}",
         event,
         bs58::encode(hashlock).into_string(),
         actor,
-        amount.as_yoctonear()
+        amount.as_yoctonear(),
+        memo_suffix
     ));
 }
+
+/// Decodes user-supplied standard base64, or `None` if it is invalid.
+pub fn try_decode_base64(value: &str) -> Option<Vec<u8>> {
+    STANDARD.decode(value).ok()
+}
+
+/// Decodes user-supplied base64, panicking with `error` alone rather than the decoder's message.
+pub fn decode_base64(value: &str, error: &str) -> Vec<u8> {
+    try_decode_base64(value).unwrap_or_else(|| env::panic_str(error))
+}
+
+/// Converts an FT amount, in the token's base units, into the `NearToken` escrows store it as.
+///
+/// Escrow amounts are `NearToken` whatever the asset, so for FT escrows one yoctoNEAR stands for
+/// one base unit of the token; no decimals are applied. Both are `u128`, so the conversion is
+/// lossless for every value.
+pub fn ft_amount_to_token(amount: U128) -> NearToken {
+    NearToken::from_yoctonear(amount.0)
+}
+
+/// Converts an escrow amount back into FT base units; the inverse of [`ft_amount_to_token`].
+pub fn token_to_ft_amount(amount: NearToken) -> U128 {
+    U128(amount.as_yoctonear())
+}
+
+/// Lowercase hex encoding, without a prefix.
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Logs a NEP-297 event (`EVENT_JSON:` prefixed) with a single data entry.
+pub fn log_nep297_event(event: &str, data: serde_json::Value) {
+    let event = serde_json::json!({
+        "standard": EVENT_STANDARD,
+        "version": EVENT_VERSION,
+        "event": event,
+        "data": [data],
+    });
+    env::log_str(&format!("EVENT_JSON:{}", event));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ft_amounts_round_trip_through_near_token() {
+        for amount in [0, 1, u128::MAX] {
+            let token = ft_amount_to_token(U128(amount));
+            assert_eq!(token.as_yoctonear(), amount);
+            assert_eq!(token_to_ft_amount(token), U128(amount));
+        }
+    }
+
+    #[test]
+    fn base64_decoding_rejects_invalid_input() {
+        assert_eq!(try_decode_base64("c2VjcmV0"), Some(b"secret".to_vec()));
+        assert_eq!(try_decode_base64("not base64!"), None);
+    }
+}
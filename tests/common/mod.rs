@@ -0,0 +1,230 @@
+#![allow(dead_code)]
+
+use anyhow::Result;
+use near_workspaces::network::Sandbox;
+use near_workspaces::types::NearToken;
+use near_workspaces::{Account, Contract, Worker};
+use serde_json::json;
+
+/// Helper function to set up the testing environment.
+/// This will:
+/// 1. Compile and deploy the HTLC contract to a fresh sandbox.
+/// 2. Initialize it with a dedicated owner account.
+pub async fn setup() -> Result<(Worker<Sandbox>, Contract, Account)> {
+    let worker = near_workspaces::sandbox().await?;
+    let wasm = near_workspaces::compile_project("./").await?;
+    let contract = worker.dev_deploy(&wasm).await?;
+    let owner = worker.dev_create_account().await?;
+
+    contract
+        .call("new")
+        .args_json(json!({ "owner_id": owner.id() }))
+        .transact()
+        .await?
+        .into_result()?;
+
+    Ok((worker, contract, owner))
+}
+
+/// Total supply minted to the owner of a mock FT deployed by `setup_ft`.
+pub const FT_TOTAL_SUPPLY: u128 = 1_000_000_000;
+
+/// Deploys the mock NEP-141 token from `tests/mock-ft`, minting its whole supply to `owner`
+/// and registering `holders` so they can receive it.
+pub async fn setup_ft(
+    worker: &Worker<Sandbox>,
+    owner: &Account,
+    holders: &[&near_workspaces::AccountId],
+) -> Result<Contract> {
+    let wasm = near_workspaces::compile_project("./tests/mock-ft").await?;
+    let ft = worker.dev_deploy(&wasm).await?;
+    ft.call("new")
+        .args_json(json!({
+            "owner_id": owner.id(),
+            "total_supply": FT_TOTAL_SUPPLY.to_string(),
+        }))
+        .transact()
+        .await?
+        .into_result()?;
+    for holder in holders {
+        owner
+            .call(ft.id(), "storage_deposit")
+            .args_json(json!({ "account_id": holder }))
+            .deposit(NearToken::from_millinear(10))
+            .transact()
+            .await?
+            .into_result()?;
+    }
+    Ok(ft)
+}
+
+/// Sends `amount` of a mock FT from `sender` to `receiver_id`.
+pub async fn ft_transfer(
+    ft: &Contract,
+    sender: &Account,
+    receiver_id: &near_workspaces::AccountId,
+    amount: u128,
+) -> Result<()> {
+    sender
+        .call(ft.id(), "ft_transfer")
+        .args_json(json!({ "receiver_id": receiver_id, "amount": amount.to_string() }))
+        .deposit(NearToken::from_yoctonear(1))
+        .transact()
+        .await?
+        .into_result()?;
+    Ok(())
+}
+
+/// Reads an account's balance of a mock FT.
+pub async fn ft_balance_of(ft: &Contract, account_id: &near_workspaces::AccountId) -> Result<u128> {
+    let balance: String = ft
+        .view("ft_balance_of")
+        .args_json(json!({ "account_id": account_id }))
+        .await?
+        .json()?;
+    Ok(balance.parse()?)
+}
+
+/// Credits `amount` of `token` to `sender`'s internal ledger.
+/// The token account calls `ft_on_transfer` directly, standing in for a real FT contract.
+pub async fn deposit(
+    contract: &Contract,
+    token: &Account,
+    sender: &Account,
+    amount: u128,
+) -> Result<()> {
+    token
+        .call(contract.id(), "ft_on_transfer")
+        .args_json(json!({
+            "sender_id": sender.id(),
+            "amount": amount.to_string(),
+            "msg": json!({ "type": "Deposit" }).to_string(),
+        }))
+        .transact()
+        .await?
+        .into_result()?;
+    Ok(())
+}
+
+/// Creates a destination escrow funded by `resolver`, with `token` standing in for the FT contract.
+pub async fn create_destination_escrow(
+    contract: &Contract,
+    token: &Account,
+    resolver: &Account,
+    msg: serde_json::Value,
+    amount: u128,
+    safety_deposit: NearToken,
+) -> Result<near_workspaces::result::ExecutionFinalResult> {
+    Ok(token
+        .call(contract.id(), "ft_on_transfer")
+        .args_json(json!({
+            "sender_id": resolver.id(),
+            "amount": amount.to_string(),
+            "msg": msg.to_string(),
+        }))
+        .deposit(safety_deposit)
+        .max_gas()
+        .transact()
+        .await?)
+}
+
+/// Asserts that a transaction failed and that its error mentions `expected`.
+pub fn assert_failure_contains(
+    outcome: near_workspaces::result::ExecutionFinalResult,
+    expected: &str,
+) {
+    let err = outcome
+        .into_result()
+        .expect_err("Transaction was expected to fail");
+    let message = format!("{:?}", err);
+    assert!(
+        message.contains(expected),
+        "Expected error containing '{}', got: {}",
+        expected,
+        message
+    );
+}
+
+/// A set of delays that passes `TimelockDelays::validate`.
+pub fn default_timelocks() -> serde_json::Value {
+    json!({
+        "src_withdrawal_delay": 0,
+        "src_public_withdrawal_delay": 300,
+        "src_cancellation_delay": 600,
+        "src_public_cancellation_delay": 900,
+        "dst_withdrawal_delay": 0,
+        "dst_public_withdrawal_delay": 120,
+        "dst_cancellation_delay": 240,
+    })
+}
+
+/// Computes the base58 sha256 hashlock of `secret`, as the contract expects it.
+pub fn hashlock(secret: &[u8]) -> String {
+    use sha2::{Digest, Sha256};
+    near_sdk::bs58::encode(Sha256::digest(secret)).into_string()
+}
+
+/// An ed25519 maker key that signs orders exactly as the contract verifies them.
+pub struct OrderSigner(ed25519_dalek::SigningKey);
+
+impl OrderSigner {
+    pub fn from_seed(seed: u8) -> Self {
+        Self(ed25519_dalek::SigningKey::from_bytes(&[seed; 32]))
+    }
+
+    /// Signs with `account`'s own full-access key, whose public key the account can register.
+    /// Only ed25519 keys are supported, as the contract only verifies ed25519 signatures.
+    pub fn from_account(account: &Account) -> Self {
+        let secret_key = account.secret_key().to_string();
+        let encoded = secret_key
+            .strip_prefix("ed25519:")
+            .expect("Order signing keys must be ed25519");
+        let bytes = near_sdk::bs58::decode(encoded)
+            .into_vec()
+            .expect("Invalid secret key encoding");
+        let seed: [u8; 32] = bytes[..32].try_into().expect("Invalid ed25519 secret key");
+        Self(ed25519_dalek::SigningKey::from_bytes(&seed))
+    }
+
+    /// The public key in NEAR's `ed25519:<base58>` string form.
+    pub fn public_key(&self) -> String {
+        format!(
+            "ed25519:{}",
+            near_sdk::bs58::encode(self.0.verifying_key().as_bytes()).into_string()
+        )
+    }
+
+    /// Signs the contract's canonical hash of `order` and returns the base64 signature.
+    pub async fn sign_order(
+        &self,
+        contract: &Contract,
+        order: &serde_json::Value,
+    ) -> Result<String> {
+        let hash: String = contract
+            .view("get_order_hash")
+            .args_json(json!({ "order": order }))
+            .await?
+            .json()?;
+        self.sign_hash(&hash)
+    }
+
+    /// Signs a base64 digest returned by one of the contract's hash views.
+    pub fn sign_hash(&self, hash: &str) -> Result<String> {
+        use base64::Engine;
+        use ed25519_dalek::Signer;
+
+        let hash = base64::engine::general_purpose::STANDARD.decode(hash)?;
+        Ok(base64::engine::general_purpose::STANDARD.encode(self.0.sign(&hash).to_bytes()))
+    }
+}
+
+/// Signs `order` with `account`'s own key and returns the base64 signature the contract expects.
+pub async fn sign_order(
+    contract: &Contract,
+    account: &Account,
+    order: &serde_json::Value,
+) -> Result<String> {
+    OrderSigner::from_account(account)
+        .sign_order(contract, order)
+        .await
+}
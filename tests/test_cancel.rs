@@ -0,0 +1,681 @@
+mod common;
+
+use anyhow::Result;
+use near_workspaces::types::NearToken;
+use serde_json::json;
+use sha2::Digest;
+
+#[tokio::test]
+async fn test_relayer_cancel_is_restricted_to_registered_relayers() -> Result<()> {
+    let (worker, contract, owner) = common::setup().await?;
+    let token = worker.dev_create_account().await?;
+    let maker = worker.dev_create_account().await?;
+    let resolver = worker.dev_create_account().await?;
+    let relayer = worker.dev_create_account().await?;
+
+    let hashlock = common::hashlock(b"dead source leg");
+    common::create_destination_escrow(
+        &contract,
+        &token,
+        &resolver,
+        json!({
+            "type": "CreateDestinationEscrow",
+            "hashlock": hashlock,
+            "maker_id": maker.id(),
+            "timelocks": common::default_timelocks(),
+        }),
+        100,
+        NearToken::from_millinear(100),
+    )
+    .await?
+    .into_result()?;
+
+    // Not registered yet.
+    let outcome = relayer
+        .call(contract.id(), "relayer_cancel")
+        .args_json(json!({ "hashlock": hashlock }))
+        .max_gas()
+        .transact()
+        .await?;
+    common::assert_failure_contains(outcome, "Only registered relayers can fast-cancel");
+
+    // Only the owner can register relayers.
+    let outcome = relayer
+        .call(contract.id(), "add_relayer")
+        .args_json(json!({ "account_id": relayer.id() }))
+        .transact()
+        .await?;
+    common::assert_failure_contains(outcome, "Only the owner can call this method");
+
+    owner
+        .call(contract.id(), "add_relayer")
+        .args_json(json!({ "account_id": relayer.id() }))
+        .transact()
+        .await?
+        .into_result()?;
+    let is_relayer: bool = contract
+        .view("is_relayer")
+        .args_json(json!({ "account_id": relayer.id() }))
+        .await?
+        .json()?;
+    assert!(is_relayer);
+
+    // A registered relayer bypasses the destination cancellation delay.
+    let outcome = relayer
+        .call(contract.id(), "relayer_cancel")
+        .args_json(json!({ "hashlock": hashlock }))
+        .max_gas()
+        .transact()
+        .await?;
+    assert!(outcome
+        .logs()
+        .iter()
+        .any(|log| log.starts_with("ESCROW_CANCELED")));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_failed_cancel_refund_falls_back_to_internal_balance() -> Result<()> {
+    let (worker, contract, owner) = common::setup().await?;
+    // The stand-in token has no contract, so every `ft_transfer` refund to the taker fails.
+    let token = worker.dev_create_account().await?;
+    let maker = worker.dev_create_account().await?;
+    let resolver = worker.dev_create_account().await?;
+    let relayer = worker.dev_create_account().await?;
+
+    let hashlock = common::hashlock(b"unregistered taker");
+    common::create_destination_escrow(
+        &contract,
+        &token,
+        &resolver,
+        json!({
+            "type": "CreateDestinationEscrow",
+            "hashlock": hashlock,
+            "maker_id": maker.id(),
+            "timelocks": common::default_timelocks(),
+        }),
+        100,
+        NearToken::from_millinear(100),
+    )
+    .await?
+    .into_result()?;
+
+    owner
+        .call(contract.id(), "add_relayer")
+        .args_json(json!({ "account_id": relayer.id() }))
+        .transact()
+        .await?
+        .into_result()?;
+    relayer
+        .call(contract.id(), "relayer_cancel")
+        .args_json(json!({ "hashlock": hashlock }))
+        .max_gas()
+        .transact()
+        .await?;
+
+    let available: String = contract
+        .view("get_available_balance")
+        .args_json(json!({ "account_id": resolver.id(), "token_id": token.id() }))
+        .await?
+        .json()?;
+    assert_eq!(available, "100");
+
+    let state: String = contract
+        .view("get_escrow_state")
+        .args_json(json!({ "hashlock": hashlock }))
+        .await?
+        .json()?;
+    assert_eq!(state, "Settled");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_repeated_settlement_callback_is_a_no_op() -> Result<()> {
+    let (worker, contract, owner) = common::setup().await?;
+    let token = worker.dev_create_account().await?;
+    let maker = worker.dev_create_account().await?;
+    let resolver = worker.dev_create_account().await?;
+
+    let secret = b"settle once";
+    common::create_destination_escrow(
+        &contract,
+        &token,
+        &resolver,
+        json!({
+            "type": "CreateDestinationEscrow",
+            "hashlock": common::hashlock(secret),
+            "maker_id": maker.id(),
+            "timelocks": common::default_timelocks(),
+        }),
+        100,
+        NearToken::from_millinear(100),
+    )
+    .await?
+    .into_result()?;
+
+    // The failed refund is credited to the resolver's ledger exactly once.
+    owner
+        .call(contract.id(), "add_relayer")
+        .args_json(json!({ "account_id": owner.id() }))
+        .transact()
+        .await?
+        .into_result()?;
+    owner
+        .call(contract.id(), "relayer_cancel")
+        .args_json(json!({ "hashlock": common::hashlock(secret) }))
+        .max_gas()
+        .transact()
+        .await?
+        .into_result()?;
+
+    // Replay the callback as the contract itself, the only account allowed to call it.
+    // The escrow is keyed by sha256(hashlock || is_source).
+    let escrow_id: [u8; 32] =
+        sha2::Sha256::digest([&sha2::Sha256::digest(secret)[..], &[0u8]].concat()).into();
+    let outcome = contract
+        .as_account()
+        .call(contract.id(), "on_escrow_settled")
+        .args_json(json!({
+            "escrow_id": escrow_id,
+            "maker_id": maker.id(),
+            "taker_id": resolver.id(),
+            "is_source": false,
+            "is_cancel": true,
+            "caller": owner.id(),
+            "safety_deposit_recipient": resolver.id(),
+            "safety_deposit_reward": "0",
+            "fee": "0",
+            "registration_retry": false,
+        }))
+        .transact()
+        .await?
+        .into_result()?;
+    assert!(outcome
+        .logs()
+        .iter()
+        .any(|log| log.starts_with("ESCROW_ALREADY_SETTLED")));
+
+    let available: String = contract
+        .view("get_available_balance")
+        .args_json(json!({ "account_id": resolver.id(), "token_id": token.id() }))
+        .await?
+        .json()?;
+    assert_eq!(available, "100");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_ft_safety_deposit_is_locked_and_released_through_the_ledger() -> Result<()> {
+    let (worker, contract, owner) = common::setup().await?;
+    let token = worker.dev_create_account().await?;
+    let maker = worker.dev_create_account().await?;
+    let resolver = worker.dev_create_account().await?;
+
+    // The resolver holds only FTs: its safety deposit comes from its internal balance.
+    common::deposit(&contract, &token, &resolver, 50).await?;
+    let hashlock = common::hashlock(b"ft safety deposit");
+    common::create_destination_escrow(
+        &contract,
+        &token,
+        &resolver,
+        json!({
+            "type": "CreateDestinationEscrow",
+            "hashlock": hashlock,
+            "maker_id": maker.id(),
+            "timelocks": common::default_timelocks(),
+            "safety_deposit_amount": "50",
+            "safety_deposit_token": token.id(),
+        }),
+        100,
+        NearToken::from_yoctonear(0),
+    )
+    .await?
+    .into_result()?;
+
+    let available: String = contract
+        .view("get_available_balance")
+        .args_json(json!({ "account_id": resolver.id(), "token_id": token.id() }))
+        .await?
+        .json()?;
+    assert_eq!(available, "0");
+    let pool: NearToken = contract.view("get_safety_deposit_pool").await?.json()?;
+    assert_eq!(pool, NearToken::from_yoctonear(0));
+
+    owner
+        .call(contract.id(), "add_relayer")
+        .args_json(json!({ "account_id": owner.id() }))
+        .transact()
+        .await?
+        .into_result()?;
+    owner
+        .call(contract.id(), "relayer_cancel")
+        .args_json(json!({ "hashlock": hashlock }))
+        .max_gas()
+        .transact()
+        .await?
+        .into_result()?;
+
+    // The safety deposit is unlocked and the failed escrow refund is credited on top of it.
+    let available: String = contract
+        .view("get_available_balance")
+        .args_json(json!({ "account_id": resolver.id(), "token_id": token.id() }))
+        .await?
+        .json()?;
+    assert_eq!(available, "150");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_settle_all_ready_cancels_only_cancellable_escrows() -> Result<()> {
+    let (worker, contract, _owner) = common::setup().await?;
+    let token = worker.dev_create_account().await?;
+    let maker = worker.dev_create_account().await?;
+    let resolver = worker.dev_create_account().await?;
+    let keeper = worker.dev_create_account().await?;
+
+    // Two escrows become cancellable within a second; the third stays in its withdrawal window.
+    let mut expiring = common::default_timelocks();
+    expiring["dst_public_withdrawal_delay"] = json!(0);
+    expiring["dst_cancellation_delay"] = json!(1);
+    for (secret, timelocks) in [
+        (&b"expired one"[..], expiring.clone()),
+        (&b"live"[..], common::default_timelocks()),
+        (&b"expired two"[..], expiring),
+    ] {
+        common::create_destination_escrow(
+            &contract,
+            &token,
+            &resolver,
+            json!({
+                "type": "CreateDestinationEscrow",
+                "hashlock": common::hashlock(secret),
+                "maker_id": maker.id(),
+                "timelocks": timelocks,
+            }),
+            100,
+            NearToken::from_millinear(100),
+        )
+        .await?
+        .into_result()?;
+    }
+    worker.fast_forward(10).await?;
+
+    let receipts: Vec<serde_json::Value> = keeper
+        .call(contract.id(), "settle_all_ready")
+        .args_json(json!({ "from_index": 0, "limit": 20 }))
+        .max_gas()
+        .transact()
+        .await?
+        .into_result()?
+        .json()?;
+    assert_eq!(receipts.len(), 2);
+    assert!(receipts
+        .iter()
+        .all(|receipt| receipt["safety_deposit_returned_to"] == json!(keeper.id())));
+
+    for (secret, expected) in [
+        (&b"expired one"[..], "Settled"),
+        (&b"live"[..], "Withdrawable"),
+        (&b"expired two"[..], "Settled"),
+    ] {
+        let state: String = contract
+            .view("get_escrow_state")
+            .args_json(json!({ "hashlock": common::hashlock(secret) }))
+            .await?
+            .json()?;
+        assert_eq!(state, expected);
+    }
+
+    // Each failed refund fell back to the taker's ledger on its own.
+    let available: String = contract
+        .view("get_available_balance")
+        .args_json(json!({ "account_id": resolver.id(), "token_id": token.id() }))
+        .await?
+        .json()?;
+    assert_eq!(available, "200");
+
+    let outcome = keeper
+        .call(contract.id(), "settle_all_ready")
+        .args_json(json!({ "from_index": 0, "limit": 21 }))
+        .max_gas()
+        .transact()
+        .await?;
+    common::assert_failure_contains(outcome, "Too many escrows in one batch");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_failed_safety_deposit_transfer_is_credited_to_the_ledger() -> Result<()> {
+    let (worker, contract, owner) = common::setup().await?;
+    let token = worker.dev_create_account().await?;
+    let maker = worker.dev_create_account().await?;
+    let resolver = worker.dev_create_account().await?;
+
+    let hashlock = common::hashlock(b"deleted resolver");
+    common::create_destination_escrow(
+        &contract,
+        &token,
+        &resolver,
+        json!({
+            "type": "CreateDestinationEscrow",
+            "hashlock": hashlock,
+            "maker_id": maker.id(),
+            "timelocks": common::default_timelocks(),
+        }),
+        100,
+        NearToken::from_millinear(100),
+    )
+    .await?
+    .into_result()?;
+
+    // Once the resolver's account is gone, the NEAR safety deposit returned to it bounces.
+    resolver
+        .clone()
+        .delete_account(owner.id())
+        .await?
+        .into_result()?;
+    owner
+        .call(contract.id(), "add_relayer")
+        .args_json(json!({ "account_id": owner.id() }))
+        .transact()
+        .await?
+        .into_result()?;
+    let outcome = owner
+        .call(contract.id(), "relayer_cancel")
+        .args_json(json!({ "hashlock": hashlock }))
+        .max_gas()
+        .transact()
+        .await?;
+    assert!(outcome
+        .logs()
+        .iter()
+        .any(|log| log.starts_with("SAFETY_DEPOSIT_CREDITED")));
+
+    // Native NEAR is held on the ledger under the contract's own account id.
+    let available: String = contract
+        .view("get_available_balance")
+        .args_json(json!({ "account_id": resolver.id(), "token_id": contract.id() }))
+        .await?
+        .json()?;
+    assert_eq!(
+        available,
+        NearToken::from_millinear(100).as_yoctonear().to_string()
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_cancellation_penalty_is_split_from_the_refund() -> Result<()> {
+    let (worker, contract, owner) = common::setup().await?;
+    let token = worker.dev_create_account().await?;
+    let maker = worker.dev_create_account().await?;
+    let resolver = worker.dev_create_account().await?;
+    let treasury = worker.dev_create_account().await?;
+
+    owner
+        .call(contract.id(), "add_relayer")
+        .args_json(json!({ "account_id": owner.id() }))
+        .transact()
+        .await?
+        .into_result()?;
+    owner
+        .call(contract.id(), "set_fee_recipient")
+        .args_json(json!({ "fee_recipient": treasury.id() }))
+        .transact()
+        .await?
+        .into_result()?;
+
+    let outcome = owner
+        .call(contract.id(), "set_cancellation_penalty")
+        .args_json(json!({ "penalty_bps": 1_001, "recipient": "Counterparty" }))
+        .transact()
+        .await?;
+    common::assert_failure_contains(outcome, "Cancellation penalty is too high");
+
+    // A destination cancellation refunds the taker; the penalty goes to the maker or the treasury.
+    for (secret, recipient, penalised) in [
+        (&b"to maker"[..], "Counterparty", &maker),
+        (&b"to treasury"[..], "FeeRecipient", &treasury),
+    ] {
+        owner
+            .call(contract.id(), "set_cancellation_penalty")
+            .args_json(json!({ "penalty_bps": 500, "recipient": recipient }))
+            .transact()
+            .await?
+            .into_result()?;
+        common::create_destination_escrow(
+            &contract,
+            &token,
+            &resolver,
+            json!({
+                "type": "CreateDestinationEscrow",
+                "hashlock": common::hashlock(secret),
+                "maker_id": maker.id(),
+                "timelocks": common::default_timelocks(),
+            }),
+            1_000,
+            NearToken::from_millinear(100),
+        )
+        .await?
+        .into_result()?;
+
+        let receipt: serde_json::Value = owner
+            .call(contract.id(), "relayer_cancel")
+            .args_json(json!({ "hashlock": common::hashlock(secret) }))
+            .max_gas()
+            .transact()
+            .await?
+            .into_result()?
+            .json()?;
+        assert_eq!(receipt["amount"], "950");
+
+        let penalty: String = contract
+            .view("get_available_balance")
+            .args_json(json!({ "account_id": penalised.id(), "token_id": token.id() }))
+            .await?
+            .json()?;
+        assert_eq!(penalty, "50");
+    }
+
+    // The failed refunds fell back to the resolver's ledger, net of the penalties.
+    let refunded: String = contract
+        .view("get_available_balance")
+        .args_json(json!({ "account_id": resolver.id(), "token_id": token.id() }))
+        .await?
+        .json()?;
+    assert_eq!(refunded, "1900");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_public_cancel_reward_splits_the_safety_deposit() -> Result<()> {
+    let (worker, contract, owner) = common::setup().await?;
+    let token = worker.dev_create_account().await?;
+    let maker = worker.dev_create_account().await?;
+    let resolver = worker.dev_create_account().await?;
+    let keeper = worker.dev_create_account().await?;
+
+    let outcome = keeper
+        .call(contract.id(), "set_public_cancel_reward_bps")
+        .args_json(json!({ "reward_bps": 2_500 }))
+        .transact()
+        .await?;
+    common::assert_failure_contains(outcome, "Only the owner can call this method");
+    let outcome = owner
+        .call(contract.id(), "set_public_cancel_reward_bps")
+        .args_json(json!({ "reward_bps": 10_001 }))
+        .transact()
+        .await?;
+    common::assert_failure_contains(outcome, "Reward cannot exceed the safety deposit");
+    owner
+        .call(contract.id(), "set_public_cancel_reward_bps")
+        .args_json(json!({ "reward_bps": 2_500 }))
+        .transact()
+        .await?
+        .into_result()?;
+
+    // Two escrows with FT safety deposits of 100 each, cancellable almost at once.
+    common::deposit(&contract, &token, &resolver, 200).await?;
+    let mut timelocks = common::default_timelocks();
+    timelocks["dst_public_withdrawal_delay"] = json!(0);
+    timelocks["dst_cancellation_delay"] = json!(1);
+    for secret in [&b"taker cancels"[..], &b"keeper cancels"[..]] {
+        common::create_destination_escrow(
+            &contract,
+            &token,
+            &resolver,
+            json!({
+                "type": "CreateDestinationEscrow",
+                "hashlock": common::hashlock(secret),
+                "maker_id": maker.id(),
+                "timelocks": timelocks,
+                "safety_deposit_amount": "100",
+                "safety_deposit_token": token.id(),
+            }),
+            100,
+            NearToken::from_yoctonear(0),
+        )
+        .await?
+        .into_result()?;
+    }
+    worker.fast_forward(10).await?;
+
+    let reward: serde_json::Value = contract
+        .view("get_public_action_reward")
+        .args_json(json!({ "hashlock": common::hashlock(b"keeper cancels") }))
+        .await?
+        .json()?;
+    assert_eq!(reward["safety_deposit"], "25");
+
+    let available = |account: &near_workspaces::Account| {
+        contract
+            .view("get_available_balance")
+            .args_json(json!({ "account_id": account.id(), "token_id": token.id() }))
+    };
+    // The taker cancelling gets its whole safety deposit back. The dev-account token cannot
+    // transfer, so each refund of 100 is credited to the taker's ledger as well.
+    resolver
+        .call(contract.id(), "cancel")
+        .args_json(json!({ "hashlock": common::hashlock(b"taker cancels") }))
+        .max_gas()
+        .transact()
+        .await?
+        .into_result()?;
+    let balance: String = available(&resolver).await?.json()?;
+    assert_eq!(balance, "200");
+
+    // A public caller earns a quarter of it and the rest goes back to the taker.
+    let receipt: serde_json::Value = keeper
+        .call(contract.id(), "cancel")
+        .args_json(json!({ "hashlock": common::hashlock(b"keeper cancels") }))
+        .max_gas()
+        .transact()
+        .await?
+        .into_result()?
+        .json()?;
+    assert_eq!(receipt["safety_deposit_returned_to"], json!(keeper.id()));
+    let balance: String = available(&keeper).await?.json()?;
+    assert_eq!(balance, "25");
+    let balance: String = available(&resolver).await?.json()?;
+    assert_eq!(balance, "375");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_concurrent_cancellations_unlock_only_their_own_escrows() -> Result<()> {
+    let (worker, contract, _owner) = common::setup().await?;
+    let token = worker.dev_create_account().await?;
+    let maker = worker.dev_create_account().await?;
+    let resolver = worker.dev_create_account().await?;
+
+    let signer = common::OrderSigner::from_seed(1);
+    maker
+        .call(contract.id(), "register_keys")
+        .args_json(json!({ "public_keys": [signer.public_key()] }))
+        .transact()
+        .await?
+        .into_result()?;
+    common::deposit(&contract, &token, &maker, 300).await?;
+
+    // Three source escrows of 100 each on the same token, publicly cancellable almost at once.
+    let timelocks = json!({
+        "src_withdrawal_delay": 0,
+        "src_public_withdrawal_delay": 0,
+        "src_cancellation_delay": 1,
+        "src_public_cancellation_delay": 1,
+        "dst_withdrawal_delay": 0,
+        "dst_public_withdrawal_delay": 0,
+        "dst_cancellation_delay": 1,
+    });
+    let secrets = [&b"first"[..], &b"second"[..], &b"third"[..]];
+    for (nonce, secret) in secrets.iter().enumerate() {
+        let order = json!({
+            "nonce": nonce + 1,
+            "maker_id": maker.id(),
+            "asset_id": token.id(),
+            "amount": "100",
+            "hashlock": common::hashlock(secret),
+            "timelocks": timelocks,
+            "deadline": u64::MAX,
+        });
+        resolver
+            .call(contract.id(), "initiate_source_escrow")
+            .args_json(json!({
+                "params": order,
+                "signature": signer.sign_order(&contract, &order).await?,
+                "public_key": signer.public_key(),
+            }))
+            .deposit(NearToken::from_millinear(100))
+            .max_gas()
+            .transact()
+            .await?
+            .into_result()?;
+    }
+    worker.fast_forward(10).await?;
+
+    let balances = || {
+        contract
+            .view("get_token_balances")
+            .args_json(json!({ "token_id": token.id(), "from_index": 0, "limit": 10 }))
+    };
+    let balance: serde_json::Value = balances().await?.json()?;
+    assert_eq!(balance, json!([[maker.id(), "300", "300"]]));
+
+    // Two keepers cancel two of the escrows at the same time.
+    let first_keeper = worker.dev_create_account().await?;
+    let second_keeper = worker.dev_create_account().await?;
+    let cancel = |keeper: &near_workspaces::Account, secret: &[u8]| {
+        keeper
+            .call(contract.id(), "cancel")
+            .args_json(json!({ "hashlock": common::hashlock(secret) }))
+            .max_gas()
+            .transact()
+    };
+    let (first, second) = tokio::join!(
+        cancel(&first_keeper, secrets[0]),
+        cancel(&second_keeper, secrets[1])
+    );
+    for outcome in [first?, second?] {
+        assert!(!outcome
+            .logs()
+            .iter()
+            .any(|log| log.starts_with("LOCKED_BALANCE_UNDERFLOW")));
+        outcome.into_result()?;
+    }
+    let balance: serde_json::Value = balances().await?.json()?;
+    assert_eq!(balance, json!([[maker.id(), "300", "100"]]));
+
+    cancel(&first_keeper, secrets[2]).await?.into_result()?;
+    let balance: serde_json::Value = balances().await?.json()?;
+    assert_eq!(balance, json!([[maker.id(), "300", "0"]]));
+
+    Ok(())
+}
@@ -0,0 +1,639 @@
+mod common;
+
+use anyhow::Result;
+use near_workspaces::types::NearToken;
+use serde_json::json;
+
+#[tokio::test]
+async fn test_deposit_lifetime_stats_track_deposits_and_withdrawals() -> Result<()> {
+    let (worker, contract, _owner) = common::setup().await?;
+    let token = worker.dev_create_account().await?;
+    let user = worker.dev_create_account().await?;
+
+    common::deposit(&contract, &token, &user, 100).await?;
+    common::deposit(&contract, &token, &user, 50).await?;
+
+    let stats: serde_json::Value = contract
+        .view("get_deposit_lifetime_stats")
+        .args_json(json!({ "account_id": user.id(), "token_id": token.id() }))
+        .await?
+        .json()?;
+    assert_eq!(stats["total_deposited"], "150");
+    assert_eq!(stats["total_withdrawn"], "0");
+
+    // The token account is not a real FT, so the outgoing transfer fails and must not count.
+    user.call(contract.id(), "withdraw_deposit")
+        .args_json(json!({ "token_id": token.id(), "amount": "40" }))
+        .max_gas()
+        .transact()
+        .await?;
+
+    let stats: serde_json::Value = contract
+        .view("get_deposit_lifetime_stats")
+        .args_json(json!({ "account_id": user.id(), "token_id": token.id() }))
+        .await?
+        .json()?;
+    assert_eq!(stats["total_deposited"], "150");
+    assert_eq!(stats["total_withdrawn"], "0");
+
+    let available: String = contract
+        .view("get_available_balance")
+        .args_json(json!({ "account_id": user.id(), "token_id": token.id() }))
+        .await?
+        .json()?;
+    assert_eq!(available, "150");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_deposit_lifetime_stats_count_a_successful_withdrawal() -> Result<()> {
+    let (worker, contract, owner) = common::setup().await?;
+    let user = worker.dev_create_account().await?;
+    let ft = common::setup_ft(&worker, &owner, &[contract.id(), user.id()]).await?;
+    common::ft_transfer(&ft, &owner, user.id(), 100).await?;
+    user.call(ft.id(), "ft_transfer_call")
+        .args_json(json!({
+            "receiver_id": contract.id(),
+            "amount": "100",
+            "msg": json!({ "type": "Deposit" }).to_string(),
+        }))
+        .deposit(NearToken::from_yoctonear(1))
+        .max_gas()
+        .transact()
+        .await?
+        .into_result()?;
+    user.call(contract.id(), "withdraw_deposit")
+        .args_json(json!({ "token_id": ft.id(), "amount": "40" }))
+        .max_gas()
+        .transact()
+        .await?
+        .into_result()?;
+    assert_eq!(common::ft_balance_of(&ft, user.id()).await?, 40);
+
+    let stats: serde_json::Value = contract
+        .view("get_deposit_lifetime_stats")
+        .args_json(json!({ "account_id": user.id(), "token_id": ft.id() }))
+        .await?
+        .json()?;
+    assert_eq!(stats["total_deposited"], "100");
+    assert_eq!(stats["total_withdrawn"], "40");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_token_balances_aggregate_across_accounts() -> Result<()> {
+    let (worker, contract, _owner) = common::setup().await?;
+    let token = worker.dev_create_account().await?;
+    let other_token = worker.dev_create_account().await?;
+
+    let mut users = Vec::new();
+    for amount in [100, 200, 300] {
+        let user = worker.dev_create_account().await?;
+        common::deposit(&contract, &token, &user, amount).await?;
+        users.push(user);
+    }
+    let outsider = worker.dev_create_account().await?;
+    common::deposit(&contract, &other_token, &outsider, 999).await?;
+
+    let first_page: Vec<(String, String, String)> = contract
+        .view("get_token_balances")
+        .args_json(json!({ "token_id": token.id(), "from_index": 0, "limit": 2 }))
+        .await?
+        .json()?;
+    let second_page: Vec<(String, String, String)> = contract
+        .view("get_token_balances")
+        .args_json(json!({ "token_id": token.id(), "from_index": 2, "limit": 2 }))
+        .await?
+        .json()?;
+    assert_eq!(first_page.len(), 2);
+    assert_eq!(second_page.len(), 1);
+
+    let balances: Vec<_> = first_page.into_iter().chain(second_page).collect();
+    for (user, expected) in users.iter().zip(["100", "200", "300"]) {
+        let (_, total, locked) = balances
+            .iter()
+            .find(|(account_id, _, _)| account_id == user.id().as_str())
+            .expect("holder missing from token balances");
+        assert_eq!(total, expected);
+        assert_eq!(locked, "0");
+    }
+    let sum: u128 = balances
+        .iter()
+        .map(|(_, total, _)| total.parse::<u128>().unwrap())
+        .sum();
+    assert_eq!(sum, 600);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_deposits_of_two_accounts_in_one_token_stay_separate() -> Result<()> {
+    let (worker, contract, _owner) = common::setup().await?;
+    let token = worker.dev_create_account().await?;
+    let alice = worker.dev_create_account().await?;
+    let bob = worker.dev_create_account().await?;
+
+    common::deposit(&contract, &token, &alice, 100).await?;
+    common::deposit(&contract, &token, &bob, 250).await?;
+    common::deposit(&contract, &token, &alice, 5).await?;
+
+    for (user, expected) in [(&alice, "105"), (&bob, "250")] {
+        let available: String = contract
+            .view("get_available_balance")
+            .args_json(json!({ "account_id": user.id(), "token_id": token.id() }))
+            .await?
+            .json()?;
+        assert_eq!(available, expected);
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_native_near_deposit_and_withdrawal() -> Result<()> {
+    let (worker, contract, _owner) = common::setup().await?;
+    let user = worker.dev_create_account().await?;
+
+    let native: String = contract.view("get_native_token_id").await?.json()?;
+    assert_eq!(native, contract.id().as_str());
+
+    user.call(contract.id(), "deposit_near")
+        .deposit(NearToken::from_near(2))
+        .transact()
+        .await?
+        .into_result()?;
+    let available: String = contract
+        .view("get_available_balance")
+        .args_json(json!({ "account_id": user.id(), "token_id": native }))
+        .await?
+        .json()?;
+    assert_eq!(
+        available,
+        NearToken::from_near(2).as_yoctonear().to_string()
+    );
+
+    let before = user.view_account().await?.balance;
+    user.call(contract.id(), "withdraw_deposit")
+        .args_json(json!({
+            "token_id": native,
+            "amount": NearToken::from_near(1).as_yoctonear().to_string(),
+        }))
+        .max_gas()
+        .transact()
+        .await?
+        .into_result()?;
+    let after = user.view_account().await?.balance;
+    // The withdrawn NEAR arrives, less the gas the withdrawal itself burned.
+    assert!(after > before.saturating_add(NearToken::from_millinear(900)));
+
+    let stats: serde_json::Value = contract
+        .view("get_deposit_lifetime_stats")
+        .args_json(json!({ "account_id": user.id(), "token_id": native }))
+        .await?
+        .json()?;
+    assert_eq!(
+        stats["total_deposited"],
+        NearToken::from_near(2).as_yoctonear().to_string()
+    );
+    assert_eq!(
+        stats["total_withdrawn"],
+        NearToken::from_near(1).as_yoctonear().to_string()
+    );
+
+    let outcome = user.call(contract.id(), "deposit_near").transact().await?;
+    common::assert_failure_contains(outcome, "Attach NEAR to deposit");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_tvl_tracks_a_swap_lifecycle() -> Result<()> {
+    let (worker, contract, owner) = common::setup().await?;
+    let token = worker.dev_create_account().await?;
+    let maker = worker.dev_create_account().await?;
+    let resolver = worker.dev_create_account().await?;
+
+    let tvl = |token_id: String| {
+        let contract = contract.clone();
+        async move {
+            let tvl: String = contract
+                .view("get_tvl")
+                .args_json(json!({ "token_id": token_id }))
+                .await?
+                .json()?;
+            Ok::<_, anyhow::Error>(tvl)
+        }
+    };
+
+    common::deposit(&contract, &token, &maker, 1_000).await?;
+    assert_eq!(tvl(token.id().to_string()).await?, "1000");
+
+    let secret = b"tvl";
+    common::create_destination_escrow(
+        &contract,
+        &token,
+        &resolver,
+        json!({
+            "type": "CreateDestinationEscrow",
+            "hashlock": common::hashlock(secret),
+            "maker_id": maker.id(),
+            "timelocks": common::default_timelocks(),
+        }),
+        100,
+        NearToken::from_millinear(100),
+    )
+    .await?
+    .into_result()?;
+    assert_eq!(tvl(token.id().to_string()).await?, "1100");
+
+    // The payout to the maker fails against the stand-in token and the claim is reverted.
+    resolver
+        .call(contract.id(), "withdraw")
+        .args_json(json!({
+            "secret": base64::Engine::encode(&base64::engine::general_purpose::STANDARD, secret),
+        }))
+        .max_gas()
+        .transact()
+        .await?;
+    assert_eq!(tvl(token.id().to_string()).await?, "1100");
+
+    // A failed ledger withdrawal is credited back.
+    maker
+        .call(contract.id(), "withdraw_deposit")
+        .args_json(json!({ "token_id": token.id(), "amount": "400" }))
+        .max_gas()
+        .transact()
+        .await?;
+    assert_eq!(tvl(token.id().to_string()).await?, "1100");
+
+    // Cancelling moves the escrowed tokens onto the resolver's ledger when the refund fails.
+    owner
+        .call(contract.id(), "add_relayer")
+        .args_json(json!({ "account_id": owner.id() }))
+        .transact()
+        .await?
+        .into_result()?;
+    owner
+        .call(contract.id(), "relayer_cancel")
+        .args_json(json!({ "hashlock": common::hashlock(secret) }))
+        .max_gas()
+        .transact()
+        .await?
+        .into_result()?;
+    assert_eq!(tvl(token.id().to_string()).await?, "1100");
+
+    // Native NEAR actually leaves the contract on withdrawal.
+    maker
+        .call(contract.id(), "deposit_near")
+        .deposit(NearToken::from_near(2))
+        .transact()
+        .await?
+        .into_result()?;
+    maker
+        .call(contract.id(), "withdraw_deposit")
+        .args_json(json!({
+            "token_id": contract.id(),
+            "amount": NearToken::from_near(1).as_yoctonear().to_string(),
+        }))
+        .max_gas()
+        .transact()
+        .await?
+        .into_result()?;
+    assert_eq!(
+        tvl(contract.id().to_string()).await?,
+        NearToken::from_near(1).as_yoctonear().to_string()
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_audit_sweep_flags_a_drifted_locked_balance() -> Result<()> {
+    let (worker, contract, owner) = common::setup().await?;
+    let token = worker.dev_create_account().await?;
+    let maker = worker.dev_create_account().await?;
+    let resolver = worker.dev_create_account().await?;
+
+    let signer = common::OrderSigner::from_seed(1);
+    maker
+        .call(contract.id(), "register_keys")
+        .args_json(json!({ "public_keys": [signer.public_key()] }))
+        .transact()
+        .await?
+        .into_result()?;
+    common::deposit(&contract, &token, &maker, 100).await?;
+    let order = json!({
+        "nonce": 1,
+        "maker_id": maker.id(),
+        "asset_id": token.id(),
+        "amount": "100",
+        "hashlock": common::hashlock(b"audited"),
+        "timelocks": common::default_timelocks(),
+        "deadline": u64::MAX,
+    });
+    resolver
+        .call(contract.id(), "initiate_source_escrow")
+        .args_json(json!({
+            "params": order,
+            "signature": signer.sign_order(&contract, &order).await?,
+            "public_key": signer.public_key(),
+        }))
+        .deposit(NearToken::from_millinear(100))
+        .max_gas()
+        .transact()
+        .await?
+        .into_result()?;
+
+    let audit = || {
+        owner
+            .call(contract.id(), "audit_sweep")
+            .args_json(json!({ "from_index": 0, "limit": 10 }))
+            .max_gas()
+    };
+    let report: serde_json::Value = audit().transact().await?.into_result()?.json()?;
+    assert_eq!(report, json!({ "scanned": 1, "violations": [] }));
+
+    // Overwrite the maker's locked balance of the token, stored under its per-account prefix,
+    // so it no longer covers the escrow.
+    let prefix = {
+        use sha2::{Digest, Sha256};
+        [&b"X"[..], &Sha256::digest(maker.id().as_bytes())[..]].concat()
+    };
+    let (key, mut value) = contract
+        .view_state()
+        .await?
+        .into_iter()
+        .find(|(key, value)| {
+            key.starts_with(&prefix) && value.len() > 16 && value[..16] == 100u128.to_le_bytes()
+        })
+        .expect("locked balance entry");
+    value[..16].copy_from_slice(&40u128.to_le_bytes());
+    worker
+        .patch(contract.id())
+        .state(&key, &value)
+        .transact()
+        .await?;
+
+    let report: serde_json::Value = audit().transact().await?.into_result()?.json()?;
+    assert_eq!(
+        report,
+        json!({
+            "scanned": 1,
+            "violations": [{
+                "LockedBalanceMismatch": {
+                    "account_id": maker.id(),
+                    "token_id": token.id(),
+                    "locked": "40",
+                    "expected": "100",
+                },
+            }],
+        })
+    );
+
+    // The sweep only reports; the drift is still there for `reconcile` to repair.
+    let balances: serde_json::Value = contract
+        .view("get_token_balances")
+        .args_json(json!({ "token_id": token.id(), "from_index": 0, "limit": 10 }))
+        .await?
+        .json()?;
+    assert_eq!(balances, json!([[maker.id(), "100", "40"]]));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_reconcile_locked_frees_a_phantom_lock() -> Result<()> {
+    let (worker, contract, _owner) = common::setup().await?;
+    let token = worker.dev_create_account().await?;
+    let maker = worker.dev_create_account().await?;
+    let resolver = worker.dev_create_account().await?;
+
+    let signer = common::OrderSigner::from_seed(1);
+    maker
+        .call(contract.id(), "register_keys")
+        .args_json(json!({ "public_keys": [signer.public_key()] }))
+        .transact()
+        .await?
+        .into_result()?;
+    common::deposit(&contract, &token, &maker, 100).await?;
+    let order = json!({
+        "nonce": 1,
+        "maker_id": maker.id(),
+        "asset_id": token.id(),
+        "amount": "40",
+        "hashlock": common::hashlock(b"phantom"),
+        "timelocks": common::default_timelocks(),
+        "deadline": u64::MAX,
+    });
+    resolver
+        .call(contract.id(), "initiate_source_escrow")
+        .args_json(json!({
+            "params": order,
+            "signature": signer.sign_order(&contract, &order).await?,
+            "public_key": signer.public_key(),
+        }))
+        .deposit(NearToken::from_millinear(100))
+        .max_gas()
+        .transact()
+        .await?
+        .into_result()?;
+
+    // Lock the rest of the maker's balance without any escrow behind it.
+    let prefix = {
+        use sha2::{Digest, Sha256};
+        [&b"X"[..], &Sha256::digest(maker.id().as_bytes())[..]].concat()
+    };
+    let (key, mut value) = contract
+        .view_state()
+        .await?
+        .into_iter()
+        .find(|(key, value)| {
+            key.starts_with(&prefix) && value.len() > 16 && value[..16] == 40u128.to_le_bytes()
+        })
+        .expect("locked balance entry");
+    value[..16].copy_from_slice(&100u128.to_le_bytes());
+    worker
+        .patch(contract.id())
+        .state(&key, &value)
+        .transact()
+        .await?;
+    let available = || {
+        contract
+            .view("get_available_balance")
+            .args_json(json!({ "account_id": maker.id(), "token_id": token.id() }))
+    };
+    assert_eq!(available().await?.json::<String>()?, "0");
+
+    let reconcile = |caller: &near_workspaces::Account| {
+        caller
+            .call(contract.id(), "reconcile_locked")
+            .args_json(json!({ "account_id": maker.id(), "token_id": token.id() }))
+            .max_gas()
+    };
+    let outcome = reconcile(&resolver).transact().await?;
+    common::assert_failure_contains(outcome, "Only the owner or the account itself");
+
+    // Only the escrow's 40 stays locked; reconciling again changes nothing.
+    let adjusted: bool = reconcile(&maker).transact().await?.into_result()?.json()?;
+    assert!(adjusted);
+    assert_eq!(available().await?.json::<String>()?, "60");
+    let adjusted: bool = reconcile(&maker).transact().await?.into_result()?.json()?;
+    assert!(!adjusted);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_emergency_sweep_requires_a_year_of_dormancy() -> Result<()> {
+    use sha2::{Digest, Sha256};
+    let (worker, contract, owner) = common::setup().await?;
+    let token = worker.dev_create_account().await?;
+    let user = worker.dev_create_account().await?;
+    let maker = worker.dev_create_account().await?;
+    let resolver = worker.dev_create_account().await?;
+
+    common::deposit(&contract, &token, &user, 100).await?;
+    let secret = b"abandoned";
+    common::create_destination_escrow(
+        &contract,
+        &token,
+        &resolver,
+        json!({
+            "type": "CreateDestinationEscrow",
+            "hashlock": common::hashlock(secret),
+            "maker_id": maker.id(),
+            "timelocks": common::default_timelocks(),
+        }),
+        50,
+        NearToken::from_millinear(100),
+    )
+    .await?
+    .into_result()?;
+
+    let targets = [
+        json!({ "Deposit": { "account_id": user.id(), "token_id": token.id() } }),
+        json!({ "Escrow": { "hashlock": common::hashlock(secret) } }),
+    ];
+    let sweep = |target: &serde_json::Value| {
+        owner
+            .call(contract.id(), "emergency_withdraw_to_owner")
+            .args_json(json!({ "target": target }))
+            .max_gas()
+    };
+    for target in &targets {
+        let outcome = sweep(target).transact().await?;
+        common::assert_failure_contains(outcome, "not been dormant long enough");
+    }
+    let outcome = user
+        .call(contract.id(), "emergency_withdraw_to_owner")
+        .args_json(json!({ "target": targets[0] }))
+        .transact()
+        .await?;
+    assert!(outcome.is_failure());
+
+    // Backdate both activity timestamps to the epoch. The account's is the first field of its
+    // `last_activity` entry; the escrow's is the last field of the escrow, before the entry index.
+    let account_key = [
+        &b"im"[..],
+        &(user.id().len() as u32).to_le_bytes(),
+        user.id().as_bytes(),
+    ]
+    .concat();
+    let escrow_id = Sha256::digest([&Sha256::digest(secret)[..], &[0u8]].concat());
+    let escrow_key = [&b"em"[..], &escrow_id[..]].concat();
+    let state = contract.view_state().await?;
+    let mut account_value = state[&account_key].clone();
+    account_value[..8].copy_from_slice(&0u64.to_le_bytes());
+    let mut escrow_value = state[&escrow_key].clone();
+    let end = escrow_value.len() - 4;
+    escrow_value[end - 8..end].copy_from_slice(&0u64.to_le_bytes());
+    worker
+        .patch(contract.id())
+        .state(&account_key, &account_value)
+        .state(&escrow_key, &escrow_value)
+        .transact()
+        .await?;
+
+    for target in &targets {
+        sweep(target).transact().await?.into_result()?;
+    }
+
+    let available = |account_id: near_workspaces::AccountId,
+                     token_id: near_workspaces::AccountId| {
+        contract
+            .view("get_available_balance")
+            .args_json(json!({ "account_id": account_id, "token_id": token_id }))
+    };
+    let user_balance: String = available(user.id().clone(), token.id().clone())
+        .await?
+        .json()?;
+    assert_eq!(user_balance, "0");
+    let owner_balance: String = available(owner.id().clone(), token.id().clone())
+        .await?
+        .json()?;
+    assert_eq!(owner_balance, "150");
+    let owner_native: String = available(owner.id().clone(), contract.id().clone())
+        .await?
+        .json()?;
+    assert_eq!(
+        owner_native,
+        NearToken::from_millinear(100).as_yoctonear().to_string()
+    );
+    let escrow: serde_json::Value = contract
+        .view("get_escrow")
+        .args_json(json!({ "hashlock": common::hashlock(secret) }))
+        .await?
+        .json()?;
+    assert_eq!(escrow["claimed"], json!(true));
+    assert_eq!(escrow["settled"], json!(true));
+    let settlement: serde_json::Value = contract
+        .view("get_settlement")
+        .args_json(json!({ "hashlock": common::hashlock(secret) }))
+        .await?
+        .json()?;
+    assert_eq!(settlement["claimant"], json!(owner.id()));
+    assert_eq!(settlement["was_cancel"], json!(true));
+    let stats: serde_json::Value = contract
+        .view("get_resolver_stats")
+        .args_json(json!({ "account_id": resolver.id() }))
+        .await?
+        .json()?;
+    assert_eq!(stats["cancelled"], 1);
+
+    // Swept escrows cannot be swept again.
+    let outcome = sweep(&targets[1]).transact().await?;
+    common::assert_failure_contains(outcome, "Escrow already claimed");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_users_with_deposits_are_paginated() -> Result<()> {
+    let (worker, contract, _owner) = common::setup().await?;
+    let token = worker.dev_create_account().await?;
+    let mut users = Vec::new();
+    for _ in 0..7 {
+        let user = worker.dev_create_account().await?;
+        common::deposit(&contract, &token, &user, 10).await?;
+        users.push(user.id().to_string());
+    }
+
+    let mut listed: Vec<String> = Vec::new();
+    for from_index in (0..9).step_by(3) {
+        let page: Vec<String> = contract
+            .view("get_users_with_deposits")
+            .args_json(json!({ "from_index": from_index, "limit": 3 }))
+            .await?
+            .json()?;
+        assert!(page.len() <= 3);
+        assert!(page.iter().all(|account| !listed.contains(account)));
+        listed.extend(page);
+    }
+    listed.sort();
+    users.sort();
+    assert_eq!(listed, users);
+
+    Ok(())
+}
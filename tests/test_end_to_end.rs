@@ -0,0 +1,747 @@
+//! Full swaps against the mock NEP-141 token from `tests/mock-ft`, so every FT transfer is real.
+mod common;
+
+use anyhow::Result;
+use base64::Engine;
+use near_workspaces::types::NearToken;
+use near_workspaces::{Account, Contract};
+use serde_json::json;
+
+/// Deploys the token, funds the maker and the resolver with 1000 each and registers the
+/// contract with the token so it can hold balances.
+async fn setup_swap() -> Result<(
+    near_workspaces::Worker<near_workspaces::network::Sandbox>,
+    Contract,
+    Contract,
+    Account,
+    Account,
+)> {
+    let (worker, contract, owner) = common::setup().await?;
+    let maker = worker.dev_create_account().await?;
+    let resolver = worker.dev_create_account().await?;
+    let ft = common::setup_ft(&worker, &owner, &[contract.id(), maker.id(), resolver.id()]).await?;
+    common::ft_transfer(&ft, &owner, maker.id(), 1_000).await?;
+    common::ft_transfer(&ft, &owner, resolver.id(), 1_000).await?;
+    Ok((worker, contract, ft, maker, resolver))
+}
+
+/// Moves `amount` of the token onto `account`'s internal ledger through `ft_transfer_call`.
+async fn deposit(
+    contract: &Contract,
+    ft: &Contract,
+    account: &Account,
+    amount: u128,
+) -> Result<()> {
+    account
+        .call(ft.id(), "ft_transfer_call")
+        .args_json(json!({
+            "receiver_id": contract.id(),
+            "amount": amount.to_string(),
+            "msg": json!({ "type": "Deposit" }).to_string(),
+        }))
+        .deposit(NearToken::from_yoctonear(1))
+        .max_gas()
+        .transact()
+        .await?
+        .into_result()?;
+    Ok(())
+}
+
+/// Has the maker sign an order for `amount` of the token with its own account key and the
+/// resolver fill it.
+async fn initiate_source_escrow(
+    contract: &Contract,
+    ft: &Contract,
+    maker: &Account,
+    resolver: &Account,
+    secret: &[u8],
+    timelocks: serde_json::Value,
+) -> Result<()> {
+    let signer = common::OrderSigner::from_account(maker);
+    maker
+        .call(contract.id(), "register_keys")
+        .args_json(json!({ "public_keys": [signer.public_key()] }))
+        .transact()
+        .await?
+        .into_result()?;
+    deposit(contract, ft, maker, 100).await?;
+
+    let order = json!({
+        "nonce": 1,
+        "maker_id": maker.id(),
+        "asset_id": ft.id(),
+        "amount": "100",
+        "hashlock": common::hashlock(secret),
+        "timelocks": timelocks,
+        "deadline": u64::MAX,
+    });
+    let signature = common::sign_order(contract, maker, &order).await?;
+    resolver
+        .call(contract.id(), "initiate_source_escrow")
+        .args_json(json!({
+            "params": order,
+            "signature": signature,
+            "public_key": signer.public_key(),
+        }))
+        .deposit(NearToken::from_millinear(100))
+        .max_gas()
+        .transact()
+        .await?
+        .into_result()?;
+    Ok(())
+}
+
+/// Has the resolver lock 50 of the token for the maker through `ft_transfer_call`, with a
+/// safety deposit of 10 from its internal ledger.
+async fn create_destination_escrow(
+    contract: &Contract,
+    ft: &Contract,
+    maker: &Account,
+    resolver: &Account,
+    secret: &[u8],
+    timelocks: serde_json::Value,
+    payout_authorization: Option<serde_json::Value>,
+) -> Result<()> {
+    // The safety deposit is posted in the token, since `ft_transfer_call` only forwards one yoctoNEAR.
+    deposit(contract, ft, resolver, 10).await?;
+    resolver
+        .call(ft.id(), "ft_transfer_call")
+        .args_json(json!({
+            "receiver_id": contract.id(),
+            "amount": "50",
+            "msg": json!({
+                "type": "CreateDestinationEscrow",
+                "hashlock": common::hashlock(secret),
+                "maker_id": maker.id(),
+                "timelocks": timelocks,
+                "safety_deposit_amount": "10",
+                "safety_deposit_token": ft.id(),
+                "payout_authorization": payout_authorization,
+            })
+            .to_string(),
+        }))
+        .deposit(NearToken::from_yoctonear(1))
+        .max_gas()
+        .transact()
+        .await?
+        .into_result()?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_full_source_escrow_flow() -> Result<()> {
+    let (_worker, contract, ft, maker, resolver) = setup_swap().await?;
+    let secret = b"my super secret string";
+    initiate_source_escrow(
+        &contract,
+        &ft,
+        &maker,
+        &resolver,
+        secret,
+        common::default_timelocks(),
+    )
+    .await?;
+    assert_eq!(common::ft_balance_of(&ft, contract.id()).await?, 100);
+
+    // The resolver reveals the secret and receives the maker's tokens.
+    resolver
+        .call(contract.id(), "withdraw")
+        .args_json(json!({ "secret": base64::engine::general_purpose::STANDARD.encode(secret) }))
+        .max_gas()
+        .transact()
+        .await?
+        .into_result()?;
+
+    assert_eq!(common::ft_balance_of(&ft, resolver.id()).await?, 1_100);
+    assert_eq!(common::ft_balance_of(&ft, contract.id()).await?, 0);
+    let tvl: String = contract
+        .view("get_tvl")
+        .args_json(json!({ "token_id": ft.id() }))
+        .await?
+        .json()?;
+    assert_eq!(tvl, "0");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_full_destination_escrow_flow() -> Result<()> {
+    let (_worker, contract, ft, maker, resolver) = setup_swap().await?;
+    let secret = b"another secret for destination";
+
+    create_destination_escrow(
+        &contract,
+        &ft,
+        &maker,
+        &resolver,
+        secret,
+        common::default_timelocks(),
+        None,
+    )
+    .await?;
+    assert_eq!(common::ft_balance_of(&ft, contract.id()).await?, 60);
+
+    // The resolver reveals the secret in its private window, paying out to the maker.
+    resolver
+        .call(contract.id(), "withdraw")
+        .args_json(json!({ "secret": base64::engine::general_purpose::STANDARD.encode(secret) }))
+        .max_gas()
+        .transact()
+        .await?
+        .into_result()?;
+
+    assert_eq!(common::ft_balance_of(&ft, maker.id()).await?, 1_050);
+    // The resolver settled the escrow itself, so its safety deposit is unlocked on its ledger.
+    let available: String = contract
+        .view("get_available_balance")
+        .args_json(json!({ "account_id": resolver.id(), "token_id": ft.id() }))
+        .await?
+        .json()?;
+    assert_eq!(available, "10");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_source_escrow_cancellation() -> Result<()> {
+    let (worker, contract, ft, maker, resolver) = setup_swap().await?;
+    let secret = b"cancellable";
+    initiate_source_escrow(
+        &contract,
+        &ft,
+        &maker,
+        &resolver,
+        secret,
+        json!({
+            "src_withdrawal_delay": 0,
+            "src_public_withdrawal_delay": 1,
+            "src_cancellation_delay": 2,
+            "src_public_cancellation_delay": 3,
+            "dst_withdrawal_delay": 0,
+            "dst_public_withdrawal_delay": 1,
+            "dst_cancellation_delay": 2,
+        }),
+    )
+    .await?;
+    worker.fast_forward(20).await?;
+
+    // The taker cancels; the tokens never left the contract and are unlocked on the maker's ledger.
+    resolver
+        .call(contract.id(), "cancel")
+        .args_json(json!({ "hashlock": common::hashlock(secret) }))
+        .max_gas()
+        .transact()
+        .await?
+        .into_result()?;
+
+    let available: String = contract
+        .view("get_available_balance")
+        .args_json(json!({ "account_id": maker.id(), "token_id": ft.id() }))
+        .await?
+        .json()?;
+    assert_eq!(available, "100");
+    assert_eq!(common::ft_balance_of(&ft, contract.id()).await?, 100);
+    assert_eq!(common::ft_balance_of(&ft, maker.id()).await?, 900);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_cancel_and_withdraw_leaves_the_penalty_on_the_ledger() -> Result<()> {
+    let (worker, contract, owner) = common::setup().await?;
+    let maker = worker.dev_create_account().await?;
+    let resolver = worker.dev_create_account().await?;
+    let ft = common::setup_ft(&worker, &owner, &[contract.id(), maker.id(), resolver.id()]).await?;
+    common::ft_transfer(&ft, &owner, maker.id(), 1_000).await?;
+    owner
+        .call(contract.id(), "set_cancellation_penalty")
+        .args_json(json!({ "penalty_bps": 500, "recipient": "Counterparty" }))
+        .transact()
+        .await?
+        .into_result()?;
+
+    let secret = b"cancel and withdraw";
+    initiate_source_escrow(
+        &contract,
+        &ft,
+        &maker,
+        &resolver,
+        secret,
+        json!({
+            "src_withdrawal_delay": 0,
+            "src_public_withdrawal_delay": 1,
+            "src_cancellation_delay": 2,
+            "src_public_cancellation_delay": 3,
+            "dst_withdrawal_delay": 0,
+            "dst_public_withdrawal_delay": 1,
+            "dst_cancellation_delay": 2,
+        }),
+    )
+    .await?;
+    worker.fast_forward(20).await?;
+
+    let outcome = maker
+        .call(contract.id(), "cancel_and_withdraw_deposit")
+        .args_json(json!({ "hashlock": common::hashlock(secret) }))
+        .max_gas()
+        .transact()
+        .await?
+        .into_result()?;
+    assert!(outcome
+        .logs()
+        .iter()
+        .any(|log| log.starts_with("ESCROW_SETTLED")));
+
+    // The maker receives the refund less the 5% penalty, which is credited to the taker.
+    assert_eq!(common::ft_balance_of(&ft, maker.id()).await?, 995);
+    assert_eq!(common::ft_balance_of(&ft, contract.id()).await?, 5);
+    for (account, expected) in [(&maker, "0"), (&resolver, "5")] {
+        let available: String = contract
+            .view("get_available_balance")
+            .args_json(json!({ "account_id": account.id(), "token_id": ft.id() }))
+            .await?
+            .json()?;
+        assert_eq!(available, expected);
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_escrow_asset_info_matches_the_token_balance() -> Result<()> {
+    let (_worker, contract, ft, maker, resolver) = setup_swap().await?;
+    let secret = b"audited";
+    create_destination_escrow(
+        &contract,
+        &ft,
+        &maker,
+        &resolver,
+        secret,
+        common::default_timelocks(),
+        None,
+    )
+    .await?;
+
+    let info: serde_json::Value = resolver
+        .call(contract.id(), "get_escrow_asset_info")
+        .args_json(json!({ "hashlock": common::hashlock(secret) }))
+        .max_gas()
+        .transact()
+        .await?
+        .into_result()?
+        .json()?;
+    let balance = common::ft_balance_of(&ft, contract.id()).await?;
+    assert_eq!(balance, 60);
+    assert_eq!(
+        info,
+        json!({
+            "asset": { "Ft": ft.id() },
+            "amount": "50",
+            "contract_balance": balance.to_string(),
+            "tracked_balance": balance.to_string(),
+        })
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_retry_settlement_completes_a_failed_withdrawal() -> Result<()> {
+    let (worker, contract, ft, _maker, resolver) = setup_swap().await?;
+    let keeper = worker.dev_create_account().await?;
+    // Not registered with the token, so the first payout to this maker fails.
+    let maker = worker.dev_create_account().await?;
+    let secret = b"retry me";
+    create_destination_escrow(
+        &contract,
+        &ft,
+        &maker,
+        &resolver,
+        secret,
+        common::default_timelocks(),
+        None,
+    )
+    .await?;
+
+    // Nothing has been revealed yet, so there is nothing to retry.
+    let outcome = keeper
+        .call(contract.id(), "retry_settlement")
+        .args_json(json!({ "hashlock": common::hashlock(secret) }))
+        .max_gas()
+        .transact()
+        .await?;
+    common::assert_failure_contains(outcome, "has not been revealed");
+
+    let outcome = resolver
+        .call(contract.id(), "withdraw")
+        .args_json(json!({ "secret": base64::engine::general_purpose::STANDARD.encode(secret) }))
+        .max_gas()
+        .transact()
+        .await?;
+    assert!(outcome
+        .logs()
+        .iter()
+        .any(|log| log.starts_with("ESCROW_SETTLEMENT_FAILED")));
+
+    // Once the maker can receive the token, anyone can finish the payout without the secret.
+    keeper
+        .call(ft.id(), "storage_deposit")
+        .args_json(json!({ "account_id": maker.id() }))
+        .deposit(NearToken::from_millinear(10))
+        .transact()
+        .await?
+        .into_result()?;
+    let outcome = keeper
+        .call(contract.id(), "retry_settlement")
+        .args_json(json!({ "hashlock": common::hashlock(secret) }))
+        .max_gas()
+        .transact()
+        .await?;
+    assert!(outcome
+        .logs()
+        .iter()
+        .any(|log| log.starts_with("ESCROW_SETTLED")));
+
+    assert_eq!(common::ft_balance_of(&ft, maker.id()).await?, 50);
+    // The safety deposit still went to the resolver, who revealed the secret.
+    let available: String = contract
+        .view("get_available_balance")
+        .args_json(json!({ "account_id": resolver.id(), "token_id": ft.id() }))
+        .await?
+        .json()?;
+    assert_eq!(available, "10");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_unregistered_recipient_is_registered_from_the_reserve() -> Result<()> {
+    let (worker, contract, ft, _maker, resolver) = setup_swap().await?;
+    // Not registered with the token, so the first payout to this maker fails.
+    let maker = worker.dev_create_account().await?;
+    let secret = b"register me";
+    create_destination_escrow(
+        &contract,
+        &ft,
+        &maker,
+        &resolver,
+        secret,
+        common::default_timelocks(),
+        None,
+    )
+    .await?;
+    resolver
+        .call(contract.id(), "fund_registration_reserve")
+        .deposit(NearToken::from_millinear(15))
+        .transact()
+        .await?
+        .into_result()?;
+
+    let outcome = resolver
+        .call(contract.id(), "withdraw")
+        .args_json(json!({ "secret": base64::engine::general_purpose::STANDARD.encode(secret) }))
+        .max_gas()
+        .transact()
+        .await?;
+    assert!(outcome
+        .logs()
+        .iter()
+        .any(|log| log.starts_with("RECIPIENT_REGISTRATION_RETRY")));
+    assert!(outcome
+        .logs()
+        .iter()
+        .any(|log| log.starts_with("ESCROW_SETTLED")));
+
+    assert_eq!(common::ft_balance_of(&ft, maker.id()).await?, 50);
+    // Each registration takes the capped deposit from the reserve.
+    let reserve: NearToken = contract.view("get_registration_reserve").await?.json()?;
+    assert_eq!(reserve, NearToken::from_millinear(5));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_destination_payout_goes_to_the_signed_recipient() -> Result<()> {
+    let (worker, contract, ft, maker, resolver) = setup_swap().await?;
+    let recipient = worker.dev_create_account().await?;
+    maker
+        .call(ft.id(), "storage_deposit")
+        .args_json(json!({ "account_id": recipient.id() }))
+        .deposit(NearToken::from_millinear(10))
+        .transact()
+        .await?
+        .into_result()?;
+    let signer = common::OrderSigner::from_account(&maker);
+    maker
+        .call(contract.id(), "register_keys")
+        .args_json(json!({ "public_keys": [signer.public_key()] }))
+        .transact()
+        .await?
+        .into_result()?;
+
+    let secret = b"paid elsewhere";
+    let hash: String = contract
+        .view("get_payout_authorization_hash")
+        .args_json(json!({
+            "maker_id": maker.id(),
+            "hashlock": common::hashlock(secret),
+            "recipient": recipient.id(),
+        }))
+        .await?
+        .json()?;
+    let authorization = json!({
+        "recipient": recipient.id(),
+        "public_key": signer.public_key(),
+        "signature": signer.sign_hash(&hash)?,
+    });
+    create_destination_escrow(
+        &contract,
+        &ft,
+        &maker,
+        &resolver,
+        secret,
+        common::default_timelocks(),
+        Some(authorization),
+    )
+    .await?;
+
+    resolver
+        .call(contract.id(), "withdraw")
+        .args_json(json!({ "secret": base64::engine::general_purpose::STANDARD.encode(secret) }))
+        .max_gas()
+        .transact()
+        .await?
+        .into_result()?;
+
+    assert_eq!(common::ft_balance_of(&ft, recipient.id()).await?, 50);
+    assert_eq!(common::ft_balance_of(&ft, maker.id()).await?, 1_000);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_destination_withdrawal_stays_open_until_source_cancellation() -> Result<()> {
+    let (worker, contract, ft, maker, resolver) = setup_swap().await?;
+    let mut expiring = common::default_timelocks();
+    expiring["dst_public_withdrawal_delay"] = json!(0);
+    expiring["dst_cancellation_delay"] = json!(1);
+    for secret in [&b"late reveal"[..], &b"cancelled first"[..]] {
+        create_destination_escrow(
+            &contract,
+            &ft,
+            &maker,
+            &resolver,
+            secret,
+            expiring.clone(),
+            None,
+        )
+        .await?;
+    }
+    // Same windows, but the source cancellation deadline passes too.
+    let mut expired = expiring.clone();
+    expired["src_public_withdrawal_delay"] = json!(1);
+    expired["src_cancellation_delay"] = json!(2);
+    expired["src_public_cancellation_delay"] = json!(2);
+    create_destination_escrow(
+        &contract,
+        &ft,
+        &maker,
+        &resolver,
+        b"too late",
+        expired,
+        None,
+    )
+    .await?;
+    worker.fast_forward(30).await?;
+
+    // The cancellation window is open, but nobody has cancelled yet, so the maker can still claim.
+    let state: String = contract
+        .view("get_escrow_state")
+        .args_json(json!({ "hashlock": common::hashlock(b"late reveal") }))
+        .await?
+        .json()?;
+    assert_eq!(state, "Cancellable");
+    let eligibility: serde_json::Value = contract
+        .view("who_can_act_now")
+        .args_json(json!({ "hashlock": common::hashlock(b"late reveal") }))
+        .await?
+        .json()?;
+    assert_eq!(
+        eligibility,
+        json!({ "action": "Cancel", "callers": "Anyone", "can_still_withdraw": true })
+    );
+    maker
+        .call(contract.id(), "withdraw")
+        .args_json(
+            json!({ "secret": base64::engine::general_purpose::STANDARD.encode(b"late reveal") }),
+        )
+        .max_gas()
+        .transact()
+        .await?
+        .into_result()?;
+    assert_eq!(common::ft_balance_of(&ft, maker.id()).await?, 1_050);
+
+    // Once the cancellation has executed, the secret no longer claims anything.
+    resolver
+        .call(contract.id(), "cancel")
+        .args_json(json!({ "hashlock": common::hashlock(b"cancelled first") }))
+        .max_gas()
+        .transact()
+        .await?
+        .into_result()?;
+    let outcome = maker
+        .call(contract.id(), "withdraw")
+        .args_json(
+            json!({ "secret": base64::engine::general_purpose::STANDARD.encode(b"cancelled first") }),
+        )
+        .max_gas()
+        .transact()
+        .await?;
+    common::assert_failure_contains(outcome, "Escrow already claimed");
+    assert_eq!(common::ft_balance_of(&ft, maker.id()).await?, 1_050);
+
+    // Past the source cancellation deadline the source may already be refunded, so it is closed.
+    let outcome = maker
+        .call(contract.id(), "withdraw")
+        .args_json(
+            json!({ "secret": base64::engine::general_purpose::STANDARD.encode(b"too late") }),
+        )
+        .max_gas()
+        .transact()
+        .await?;
+    common::assert_failure_contains(outcome, "Withdrawal period (dst) has ended");
+    assert_eq!(common::ft_balance_of(&ft, maker.id()).await?, 1_050);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_order_status_follows_the_order_lifecycle() -> Result<()> {
+    let (_worker, contract, ft, maker, resolver) = setup_swap().await?;
+    let secret = b"tracked order";
+    let status = |nonce: u128| {
+        contract
+            .view("get_order_status")
+            .args_json(json!({ "maker_id": maker.id(), "nonce": nonce }))
+    };
+
+    let unused: serde_json::Value = status(1).await?.json()?;
+    assert_eq!(unused, json!("Unused"));
+
+    initiate_source_escrow(
+        &contract,
+        &ft,
+        &maker,
+        &resolver,
+        secret,
+        common::default_timelocks(),
+    )
+    .await?;
+    let filled: serde_json::Value = status(1).await?.json()?;
+    assert_eq!(
+        filled,
+        json!({ "Filled": { "hashlock": common::hashlock(secret), "state": "Withdrawable" } })
+    );
+
+    resolver
+        .call(contract.id(), "withdraw")
+        .args_json(json!({ "secret": base64::engine::general_purpose::STANDARD.encode(secret) }))
+        .max_gas()
+        .transact()
+        .await?
+        .into_result()?;
+    let settled: serde_json::Value = status(1).await?.json()?;
+    assert_eq!(
+        settled,
+        json!({ "Filled": { "hashlock": common::hashlock(secret), "state": "Settled" } })
+    );
+
+    // A retracted order that was never filled reports as invalidated.
+    maker
+        .call(contract.id(), "invalidate_nonce")
+        .args_json(json!({ "nonce": 2 }))
+        .transact()
+        .await?
+        .into_result()?;
+    let invalidated: serde_json::Value = status(2).await?.json()?;
+    assert_eq!(invalidated, json!("Invalidated"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_public_withdrawal_reward_grows_with_lateness() -> Result<()> {
+    let (worker, contract, owner) = common::setup().await?;
+    let maker = worker.dev_create_account().await?;
+    let resolver = worker.dev_create_account().await?;
+    let keeper = worker.dev_create_account().await?;
+    let ft = common::setup_ft(
+        &worker,
+        &owner,
+        &[contract.id(), maker.id(), resolver.id(), keeper.id()],
+    )
+    .await?;
+    common::ft_transfer(&ft, &owner, resolver.id(), 1_000).await?;
+    owner
+        .call(contract.id(), "set_safety_deposit_decay_period")
+        .args_json(json!({ "period_seconds": 60 }))
+        .transact()
+        .await?
+        .into_result()?;
+
+    // Both escrows are publicly withdrawable from the start.
+    let mut timelocks = common::default_timelocks();
+    timelocks["dst_public_withdrawal_delay"] = json!(0);
+    for secret in [&b"settled promptly"[..], &b"settled late"[..]] {
+        create_destination_escrow(
+            &contract,
+            &ft,
+            &maker,
+            &resolver,
+            secret,
+            timelocks.clone(),
+            None,
+        )
+        .await?;
+    }
+    let available = |account: &Account| {
+        contract
+            .view("get_available_balance")
+            .args_json(json!({ "account_id": account.id(), "token_id": ft.id() }))
+    };
+    let withdraw = |secret: &[u8]| {
+        keeper
+            .call(contract.id(), "withdraw")
+            .args_json(
+                json!({ "secret": base64::engine::general_purpose::STANDARD.encode(secret) }),
+            )
+            .max_gas()
+    };
+
+    // Settled a few seconds in, the keeper earns only a sliver of the safety deposit of 10.
+    withdraw(b"settled promptly")
+        .transact()
+        .await?
+        .into_result()?;
+    let prompt_reward: u128 = available(&keeper).await?.json::<String>()?.parse()?;
+    assert!(prompt_reward < 10);
+    let resolver_balance: String = available(&resolver).await?.json()?;
+    assert_eq!(resolver_balance, (10 - prompt_reward).to_string());
+
+    // Once the whole decay period has passed, the safety deposit goes entirely to the keeper.
+    worker.fast_forward(200).await?;
+    let estimate: serde_json::Value = contract
+        .view("get_public_action_reward")
+        .args_json(json!({ "hashlock": common::hashlock(b"settled late") }))
+        .await?
+        .json()?;
+    assert_eq!(estimate["safety_deposit"], "10");
+    withdraw(b"settled late").transact().await?.into_result()?;
+    let keeper_balance: String = available(&keeper).await?.json()?;
+    assert_eq!(keeper_balance, (prompt_reward + 10).to_string());
+    let resolver_balance: String = available(&resolver).await?.json()?;
+    assert_eq!(resolver_balance, (10 - prompt_reward).to_string());
+    assert_eq!(common::ft_balance_of(&ft, maker.id()).await?, 100);
+
+    Ok(())
+}
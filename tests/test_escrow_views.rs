@@ -0,0 +1,1180 @@
+mod common;
+
+use anyhow::Result;
+use base64::Engine;
+use near_workspaces::types::NearToken;
+use serde_json::json;
+
+fn dst_timelocks(withdrawal: u64, public_withdrawal: u64, cancellation: u64) -> serde_json::Value {
+    json!({
+        "src_withdrawal_delay": 0,
+        "src_public_withdrawal_delay": 3000,
+        "src_cancellation_delay": 6000,
+        "src_public_cancellation_delay": 9000,
+        "dst_withdrawal_delay": withdrawal,
+        "dst_public_withdrawal_delay": public_withdrawal,
+        "dst_cancellation_delay": cancellation,
+    })
+}
+
+#[tokio::test]
+async fn test_who_can_act_now_follows_destination_windows() -> Result<()> {
+    let (worker, contract, _owner) = common::setup().await?;
+    let token = worker.dev_create_account().await?;
+    let maker = worker.dev_create_account().await?;
+    let resolver = worker.dev_create_account().await?;
+
+    // Each escrow is created in a different stage, since windows are relative to creation time.
+    let cases = [
+        (
+            common::hashlock(b"finality"),
+            dst_timelocks(1000, 2000, 3000),
+            json!({ "action": null, "callers": "Nobody", "can_still_withdraw": false }),
+        ),
+        (
+            common::hashlock(b"private"),
+            dst_timelocks(0, 2000, 3000),
+            json!({ "action": "Withdraw", "callers": { "Taker": resolver.id() }, "can_still_withdraw": false }),
+        ),
+        (
+            common::hashlock(b"public"),
+            dst_timelocks(0, 0, 3000),
+            json!({ "action": "Withdraw", "callers": "Anyone", "can_still_withdraw": false }),
+        ),
+    ];
+
+    for (hashlock, timelocks, expected) in cases {
+        common::create_destination_escrow(
+            &contract,
+            &token,
+            &resolver,
+            json!({
+                "type": "CreateDestinationEscrow",
+                "hashlock": &hashlock,
+                "maker_id": maker.id(),
+                "timelocks": timelocks,
+            }),
+            100,
+            NearToken::from_millinear(100),
+        )
+        .await?
+        .into_result()?;
+
+        let eligibility: serde_json::Value = contract
+            .view("who_can_act_now")
+            .args_json(json!({ "hashlock": &hashlock }))
+            .await?
+            .json()?;
+        assert_eq!(eligibility, expected, "hashlock {}", hashlock);
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_self_swap_is_rejected() -> Result<()> {
+    let (worker, contract, _owner) = common::setup().await?;
+    let token = worker.dev_create_account().await?;
+    let resolver = worker.dev_create_account().await?;
+
+    let outcome = common::create_destination_escrow(
+        &contract,
+        &token,
+        &resolver,
+        json!({
+            "type": "CreateDestinationEscrow",
+            "hashlock": common::hashlock(b"self-swap"),
+            "maker_id": resolver.id(),
+            "timelocks": common::default_timelocks(),
+        }),
+        100,
+        NearToken::from_millinear(100),
+    )
+    .await?;
+    common::assert_failure_contains(outcome, "Maker and taker must differ");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_count_escrows_by_state() -> Result<()> {
+    let (worker, contract, _owner) = common::setup().await?;
+    let token = worker.dev_create_account().await?;
+    let maker = worker.dev_create_account().await?;
+    let resolver = worker.dev_create_account().await?;
+
+    for (secret, timelocks) in [
+        (&b"open"[..], dst_timelocks(1000, 2000, 3000)),
+        (&b"withdrawable-1"[..], dst_timelocks(0, 2000, 3000)),
+        (&b"withdrawable-2"[..], dst_timelocks(0, 0, 3000)),
+    ] {
+        common::create_destination_escrow(
+            &contract,
+            &token,
+            &resolver,
+            json!({
+                "type": "CreateDestinationEscrow",
+                "hashlock": common::hashlock(secret),
+                "maker_id": maker.id(),
+                "timelocks": timelocks,
+            }),
+            100,
+            NearToken::from_millinear(100),
+        )
+        .await?
+        .into_result()?;
+    }
+
+    let counts: serde_json::Value = contract
+        .view("count_escrows_by_state")
+        .args_json(json!({ "from_index": 0, "limit": 10 }))
+        .await?
+        .json()?;
+    assert_eq!(
+        counts,
+        json!({ "open": 1, "withdrawable": 2, "cancellable": 0, "settled": 0 })
+    );
+
+    // Pages are disjoint and add up to the full tally.
+    let first: serde_json::Value = contract
+        .view("count_escrows_by_state")
+        .args_json(json!({ "from_index": 0, "limit": 2 }))
+        .await?
+        .json()?;
+    let second: serde_json::Value = contract
+        .view("count_escrows_by_state")
+        .args_json(json!({ "from_index": 2, "limit": 2 }))
+        .await?
+        .json()?;
+    for state in ["open", "withdrawable"] {
+        assert_eq!(
+            first[state].as_u64().unwrap() + second[state].as_u64().unwrap(),
+            counts[state].as_u64().unwrap()
+        );
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_memo_round_trips_through_get_escrow() -> Result<()> {
+    let (worker, contract, _owner) = common::setup().await?;
+    let token = worker.dev_create_account().await?;
+    let maker = worker.dev_create_account().await?;
+    let resolver = worker.dev_create_account().await?;
+
+    let hashlock = common::hashlock(b"memo");
+    let outcome = common::create_destination_escrow(
+        &contract,
+        &token,
+        &resolver,
+        json!({
+            "type": "CreateDestinationEscrow",
+            "hashlock": hashlock,
+            "maker_id": maker.id(),
+            "timelocks": common::default_timelocks(),
+            "memo": "0xdeadbeef",
+        }),
+        100,
+        NearToken::from_millinear(100),
+    )
+    .await?
+    .into_result()?;
+    assert!(outcome
+        .logs()
+        .iter()
+        .any(|log| log.contains("memo='0xdeadbeef'")));
+
+    let escrow: serde_json::Value = contract
+        .view("get_escrow")
+        .args_json(json!({ "hashlock": hashlock }))
+        .await?
+        .json()?;
+    assert_eq!(escrow["memo"], "0xdeadbeef");
+    assert_eq!(escrow["hashlock_b58"], hashlock);
+
+    let outcome = common::create_destination_escrow(
+        &contract,
+        &token,
+        &resolver,
+        json!({
+            "type": "CreateDestinationEscrow",
+            "hashlock": common::hashlock(b"long memo"),
+            "maker_id": maker.id(),
+            "timelocks": common::default_timelocks(),
+            "memo": "x".repeat(257),
+        }),
+        100,
+        NearToken::from_millinear(100),
+    )
+    .await?;
+    common::assert_failure_contains(outcome, "Memo is too long");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_safety_deposit_pool_tracks_creation_and_settlement() -> Result<()> {
+    let (worker, contract, owner) = common::setup().await?;
+    let token = worker.dev_create_account().await?;
+    let maker = worker.dev_create_account().await?;
+    let resolver = worker.dev_create_account().await?;
+
+    for secret in [&b"pool-1"[..], &b"pool-2"[..]] {
+        common::create_destination_escrow(
+            &contract,
+            &token,
+            &resolver,
+            json!({
+                "type": "CreateDestinationEscrow",
+                "hashlock": common::hashlock(secret),
+                "maker_id": maker.id(),
+                "timelocks": common::default_timelocks(),
+            }),
+            100,
+            NearToken::from_millinear(100),
+        )
+        .await?
+        .into_result()?;
+    }
+
+    let pool: NearToken = contract.view("get_safety_deposit_pool").await?.json()?;
+    assert_eq!(pool, NearToken::from_millinear(200));
+
+    // Settling one escrow pays its safety deposit out of the pool.
+    owner
+        .call(contract.id(), "add_relayer")
+        .args_json(json!({ "account_id": owner.id() }))
+        .transact()
+        .await?
+        .into_result()?;
+    owner
+        .call(contract.id(), "relayer_cancel")
+        .args_json(json!({ "hashlock": common::hashlock(b"pool-1") }))
+        .max_gas()
+        .transact()
+        .await?;
+
+    let pool: NearToken = contract.view("get_safety_deposit_pool").await?.json()?;
+    assert_eq!(pool, NearToken::from_millinear(100));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_escrow_view_hashlock_encodings_match() -> Result<()> {
+    let (worker, contract, _owner) = common::setup().await?;
+    let token = worker.dev_create_account().await?;
+    let maker = worker.dev_create_account().await?;
+    let resolver = worker.dev_create_account().await?;
+
+    let hashlock = common::hashlock(b"encodings");
+    common::create_destination_escrow(
+        &contract,
+        &token,
+        &resolver,
+        json!({
+            "type": "CreateDestinationEscrow",
+            "hashlock": hashlock,
+            "maker_id": maker.id(),
+            "timelocks": common::default_timelocks(),
+        }),
+        100,
+        NearToken::from_millinear(100),
+    )
+    .await?
+    .into_result()?;
+
+    let escrow: serde_json::Value = contract
+        .view("get_escrow")
+        .args_json(json!({ "hashlock": hashlock }))
+        .await?
+        .json()?;
+    let b58_bytes = near_sdk::bs58::decode(escrow["hashlock_b58"].as_str().unwrap()).into_vec()?;
+    let hex = escrow["hashlock_hex"].as_str().unwrap();
+    assert!(hex.starts_with("0x"));
+    let hex_bytes = (2..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16))
+        .collect::<Result<Vec<u8>, _>>()?;
+    assert_eq!(b58_bytes.len(), 32);
+    assert_eq!(b58_bytes, hex_bytes);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_ft_metadata_check_rejects_non_ft_asset() -> Result<()> {
+    let (worker, contract, owner) = common::setup().await?;
+    let not_a_token = worker.dev_create_account().await?;
+    let maker = worker.dev_create_account().await?;
+    let resolver = worker.dev_create_account().await?;
+
+    owner
+        .call(contract.id(), "set_verify_ft_metadata")
+        .args_json(json!({ "enabled": true }))
+        .transact()
+        .await?
+        .into_result()?;
+
+    let hashlock = common::hashlock(b"not-a-token");
+    common::create_destination_escrow(
+        &contract,
+        &not_a_token,
+        &resolver,
+        json!({
+            "type": "CreateDestinationEscrow",
+            "hashlock": hashlock,
+            "maker_id": maker.id(),
+            "timelocks": common::default_timelocks(),
+        }),
+        100,
+        NearToken::from_millinear(100),
+    )
+    .await?
+    .into_result()?;
+
+    let escrow: Option<serde_json::Value> = contract
+        .view("get_escrow")
+        .args_json(json!({ "hashlock": hashlock }))
+        .await?
+        .json()?;
+    assert!(escrow.is_none());
+
+    let pool: NearToken = contract.view("get_safety_deposit_pool").await?.json()?;
+    assert_eq!(pool, NearToken::from_yoctonear(0));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_excess_safety_deposit_is_refunded_on_creation() -> Result<()> {
+    let (worker, contract, _owner) = common::setup().await?;
+    let token = worker.dev_create_account().await?;
+    let maker = worker.dev_create_account().await?;
+    let resolver = worker.dev_create_account().await?;
+
+    let balance_before = resolver.view_account().await?.balance;
+    let hashlock = common::hashlock(b"over-attached");
+    common::create_destination_escrow(
+        &contract,
+        &token,
+        &resolver,
+        json!({
+            "type": "CreateDestinationEscrow",
+            "hashlock": hashlock,
+            "maker_id": maker.id(),
+            "timelocks": common::default_timelocks(),
+            "safety_deposit_amount": NearToken::from_millinear(100),
+        }),
+        100,
+        NearToken::from_near(1),
+    )
+    .await?
+    .into_result()?;
+
+    let escrow: serde_json::Value = contract
+        .view("get_escrow")
+        .args_json(json!({ "hashlock": hashlock }))
+        .await?
+        .json()?;
+    assert_eq!(
+        escrow["safety_deposit"],
+        json!(NearToken::from_millinear(100))
+    );
+    let pool: NearToken = contract.view("get_safety_deposit_pool").await?.json()?;
+    assert_eq!(pool, NearToken::from_millinear(100));
+
+    // The resolver did not pay for the transaction, so its balance grows by exactly the excess.
+    let balance_after = resolver.view_account().await?.balance;
+    assert_eq!(
+        balance_after,
+        balance_before.saturating_add(NearToken::from_millinear(900))
+    );
+
+    let outcome = common::create_destination_escrow(
+        &contract,
+        &token,
+        &resolver,
+        json!({
+            "type": "CreateDestinationEscrow",
+            "hashlock": common::hashlock(b"under-attached"),
+            "maker_id": maker.id(),
+            "timelocks": common::default_timelocks(),
+            "safety_deposit_amount": NearToken::from_near(2),
+        }),
+        100,
+        NearToken::from_near(1),
+    )
+    .await?;
+    common::assert_failure_contains(outcome, "Attached deposit is less than the safety deposit");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_disallowed_hash_algo_is_rejected() -> Result<()> {
+    let (worker, contract, owner) = common::setup().await?;
+    let token = worker.dev_create_account().await?;
+    let maker = worker.dev_create_account().await?;
+    let resolver = worker.dev_create_account().await?;
+
+    let allowed: Vec<String> = contract.view("get_allowed_hash_algos").await?.json()?;
+    assert_eq!(allowed, vec!["Sha256", "Keccak256"]);
+
+    owner
+        .call(contract.id(), "set_allowed_hash_algos")
+        .args_json(json!({ "hash_algos": ["Sha256"] }))
+        .transact()
+        .await?
+        .into_result()?;
+
+    let create = |hash_algo: &'static str, secret: &'static [u8]| {
+        common::create_destination_escrow(
+            &contract,
+            &token,
+            &resolver,
+            json!({
+                "type": "CreateDestinationEscrow",
+                "hashlock": common::hashlock(secret),
+                "maker_id": maker.id(),
+                "timelocks": common::default_timelocks(),
+                "hash_algo": hash_algo,
+            }),
+            100,
+            NearToken::from_millinear(100),
+        )
+    };
+    let outcome = create("Keccak256", b"keccak").await?;
+    common::assert_failure_contains(outcome, "Hash algorithm is not allowed");
+    create("Sha256", b"sha256").await?.into_result()?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_find_escrow_by_secret() -> Result<()> {
+    let (worker, contract, _owner) = common::setup().await?;
+    let token = worker.dev_create_account().await?;
+    let maker = worker.dev_create_account().await?;
+    let resolver = worker.dev_create_account().await?;
+
+    let secret = b"revealed elsewhere";
+    common::create_destination_escrow(
+        &contract,
+        &token,
+        &resolver,
+        json!({
+            "type": "CreateDestinationEscrow",
+            "hashlock": common::hashlock(secret),
+            "maker_id": maker.id(),
+            "timelocks": common::default_timelocks(),
+        }),
+        100,
+        NearToken::from_millinear(100),
+    )
+    .await?
+    .into_result()?;
+
+    let find = |secret: String, algo: &'static str| {
+        contract
+            .view("find_escrow_by_secret")
+            .args_json(json!({ "secret": secret, "algo": algo }))
+    };
+    let found: Option<serde_json::Value> = find(
+        base64::engine::general_purpose::STANDARD.encode(secret),
+        "Sha256",
+    )
+    .await?
+    .json()?;
+    assert_eq!(found.unwrap()["hashlock_b58"], common::hashlock(secret));
+
+    // The same secret under another hash function locks nothing here.
+    let found: Option<serde_json::Value> = find(
+        base64::engine::general_purpose::STANDARD.encode(secret),
+        "Keccak256",
+    )
+    .await?
+    .json()?;
+    assert!(found.is_none());
+
+    let found: Option<serde_json::Value> =
+        find("not base64!".to_string(), "Sha256").await?.json()?;
+    assert!(found.is_none());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_compute_hashlock_matches_host_hashes() -> Result<()> {
+    let (_worker, contract, _owner) = common::setup().await?;
+
+    for secret in [&b""[..], &b"abc"[..], &b"my super secret string"[..]] {
+        let compute = |algo: &'static str| {
+            contract.view("compute_hashlock").args_json(json!({
+                "secret": base64::engine::general_purpose::STANDARD.encode(secret),
+                "algo": algo,
+            }))
+        };
+        let sha256: String = compute("Sha256").await?.json()?;
+        assert_eq!(
+            sha256,
+            near_sdk::bs58::encode(near_sdk::env::sha256_array(secret)).into_string()
+        );
+        assert_eq!(sha256, common::hashlock(secret));
+        let keccak256: String = compute("Keccak256").await?.json()?;
+        assert_eq!(
+            keccak256,
+            near_sdk::bs58::encode(near_sdk::env::keccak256_array(secret)).into_string()
+        );
+    }
+
+    let result = contract
+        .view("compute_hashlock")
+        .args_json(json!({ "secret": "not base64!", "algo": "Sha256" }))
+        .await;
+    assert!(result.is_err());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_escrow_raw_round_trips_through_borsh() -> Result<()> {
+    use sha2::{Digest, Sha256};
+    let (worker, contract, _owner) = common::setup().await?;
+    let token = worker.dev_create_account().await?;
+    let maker = worker.dev_create_account().await?;
+    let resolver = worker.dev_create_account().await?;
+
+    let secret = b"raw bytes";
+    common::create_destination_escrow(
+        &contract,
+        &token,
+        &resolver,
+        json!({
+            "type": "CreateDestinationEscrow",
+            "hashlock": common::hashlock(secret),
+            "maker_id": maker.id(),
+            "timelocks": common::default_timelocks(),
+            "memo": "order-42",
+        }),
+        100,
+        NearToken::from_millinear(100),
+    )
+    .await?
+    .into_result()?;
+
+    let raw: Option<String> = contract
+        .view("get_escrow_raw")
+        .args_json(json!({ "hashlock": common::hashlock(secret) }))
+        .await?
+        .json()?;
+    let bytes = base64::engine::general_purpose::STANDARD.decode(raw.unwrap())?;
+    let escrow: cross_chain_swap_near::Escrow = near_sdk::borsh::from_slice(&bytes)?;
+    assert_eq!(escrow.hashlock[..], Sha256::digest(secret)[..]);
+    assert_eq!(escrow.maker.as_str(), maker.id().as_str());
+    assert_eq!(escrow.taker.as_str(), resolver.id().as_str());
+    assert_eq!(escrow.amount.as_yoctonear(), 100);
+    assert_eq!(escrow.memo.as_deref(), Some("order-42"));
+    assert!(!escrow.is_source);
+    assert_eq!(near_sdk::borsh::to_vec(&escrow)?, bytes);
+
+    // The same bytes are stored, followed by the escrow's index in the map.
+    let escrow_id = Sha256::digest([&Sha256::digest(secret)[..], &[0u8]].concat());
+    let stored = contract.view_state().await?[&[&b"em"[..], &escrow_id[..]].concat()].clone();
+    assert_eq!(stored[..stored.len() - 4], bytes[..]);
+
+    let raw: Option<String> = contract
+        .view("get_escrow_raw")
+        .args_json(json!({ "hashlock": common::hashlock(b"no such escrow") }))
+        .await?
+        .json()?;
+    assert!(raw.is_none());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_top_up_destination_escrow() -> Result<()> {
+    let (worker, contract, _owner) = common::setup().await?;
+    let token = worker.dev_create_account().await?;
+    let maker = worker.dev_create_account().await?;
+    let resolver = worker.dev_create_account().await?;
+    let stranger = worker.dev_create_account().await?;
+
+    let hashlock = common::hashlock(b"top up");
+    common::create_destination_escrow(
+        &contract,
+        &token,
+        &resolver,
+        json!({
+            "type": "CreateDestinationEscrow",
+            "hashlock": hashlock,
+            "maker_id": maker.id(),
+            "timelocks": common::default_timelocks(),
+        }),
+        100,
+        NearToken::from_millinear(100),
+    )
+    .await?
+    .into_result()?;
+
+    let top_up = |sender: &near_workspaces::Account| {
+        token
+            .call(contract.id(), "ft_on_transfer")
+            .args_json(json!({
+                "sender_id": sender.id(),
+                "amount": "50",
+                "msg": json!({ "type": "TopUpDestinationEscrow", "hashlock": hashlock }).to_string(),
+            }))
+            .transact()
+    };
+    let outcome = top_up(&stranger).await?;
+    common::assert_failure_contains(outcome, "Only the taker can top up the escrow");
+    top_up(&resolver).await?.into_result()?;
+
+    let escrow: serde_json::Value = contract
+        .view("get_escrow")
+        .args_json(json!({ "hashlock": hashlock }))
+        .await?
+        .json()?;
+    assert_eq!(escrow["amount"], "150");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_safety_deposit_below_token_minimum_is_rejected() -> Result<()> {
+    let (worker, contract, owner) = common::setup().await?;
+    let token = worker.dev_create_account().await?;
+    let maker = worker.dev_create_account().await?;
+    let resolver = worker.dev_create_account().await?;
+
+    // 1 milliNEAR of safety deposit per base unit of the token.
+    owner
+        .call(contract.id(), "set_min_safety_deposit_per_unit")
+        .args_json(json!({
+            "token_id": token.id(),
+            "min": NearToken::from_millinear(1).as_yoctonear().to_string(),
+        }))
+        .transact()
+        .await?
+        .into_result()?;
+
+    let create = |secret: &'static [u8], amount: u128| {
+        common::create_destination_escrow(
+            &contract,
+            &token,
+            &resolver,
+            json!({
+                "type": "CreateDestinationEscrow",
+                "hashlock": common::hashlock(secret),
+                "maker_id": maker.id(),
+                "timelocks": common::default_timelocks(),
+            }),
+            amount,
+            NearToken::from_millinear(100),
+        )
+    };
+    let outcome = create(b"large", 1_000).await?;
+    common::assert_failure_contains(outcome, "Safety deposit is too small for the escrow amount");
+    create(b"small", 100).await?.into_result()?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_dust_escrow_below_token_minimum_is_rejected() -> Result<()> {
+    let (worker, contract, owner) = common::setup().await?;
+    let token = worker.dev_create_account().await?;
+    let other_token = worker.dev_create_account().await?;
+    let maker = worker.dev_create_account().await?;
+    let resolver = worker.dev_create_account().await?;
+
+    owner
+        .call(contract.id(), "set_min_escrow_amount")
+        .args_json(json!({ "token_id": token.id(), "min": "100" }))
+        .transact()
+        .await?
+        .into_result()?;
+    let min: Option<String> = contract
+        .view("get_min_escrow_amount")
+        .args_json(json!({ "token_id": token.id() }))
+        .await?
+        .json()?;
+    assert_eq!(min.as_deref(), Some("100"));
+
+    let create = |token: &near_workspaces::Account, secret: &'static [u8], amount: u128| {
+        common::create_destination_escrow(
+            &contract,
+            token,
+            &resolver,
+            json!({
+                "type": "CreateDestinationEscrow",
+                "hashlock": common::hashlock(secret),
+                "maker_id": maker.id(),
+                "timelocks": common::default_timelocks(),
+            }),
+            amount,
+            NearToken::from_millinear(100),
+        )
+    };
+    let outcome = create(&token, b"dust", 99).await?;
+    common::assert_failure_contains(outcome, "Escrow amount is below the token's minimum");
+    create(&token, b"at the floor", 100).await?.into_result()?;
+    // Tokens without a configured minimum accept any amount.
+    create(&other_token, b"unfloored", 1).await?.into_result()?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_account_escrow_history_lists_active_and_settled_escrows() -> Result<()> {
+    let (worker, contract, owner) = common::setup().await?;
+    let token = worker.dev_create_account().await?;
+    let maker = worker.dev_create_account().await?;
+    let other_maker = worker.dev_create_account().await?;
+    let resolver = worker.dev_create_account().await?;
+    let relayer = worker.dev_create_account().await?;
+
+    for (secret, maker_id) in [
+        (&b"first"[..], maker.id()),
+        (&b"someone else's"[..], other_maker.id()),
+        (&b"second"[..], maker.id()),
+    ] {
+        common::create_destination_escrow(
+            &contract,
+            &token,
+            &resolver,
+            json!({
+                "type": "CreateDestinationEscrow",
+                "hashlock": common::hashlock(secret),
+                "maker_id": maker_id,
+                "timelocks": common::default_timelocks(),
+            }),
+            100,
+            NearToken::from_millinear(100),
+        )
+        .await?
+        .into_result()?;
+    }
+
+    owner
+        .call(contract.id(), "add_relayer")
+        .args_json(json!({ "account_id": relayer.id() }))
+        .transact()
+        .await?
+        .into_result()?;
+    relayer
+        .call(contract.id(), "relayer_cancel")
+        .args_json(json!({ "hashlock": common::hashlock(b"first") }))
+        .max_gas()
+        .transact()
+        .await?
+        .into_result()?;
+
+    let history: Vec<serde_json::Value> = contract
+        .view("get_account_escrow_history")
+        .args_json(json!({ "account_id": maker.id(), "from_index": 0, "limit": 10 }))
+        .await?
+        .json()?;
+    assert_eq!(history.len(), 2);
+    assert_eq!(
+        history[0]["escrow"]["hashlock_b58"],
+        common::hashlock(b"first")
+    );
+    assert_eq!(history[0]["status"], "Settled");
+    assert_eq!(history[0]["settlement"]["was_cancel"], true);
+    assert_eq!(
+        history[1]["escrow"]["hashlock_b58"],
+        common::hashlock(b"second")
+    );
+    assert_eq!(history[1]["status"], "Withdrawable");
+    assert!(history[1]["settlement"].is_null());
+
+    // The taker sees every escrow it filled.
+    let history: Vec<serde_json::Value> = contract
+        .view("get_account_escrow_history")
+        .args_json(json!({ "account_id": resolver.id(), "from_index": 0, "limit": 10 }))
+        .await?
+        .json()?;
+    assert_eq!(history.len(), 3);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_emit_metrics_event_shape() -> Result<()> {
+    let (worker, contract, owner) = common::setup().await?;
+    let token = worker.dev_create_account().await?;
+    let other_token = worker.dev_create_account().await?;
+    let maker = worker.dev_create_account().await?;
+    let resolver = worker.dev_create_account().await?;
+
+    for (secret, token, amount) in [
+        (&b"one"[..], &token, 100),
+        (&b"two"[..], &token, 50),
+        (&b"three"[..], &other_token, 70),
+    ] {
+        common::create_destination_escrow(
+            &contract,
+            token,
+            &resolver,
+            json!({
+                "type": "CreateDestinationEscrow",
+                "hashlock": common::hashlock(secret),
+                "maker_id": maker.id(),
+                "timelocks": common::default_timelocks(),
+            }),
+            amount,
+            NearToken::from_millinear(100),
+        )
+        .await?
+        .into_result()?;
+    }
+
+    let outcome = resolver
+        .call(contract.id(), "emit_metrics")
+        .transact()
+        .await?;
+    common::assert_failure_contains(outcome, "Only the owner can call this method");
+
+    let outcome = owner
+        .call(contract.id(), "emit_metrics")
+        .transact()
+        .await?
+        .into_result()?;
+    let event = outcome
+        .logs()
+        .iter()
+        .find_map(|log| log.strip_prefix("EVENT_JSON:"))
+        .expect("metrics event missing");
+    let event: serde_json::Value = serde_json::from_str(event)?;
+    assert_eq!(event["event"], "metrics");
+    assert_eq!(
+        event["data"][0],
+        json!({
+            "num_active_escrows": 3,
+            "safety_deposit_pool": NearToken::from_millinear(300).as_yoctonear().to_string(),
+            "locked_per_token": [[token.id(), "150"], [other_token.id(), "70"]],
+        })
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_declared_asset_must_match_the_transferred_token() -> Result<()> {
+    let (worker, contract, _owner) = common::setup().await?;
+    let token = worker.dev_create_account().await?;
+    let other_token = worker.dev_create_account().await?;
+    let maker = worker.dev_create_account().await?;
+    let resolver = worker.dev_create_account().await?;
+
+    for (secret, declared) in [(&b"spoofed"[..], &other_token), (&b"declared"[..], &token)] {
+        let outcome = common::create_destination_escrow(
+            &contract,
+            &token,
+            &resolver,
+            json!({
+                "type": "CreateDestinationEscrow",
+                "hashlock": common::hashlock(secret),
+                "maker_id": maker.id(),
+                "timelocks": common::default_timelocks(),
+                "asset_id": declared.id(),
+            }),
+            100,
+            NearToken::from_millinear(100),
+        )
+        .await?;
+        if declared.id() == token.id() {
+            outcome.into_result()?;
+        } else {
+            common::assert_failure_contains(
+                outcome,
+                "Declared asset does not match the transferred token",
+            );
+        }
+    }
+
+    let spoofed: Option<serde_json::Value> = contract
+        .view("get_escrow")
+        .args_json(json!({ "hashlock": common::hashlock(b"spoofed") }))
+        .await?
+        .json()?;
+    assert!(spoofed.is_none());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_public_action_reward_is_paid_only_in_public_windows() -> Result<()> {
+    let (worker, contract, _owner) = common::setup().await?;
+    let token = worker.dev_create_account().await?;
+    let maker = worker.dev_create_account().await?;
+    let resolver = worker.dev_create_account().await?;
+
+    let safety_deposit = NearToken::from_millinear(100);
+    let cases = [
+        (
+            common::hashlock(b"private"),
+            dst_timelocks(0, 2000, 3000),
+            None,
+            0,
+        ),
+        (
+            common::hashlock(b"public"),
+            dst_timelocks(0, 0, 3000),
+            Some("Withdraw"),
+            safety_deposit.as_yoctonear(),
+        ),
+    ];
+
+    for (hashlock, timelocks, action, reward) in cases {
+        common::create_destination_escrow(
+            &contract,
+            &token,
+            &resolver,
+            json!({
+                "type": "CreateDestinationEscrow",
+                "hashlock": &hashlock,
+                "maker_id": maker.id(),
+                "timelocks": timelocks,
+            }),
+            100,
+            safety_deposit,
+        )
+        .await?
+        .into_result()?;
+
+        let estimate: serde_json::Value = contract
+            .view("get_public_action_reward")
+            .args_json(json!({ "hashlock": &hashlock }))
+            .await?
+            .json()?;
+        assert_eq!(
+            estimate,
+            json!({
+                "action": action,
+                "safety_deposit": reward.to_string(),
+                "safety_deposit_token": null,
+                "bounty": "0",
+            }),
+            "hashlock {}",
+            hashlock
+        );
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_is_escrow_claimable_by_depends_on_window_and_identity() -> Result<()> {
+    let (worker, contract, _owner) = common::setup().await?;
+    let token = worker.dev_create_account().await?;
+    let maker = worker.dev_create_account().await?;
+    let resolver = worker.dev_create_account().await?;
+    let stranger = worker.dev_create_account().await?;
+
+    let eligibility = |can_withdraw: bool, can_cancel: bool| json!({ "can_withdraw": can_withdraw, "can_cancel": can_cancel });
+    // (secret, timelocks, expected for the taker, the maker and a third party)
+    let cases = [
+        (
+            &b"private"[..],
+            dst_timelocks(0, 2000, 3000),
+            [
+                eligibility(true, false),
+                eligibility(false, false),
+                eligibility(false, false),
+            ],
+        ),
+        (
+            &b"public"[..],
+            dst_timelocks(0, 0, 3000),
+            [
+                eligibility(true, false),
+                eligibility(true, false),
+                eligibility(true, false),
+            ],
+        ),
+        (
+            &b"cancellable"[..],
+            dst_timelocks(0, 0, 1),
+            // A destination escrow can still be withdrawn until it is cancelled or the source
+            // cancellation deadline passes.
+            [
+                eligibility(true, true),
+                eligibility(true, true),
+                eligibility(true, true),
+            ],
+        ),
+    ];
+    for (secret, timelocks, _) in &cases {
+        common::create_destination_escrow(
+            &contract,
+            &token,
+            &resolver,
+            json!({
+                "type": "CreateDestinationEscrow",
+                "hashlock": common::hashlock(secret),
+                "maker_id": maker.id(),
+                "timelocks": timelocks,
+            }),
+            100,
+            NearToken::from_millinear(100),
+        )
+        .await?
+        .into_result()?;
+    }
+    worker.fast_forward(10).await?;
+
+    for (secret, _, expected) in &cases {
+        for (account, expected) in [&resolver, &maker, &stranger].into_iter().zip(expected) {
+            let actual: serde_json::Value = contract
+                .view("is_escrow_claimable_by")
+                .args_json(json!({
+                    "hashlock": common::hashlock(secret),
+                    "account_id": account.id(),
+                }))
+                .await?
+                .json()?;
+            assert_eq!(&actual, expected, "{:?} for {}", secret, account.id());
+        }
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_ft_amount_round_trips_at_u128_max() -> Result<()> {
+    let (worker, contract, _owner) = common::setup().await?;
+    let token = worker.dev_create_account().await?;
+    let resolver = worker.dev_create_account().await?;
+    let maker = worker.dev_create_account().await?;
+    let hashlock = common::hashlock(b"max-amount");
+
+    common::create_destination_escrow(
+        &contract,
+        &token,
+        &resolver,
+        json!({
+            "type": "CreateDestinationEscrow",
+            "hashlock": &hashlock,
+            "maker_id": maker.id(),
+            "timelocks": common::default_timelocks(),
+        }),
+        u128::MAX,
+        NearToken::from_millinear(100),
+    )
+    .await?
+    .into_result()?;
+
+    let escrow: serde_json::Value = contract
+        .view("get_escrow")
+        .args_json(json!({ "hashlock": &hashlock }))
+        .await?
+        .json()?;
+    assert_eq!(escrow["amount"], u128::MAX.to_string());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_near_breakdown_is_consistent() -> Result<()> {
+    let (worker, contract, _owner) = common::setup().await?;
+    let token = worker.dev_create_account().await?;
+    let maker = worker.dev_create_account().await?;
+    let resolver = worker.dev_create_account().await?;
+
+    common::create_destination_escrow(
+        &contract,
+        &token,
+        &resolver,
+        json!({
+            "type": "CreateDestinationEscrow",
+            "hashlock": common::hashlock(b"breakdown"),
+            "maker_id": maker.id(),
+            "timelocks": common::default_timelocks(),
+        }),
+        100,
+        NearToken::from_millinear(100),
+    )
+    .await?
+    .into_result()?;
+
+    let breakdown: serde_json::Value = contract.view("get_near_breakdown").await?.json()?;
+    let field = |name: &str| -> u128 { breakdown[name].as_str().unwrap().parse().unwrap() };
+    let details = contract.view_account().await?;
+
+    assert_eq!(field("account_balance"), details.balance.as_yoctonear());
+    assert_eq!(
+        field("locked_for_storage"),
+        u128::from(details.storage_usage) * 10u128.pow(19)
+    );
+    assert_eq!(
+        field("safety_deposit_pool"),
+        NearToken::from_millinear(100).as_yoctonear()
+    );
+    assert!(field("free") > 0);
+    assert_eq!(
+        field("account_balance"),
+        field("locked_for_storage") + field("safety_deposit_pool") + field("free")
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_missing_escrow_views_return_none_and_calls_return_coded_error() -> Result<()> {
+    let (worker, contract, _owner) = common::setup().await?;
+    let caller = worker.dev_create_account().await?;
+    let hashlock = common::hashlock(b"never created");
+
+    for view in [
+        "get_escrow",
+        "get_escrow_state",
+        "who_can_act_now",
+        "get_public_action_reward",
+        "estimate_settlement_gas",
+    ] {
+        let result: serde_json::Value = contract
+            .view(view)
+            .args_json(json!({ "hashlock": &hashlock }))
+            .await?
+            .json()?;
+        assert!(result.is_null(), "{} returned {}", view, result);
+    }
+    let result: serde_json::Value = contract
+        .view("is_escrow_claimable_by")
+        .args_json(json!({ "hashlock": &hashlock, "account_id": caller.id() }))
+        .await?
+        .json()?;
+    assert!(result.is_null());
+
+    let outcome = caller
+        .call(contract.id(), "cancel")
+        .args_json(json!({ "hashlock": &hashlock }))
+        .max_gas()
+        .transact()
+        .await?;
+    common::assert_failure_contains(outcome, "ERR_ESCROW_NOT_FOUND");
+
+    let secret = base64::engine::general_purpose::STANDARD.encode(b"never created");
+    let outcome = caller
+        .call(contract.id(), "withdraw")
+        .args_json(json!({ "secret": &secret }))
+        .max_gas()
+        .transact()
+        .await?;
+    common::assert_failure_contains(outcome, "ERR_ESCROW_NOT_FOUND");
+
+    let outcome = caller
+        .call(contract.id(), "withdraw_escrow")
+        .args_json(json!({ "hashlock": &hashlock, "secret": &secret }))
+        .max_gas()
+        .transact()
+        .await?;
+    common::assert_failure_contains(outcome, "ERR_ESCROW_NOT_FOUND");
+
+    Ok(())
+}
@@ -0,0 +1,141 @@
+mod common;
+
+use anyhow::Result;
+use near_workspaces::types::{KeyType, NearToken, SecretKey};
+use serde_json::json;
+
+#[tokio::test]
+async fn test_registered_accounts_pagination() -> Result<()> {
+    let (worker, contract, _owner) = common::setup().await?;
+
+    let mut accounts = Vec::new();
+    for key_count in 1..=3 {
+        let account = worker.dev_create_account().await?;
+        let keys: Vec<_> = (0..key_count)
+            .map(|_| SecretKey::from_random(KeyType::ED25519).public_key())
+            .collect();
+        account
+            .call(contract.id(), "register_keys")
+            .args_json(json!({ "public_keys": keys }))
+            .transact()
+            .await?
+            .into_result()?;
+        accounts.push(account);
+    }
+
+    let first_page: Vec<String> = contract
+        .view("get_registered_accounts")
+        .args_json(json!({ "from_index": 0, "limit": 2 }))
+        .await?
+        .json()?;
+    let second_page: Vec<String> = contract
+        .view("get_registered_accounts")
+        .args_json(json!({ "from_index": 2, "limit": 2 }))
+        .await?
+        .json()?;
+    assert_eq!(first_page.len(), 2);
+    assert_eq!(second_page.len(), 1);
+    let all: Vec<String> = first_page.into_iter().chain(second_page).collect();
+    for account in &accounts {
+        assert!(all.contains(&account.id().to_string()));
+    }
+
+    for (expected, account) in (1..=3).zip(&accounts) {
+        let count: u32 = contract
+            .view("get_registered_key_count")
+            .args_json(json!({ "account_id": account.id() }))
+            .await?
+            .json()?;
+        assert_eq!(count, expected);
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_remove_all_keys() -> Result<()> {
+    let (worker, contract, _owner) = common::setup().await?;
+    let account = worker.dev_create_account().await?;
+
+    // Clearing with nothing registered is a no-op.
+    account
+        .call(contract.id(), "remove_all_keys")
+        .deposit(NearToken::from_yoctonear(1))
+        .transact()
+        .await?
+        .into_result()?;
+
+    let keys: Vec<_> = (0..3)
+        .map(|_| SecretKey::from_random(KeyType::ED25519).public_key())
+        .collect();
+    account
+        .call(contract.id(), "register_keys")
+        .args_json(json!({ "public_keys": keys }))
+        .transact()
+        .await?
+        .into_result()?;
+
+    let outcome = account
+        .call(contract.id(), "remove_all_keys")
+        .transact()
+        .await?;
+    assert!(outcome.is_failure());
+
+    account
+        .call(contract.id(), "remove_all_keys")
+        .deposit(NearToken::from_yoctonear(1))
+        .transact()
+        .await?
+        .into_result()?;
+
+    let remaining: Vec<String> = contract
+        .view("get_registered_keys")
+        .args_json(json!({ "account_id": account.id() }))
+        .await?
+        .json()?;
+    assert!(remaining.is_empty());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_admin_register_keys_for_several_accounts() -> Result<()> {
+    let (worker, contract, owner) = common::setup().await?;
+
+    let mut entries = Vec::new();
+    for _ in 0..3 {
+        let account = worker.dev_create_account().await?;
+        let keys = vec![
+            SecretKey::from_random(KeyType::ED25519).public_key(),
+            SecretKey::from_random(KeyType::ED25519).public_key(),
+        ];
+        entries.push((account.id().clone(), keys));
+    }
+
+    let outcome = worker
+        .dev_create_account()
+        .await?
+        .call(contract.id(), "admin_register_keys")
+        .args_json(json!({ "entries": entries }))
+        .transact()
+        .await?;
+    common::assert_failure_contains(outcome, "Only the owner can call this method");
+
+    owner
+        .call(contract.id(), "admin_register_keys")
+        .args_json(json!({ "entries": entries }))
+        .transact()
+        .await?
+        .into_result()?;
+
+    for (account_id, keys) in &entries {
+        let registered: serde_json::Value = contract
+            .view("get_registered_keys")
+            .args_json(json!({ "account_id": account_id }))
+            .await?
+            .json()?;
+        assert_eq!(registered, json!(keys));
+    }
+
+    Ok(())
+}
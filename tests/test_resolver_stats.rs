@@ -0,0 +1,138 @@
+mod common;
+
+use anyhow::Result;
+use near_workspaces::types::NearToken;
+use serde_json::json;
+
+#[tokio::test]
+async fn test_resolver_stats_count_fills_and_cancels() -> Result<()> {
+    let (worker, contract, owner) = common::setup().await?;
+    let token = worker.dev_create_account().await?;
+    let maker = worker.dev_create_account().await?;
+    let resolver = worker.dev_create_account().await?;
+
+    for secret in [&b"first"[..], &b"second"[..]] {
+        common::create_destination_escrow(
+            &contract,
+            &token,
+            &resolver,
+            json!({
+                "type": "CreateDestinationEscrow",
+                "hashlock": common::hashlock(secret),
+                "maker_id": maker.id(),
+                "timelocks": common::default_timelocks(),
+            }),
+            100,
+            NearToken::from_millinear(100),
+        )
+        .await?
+        .into_result()?;
+    }
+
+    let stats: serde_json::Value = contract
+        .view("get_resolver_stats")
+        .args_json(json!({ "account_id": resolver.id() }))
+        .await?
+        .json()?;
+    assert_eq!(
+        stats,
+        json!({ "filled": 2, "withdrawn": 0, "cancelled": 0 })
+    );
+
+    // The refund to the resolver fails against the stand-in token and falls back to the ledger,
+    // which still counts as a completed cancellation.
+    owner
+        .call(contract.id(), "add_relayer")
+        .args_json(json!({ "account_id": owner.id() }))
+        .transact()
+        .await?
+        .into_result()?;
+    owner
+        .call(contract.id(), "relayer_cancel")
+        .args_json(json!({ "hashlock": common::hashlock(b"first") }))
+        .max_gas()
+        .transact()
+        .await?
+        .into_result()?;
+
+    let stats: serde_json::Value = contract
+        .view("get_resolver_stats")
+        .args_json(json!({ "account_id": resolver.id() }))
+        .await?
+        .json()?;
+    assert_eq!(
+        stats,
+        json!({ "filled": 2, "withdrawn": 0, "cancelled": 1 })
+    );
+
+    let volume: String = contract
+        .view("get_resolver_volume")
+        .args_json(json!({ "account_id": resolver.id(), "token_id": token.id() }))
+        .await?
+        .json()?;
+    assert_eq!(volume, "0");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_max_active_escrows_per_resolver() -> Result<()> {
+    let (worker, contract, owner) = common::setup().await?;
+    let token = worker.dev_create_account().await?;
+    let maker = worker.dev_create_account().await?;
+    let resolver = worker.dev_create_account().await?;
+
+    let outcome = resolver
+        .call(contract.id(), "set_max_active_per_resolver")
+        .args_json(json!({ "max_active": 2 }))
+        .transact()
+        .await?;
+    common::assert_failure_contains(outcome, "Only the owner can call this method");
+
+    owner
+        .call(contract.id(), "set_max_active_per_resolver")
+        .args_json(json!({ "max_active": 2 }))
+        .transact()
+        .await?
+        .into_result()?;
+
+    let create = |secret: &'static [u8]| {
+        common::create_destination_escrow(
+            &contract,
+            &token,
+            &resolver,
+            json!({
+                "type": "CreateDestinationEscrow",
+                "hashlock": common::hashlock(secret),
+                "maker_id": maker.id(),
+                "timelocks": common::default_timelocks(),
+            }),
+            100,
+            NearToken::from_millinear(100),
+        )
+    };
+    create(b"first").await?.into_result()?;
+    create(b"second").await?.into_result()?;
+    common::assert_failure_contains(
+        create(b"third").await?,
+        "Resolver has too many active escrows",
+    );
+
+    // Settling one escrow frees a slot.
+    owner
+        .call(contract.id(), "add_relayer")
+        .args_json(json!({ "account_id": owner.id() }))
+        .transact()
+        .await?
+        .into_result()?;
+    owner
+        .call(contract.id(), "relayer_cancel")
+        .args_json(json!({ "hashlock": common::hashlock(b"first") }))
+        .max_gas()
+        .transact()
+        .await?
+        .into_result()?;
+    create(b"third").await?.into_result()?;
+
+    Ok(())
+}
@@ -0,0 +1,1124 @@
+mod common;
+
+use anyhow::Result;
+use base64::Engine;
+use near_workspaces::types::{KeyType, NearToken, SecretKey};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[tokio::test]
+async fn test_implicit_maker_key_does_not_require_registration() -> Result<()> {
+    let (worker, contract, _owner) = common::setup().await?;
+    let resolver = worker.dev_create_account().await?;
+    let token = worker.dev_create_account().await?;
+
+    let maker_key = SecretKey::from_random(KeyType::ED25519).public_key();
+    let implicit_maker_id = to_hex(maker_key.key_data());
+    let other_key = SecretKey::from_random(KeyType::ED25519).public_key();
+
+    let order = json!({
+        "nonce": 1,
+        "maker_id": implicit_maker_id,
+        "asset_id": token.id(),
+        "amount": "100",
+        "hashlock": common::hashlock(b"implicit"),
+        "timelocks": common::default_timelocks(),
+        "deadline": u64::MAX,
+    });
+    let bogus_signature = base64::engine::general_purpose::STANDARD.encode([7u8; 64]);
+
+    // A key that is neither registered nor derived from the implicit id is rejected up front.
+    let outcome = resolver
+        .call(contract.id(), "initiate_source_escrow")
+        .args_json(json!({
+            "params": order,
+            "signature": bogus_signature,
+            "public_key": other_key,
+        }))
+        .deposit(NearToken::from_millinear(100))
+        .max_gas()
+        .transact()
+        .await?;
+    common::assert_failure_contains(outcome, "Public key not registered for maker");
+
+    // The implicit key passes the registration check and reaches signature verification.
+    let outcome = resolver
+        .call(contract.id(), "initiate_source_escrow")
+        .args_json(json!({
+            "params": order,
+            "signature": bogus_signature,
+            "public_key": maker_key,
+        }))
+        .deposit(NearToken::from_millinear(100))
+        .max_gas()
+        .transact()
+        .await?;
+    common::assert_failure_contains(outcome, "Signature verification failed");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_invalidated_nonce_cannot_be_filled() -> Result<()> {
+    let (worker, contract, _owner) = common::setup().await?;
+    let maker = worker.dev_create_account().await?;
+    let other_maker = worker.dev_create_account().await?;
+    let resolver = worker.dev_create_account().await?;
+    let token = worker.dev_create_account().await?;
+
+    let maker_key = SecretKey::from_random(KeyType::ED25519).public_key();
+    maker
+        .call(contract.id(), "register_keys")
+        .args_json(json!({ "public_keys": [maker_key] }))
+        .transact()
+        .await?
+        .into_result()?;
+
+    maker
+        .call(contract.id(), "invalidate_nonce")
+        .args_json(json!({ "nonce": 7 }))
+        .transact()
+        .await?
+        .into_result()?;
+
+    // Nonces are namespaced per maker.
+    let used: bool = contract
+        .view("is_nonce_used")
+        .args_json(json!({ "maker_id": maker.id(), "nonce": 7 }))
+        .await?
+        .json()?;
+    assert!(used);
+    let used: bool = contract
+        .view("is_nonce_used")
+        .args_json(json!({ "maker_id": other_maker.id(), "nonce": 7 }))
+        .await?
+        .json()?;
+    assert!(!used);
+
+    let outcome = resolver
+        .call(contract.id(), "initiate_source_escrow")
+        .args_json(json!({
+            "params": {
+                "nonce": 7,
+                "maker_id": maker.id(),
+                "asset_id": token.id(),
+                "amount": "100",
+                "hashlock": common::hashlock(b"retracted"),
+                "timelocks": common::default_timelocks(),
+                "deadline": u64::MAX,
+            },
+            "signature": base64::engine::general_purpose::STANDARD.encode([7u8; 64]),
+            "public_key": maker_key,
+        }))
+        .deposit(NearToken::from_millinear(100))
+        .max_gas()
+        .transact()
+        .await?;
+    common::assert_failure_contains(outcome, "Nonce already used");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_order_bound_to_another_taker_is_rejected() -> Result<()> {
+    let (worker, contract, _owner) = common::setup().await?;
+    let maker = worker.dev_create_account().await?;
+    let bound_resolver = worker.dev_create_account().await?;
+    let other_resolver = worker.dev_create_account().await?;
+    let token = worker.dev_create_account().await?;
+
+    let outcome = other_resolver
+        .call(contract.id(), "initiate_source_escrow")
+        .args_json(json!({
+            "params": {
+                "nonce": 1,
+                "maker_id": maker.id(),
+                "taker_id": bound_resolver.id(),
+                "asset_id": token.id(),
+                "amount": "100",
+                "hashlock": common::hashlock(b"bound"),
+                "timelocks": common::default_timelocks(),
+                "deadline": u64::MAX,
+            },
+            "signature": base64::engine::general_purpose::STANDARD.encode([7u8; 64]),
+            "public_key": SecretKey::from_random(KeyType::ED25519).public_key(),
+        }))
+        .deposit(NearToken::from_millinear(100))
+        .max_gas()
+        .transact()
+        .await?;
+    common::assert_failure_contains(outcome, "Order is bound to a different taker");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_order_taker_allowlist() -> Result<()> {
+    let (worker, contract, _owner) = common::setup().await?;
+    let maker = worker.dev_create_account().await?;
+    let listed_resolver = worker.dev_create_account().await?;
+    let other_resolver = worker.dev_create_account().await?;
+    let token = worker.dev_create_account().await?;
+
+    let args = json!({
+        "params": {
+            "nonce": 1,
+            "maker_id": maker.id(),
+            "taker_allowlist": [listed_resolver.id()],
+            "asset_id": token.id(),
+            "amount": "100",
+            "hashlock": common::hashlock(b"allowlist"),
+            "timelocks": common::default_timelocks(),
+            "deadline": u64::MAX,
+        },
+        "signature": base64::engine::general_purpose::STANDARD.encode([7u8; 64]),
+        "public_key": SecretKey::from_random(KeyType::ED25519).public_key(),
+    });
+
+    let outcome = other_resolver
+        .call(contract.id(), "initiate_source_escrow")
+        .args_json(args.clone())
+        .deposit(NearToken::from_millinear(100))
+        .max_gas()
+        .transact()
+        .await?;
+    common::assert_failure_contains(outcome, "Resolver is not in the order's taker allowlist");
+
+    // An allowlisted resolver gets past the taker check and only fails on the unregistered key.
+    let outcome = listed_resolver
+        .call(contract.id(), "initiate_source_escrow")
+        .args_json(args)
+        .deposit(NearToken::from_millinear(100))
+        .max_gas()
+        .transact()
+        .await?;
+    common::assert_failure_contains(outcome, "Public key not registered for maker");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_order_message_bytes_and_hash_views() -> Result<()> {
+    let (worker, contract, _owner) = common::setup().await?;
+    let maker = worker.dev_create_account().await?;
+    let token = worker.dev_create_account().await?;
+
+    let order = json!({
+        "nonce": 7,
+        "maker_id": maker.id(),
+        "asset_id": token.id(),
+        "amount": "100",
+        "hashlock": common::hashlock(b"order-hash"),
+        "timelocks": common::default_timelocks(),
+        "deadline": u64::MAX,
+    });
+
+    let message: String = contract
+        .view("get_order_message_bytes")
+        .args_json(json!({ "order": order }))
+        .await?
+        .json()?;
+    let hash: String = contract
+        .view("get_order_hash")
+        .args_json(json!({ "order": order }))
+        .await?
+        .json()?;
+
+    let message = base64::engine::general_purpose::STANDARD.decode(message)?;
+    let hash = base64::engine::general_purpose::STANDARD.decode(hash)?;
+
+    // Borsh layout: u128 nonce (LE), then the length-prefixed maker id.
+    let maker_id = maker.id().as_str().as_bytes();
+    assert_eq!(&message[..16], &7u128.to_le_bytes());
+    assert_eq!(&message[16..20], &(maker_id.len() as u32).to_le_bytes());
+    assert_eq!(&message[20..20 + maker_id.len()], maker_id);
+    assert_eq!(hash, Sha256::digest(&message).to_vec());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_order_past_deadline_is_rejected() -> Result<()> {
+    let (worker, contract, _owner) = common::setup().await?;
+    let maker = worker.dev_create_account().await?;
+    let resolver = worker.dev_create_account().await?;
+    let token = worker.dev_create_account().await?;
+
+    let maker_key = SecretKey::from_random(KeyType::ED25519).public_key();
+    maker
+        .call(contract.id(), "register_keys")
+        .args_json(json!({ "public_keys": [maker_key] }))
+        .transact()
+        .await?
+        .into_result()?;
+
+    // The nonce is fresh, but the order expired a moment after genesis.
+    let outcome = resolver
+        .call(contract.id(), "initiate_source_escrow")
+        .args_json(json!({
+            "params": {
+                "nonce": 1,
+                "maker_id": maker.id(),
+                "asset_id": token.id(),
+                "amount": "100",
+                "hashlock": common::hashlock(b"expired"),
+                "timelocks": common::default_timelocks(),
+                "deadline": 1,
+            },
+            "signature": base64::engine::general_purpose::STANDARD.encode([7u8; 64]),
+            "public_key": maker_key,
+        }))
+        .deposit(NearToken::from_millinear(100))
+        .max_gas()
+        .transact()
+        .await?;
+    common::assert_failure_contains(outcome, "Order deadline has passed");
+
+    let used: bool = contract
+        .view("is_nonce_used")
+        .args_json(json!({ "maker_id": maker.id(), "nonce": 1 }))
+        .await?
+        .json()?;
+    assert!(!used);
+
+    Ok(())
+}
+
+/// Registers `signer` as a key of `maker` and funds the maker's ledger so its orders can be filled.
+async fn signed_order_setup(
+    contract: &near_workspaces::Contract,
+    maker: &near_workspaces::Account,
+    token: &near_workspaces::Account,
+    signer: &common::OrderSigner,
+) -> Result<()> {
+    maker
+        .call(contract.id(), "register_keys")
+        .args_json(json!({ "public_keys": [signer.public_key()] }))
+        .transact()
+        .await?
+        .into_result()?;
+    common::deposit(contract, token, maker, 1_000).await?;
+    Ok(())
+}
+
+fn order(
+    maker: &near_workspaces::Account,
+    token: &near_workspaces::Account,
+    nonce: u128,
+    secret: &[u8],
+) -> serde_json::Value {
+    json!({
+        "nonce": nonce,
+        "maker_id": maker.id(),
+        "asset_id": token.id(),
+        "amount": "100",
+        "hashlock": common::hashlock(secret),
+        "timelocks": common::default_timelocks(),
+        "deadline": u64::MAX,
+    })
+}
+
+#[tokio::test]
+async fn test_valid_signature_with_unregistered_key_is_rejected() -> Result<()> {
+    let (worker, contract, _owner) = common::setup().await?;
+    let maker = worker.dev_create_account().await?;
+    let resolver = worker.dev_create_account().await?;
+    let token = worker.dev_create_account().await?;
+    signed_order_setup(
+        &contract,
+        &maker,
+        &token,
+        &common::OrderSigner::from_seed(1),
+    )
+    .await?;
+
+    // Correctly signed, but with a key the maker never registered.
+    let stranger = common::OrderSigner::from_seed(2);
+    let params = order(&maker, &token, 1, b"unregistered");
+    let outcome = resolver
+        .call(contract.id(), "initiate_source_escrow")
+        .args_json(json!({
+            "params": params,
+            "signature": stranger.sign_order(&contract, &params).await?,
+            "public_key": stranger.public_key(),
+        }))
+        .deposit(NearToken::from_millinear(100))
+        .max_gas()
+        .transact()
+        .await?;
+    common::assert_failure_contains(outcome, "Public key not registered for maker");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_tampered_amount_fails_verification() -> Result<()> {
+    let (worker, contract, _owner) = common::setup().await?;
+    let maker = worker.dev_create_account().await?;
+    let resolver = worker.dev_create_account().await?;
+    let token = worker.dev_create_account().await?;
+    let signer = common::OrderSigner::from_seed(1);
+    signed_order_setup(&contract, &maker, &token, &signer).await?;
+
+    let mut params = order(&maker, &token, 1, b"tampered");
+    let signature = signer.sign_order(&contract, &params).await?;
+    params["amount"] = json!("900");
+    let outcome = resolver
+        .call(contract.id(), "initiate_source_escrow")
+        .args_json(json!({
+            "params": params,
+            "signature": signature,
+            "public_key": signer.public_key(),
+        }))
+        .deposit(NearToken::from_millinear(100))
+        .max_gas()
+        .transact()
+        .await?;
+    common::assert_failure_contains(outcome, "Signature verification failed");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_consumed_nonce_cannot_be_reused() -> Result<()> {
+    let (worker, contract, _owner) = common::setup().await?;
+    let maker = worker.dev_create_account().await?;
+    let resolver = worker.dev_create_account().await?;
+    let token = worker.dev_create_account().await?;
+    let signer = common::OrderSigner::from_seed(1);
+    signed_order_setup(&contract, &maker, &token, &signer).await?;
+
+    let first = order(&maker, &token, 1, b"first fill");
+    resolver
+        .call(contract.id(), "initiate_source_escrow")
+        .args_json(json!({
+            "params": first,
+            "signature": signer.sign_order(&contract, &first).await?,
+            "public_key": signer.public_key(),
+        }))
+        .deposit(NearToken::from_millinear(100))
+        .max_gas()
+        .transact()
+        .await?
+        .into_result()?;
+
+    // A second, validly signed order under the same nonce is refused.
+    let second = order(&maker, &token, 1, b"second fill");
+    let outcome = resolver
+        .call(contract.id(), "initiate_source_escrow")
+        .args_json(json!({
+            "params": second,
+            "signature": signer.sign_order(&contract, &second).await?,
+            "public_key": signer.public_key(),
+        }))
+        .deposit(NearToken::from_millinear(100))
+        .max_gas()
+        .transact()
+        .await?;
+    common::assert_failure_contains(outcome, "Nonce already used");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_random_signature_bytes_are_rejected() -> Result<()> {
+    let (worker, contract, _owner) = common::setup().await?;
+    let maker = worker.dev_create_account().await?;
+    let resolver = worker.dev_create_account().await?;
+    let token = worker.dev_create_account().await?;
+    let signer = common::OrderSigner::from_seed(1);
+    signed_order_setup(&contract, &maker, &token, &signer).await?;
+
+    let random_bytes: [u8; 64] =
+        std::array::from_fn(|i| (i as u8).wrapping_mul(37).wrapping_add(11));
+    let outcome = resolver
+        .call(contract.id(), "initiate_source_escrow")
+        .args_json(json!({
+            "params": order(&maker, &token, 1, b"random"),
+            "signature": base64::engine::general_purpose::STANDARD.encode(random_bytes),
+            "public_key": signer.public_key(),
+        }))
+        .deposit(NearToken::from_millinear(100))
+        .max_gas()
+        .transact()
+        .await?;
+    common::assert_failure_contains(outcome, "Signature verification failed");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_function_call_key_signs_orders_for_this_contract() -> Result<()> {
+    let (worker, contract, _owner) = common::setup().await?;
+    let maker = worker.dev_create_account().await?;
+    let resolver = worker.dev_create_account().await?;
+    let token = worker.dev_create_account().await?;
+    common::deposit(&contract, &token, &maker, 1_000).await?;
+
+    let scoped = common::OrderSigner::from_seed(1);
+    let unrelated = common::OrderSigner::from_seed(2);
+
+    // A function-call key scoped to another contract cannot be registered.
+    let outcome = maker
+        .call(contract.id(), "register_keys")
+        .args_json(json!({
+            "public_keys": [scoped.public_key()],
+            "permission": { "FunctionCall": { "receiver_id": token.id(), "method_names": [] } },
+        }))
+        .transact()
+        .await?;
+    common::assert_failure_contains(
+        outcome,
+        "Function-call keys must be scoped to this contract",
+    );
+
+    for (signer, method) in [
+        (&scoped, "initiate_source_escrow"),
+        (&unrelated, "withdraw_deposit"),
+    ] {
+        maker
+            .call(contract.id(), "register_keys")
+            .args_json(json!({
+                "public_keys": [signer.public_key()],
+                "permission": {
+                    "FunctionCall": { "receiver_id": contract.id(), "method_names": [method] },
+                },
+            }))
+            .transact()
+            .await?
+            .into_result()?;
+    }
+    let permission: serde_json::Value = contract
+        .view("get_key_permission")
+        .args_json(json!({ "account_id": maker.id(), "public_key": scoped.public_key() }))
+        .await?
+        .json()?;
+    assert_eq!(
+        permission,
+        json!({
+            "FunctionCall": {
+                "receiver_id": contract.id(),
+                "method_names": ["initiate_source_escrow"],
+            }
+        })
+    );
+
+    let params = order(&maker, &token, 1, b"scoped");
+    resolver
+        .call(contract.id(), "initiate_source_escrow")
+        .args_json(json!({
+            "params": params,
+            "signature": scoped.sign_order(&contract, &params).await?,
+            "public_key": scoped.public_key(),
+        }))
+        .deposit(NearToken::from_millinear(100))
+        .max_gas()
+        .transact()
+        .await?
+        .into_result()?;
+
+    // A key scoped to other methods of this contract may not authorize orders.
+    let params = order(&maker, &token, 2, b"unrelated");
+    let outcome = resolver
+        .call(contract.id(), "initiate_source_escrow")
+        .args_json(json!({
+            "params": params,
+            "signature": unrelated.sign_order(&contract, &params).await?,
+            "public_key": unrelated.public_key(),
+        }))
+        .deposit(NearToken::from_millinear(100))
+        .max_gas()
+        .transact()
+        .await?;
+    common::assert_failure_contains(outcome, "Key is not permitted to sign orders");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_sequential_nonces_advance_a_high_water_mark() -> Result<()> {
+    let (worker, contract, _owner) = common::setup().await?;
+    let maker = worker.dev_create_account().await?;
+    let resolver = worker.dev_create_account().await?;
+    let token = worker.dev_create_account().await?;
+    let signer = common::OrderSigner::from_seed(1);
+    signed_order_setup(&contract, &maker, &token, &signer).await?;
+
+    maker
+        .call(contract.id(), "bump_nonce")
+        .args_json(json!({ "nonce": 10 }))
+        .transact()
+        .await?
+        .into_result()?;
+    let outcome = maker
+        .call(contract.id(), "bump_nonce")
+        .args_json(json!({ "nonce": 10 }))
+        .transact()
+        .await?;
+    common::assert_failure_contains(outcome, "Nonce must increase");
+
+    let fill = |nonce: u128, secret: &'static [u8]| {
+        let params = order(&maker, &token, nonce, secret);
+        let resolver = &resolver;
+        let contract = &contract;
+        let signer = &signer;
+        async move {
+            Ok::<_, anyhow::Error>(
+                resolver
+                    .call(contract.id(), "initiate_source_escrow")
+                    .args_json(json!({
+                        "params": params,
+                        "signature": signer.sign_order(contract, &params).await?,
+                        "public_key": signer.public_key(),
+                    }))
+                    .deposit(NearToken::from_millinear(100))
+                    .max_gas()
+                    .transact()
+                    .await?,
+            )
+        }
+    };
+
+    // Nonces at or below the mark are spent; a higher one is accepted and advances the mark.
+    common::assert_failure_contains(fill(10, b"at the mark").await?, "Nonce already used");
+    fill(12, b"above the mark").await?.into_result()?;
+    let high_water: Option<String> = contract
+        .view("get_nonce_high_water")
+        .args_json(json!({ "maker_id": maker.id() }))
+        .await?
+        .json()?;
+    assert_eq!(high_water.as_deref(), Some("12"));
+
+    // Skipped and replayed nonces are both below the new mark.
+    let used: bool = contract
+        .view("is_nonce_used")
+        .args_json(json!({ "maker_id": maker.id(), "nonce": 11 }))
+        .await?
+        .json()?;
+    assert!(used);
+    common::assert_failure_contains(fill(11, b"skipped").await?, "Nonce already used");
+    common::assert_failure_contains(fill(12, b"replayed").await?, "Nonce already used");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_retry_of_a_filled_order_reports_the_existing_escrow() -> Result<()> {
+    let (worker, contract, _owner) = common::setup().await?;
+    let maker = worker.dev_create_account().await?;
+    let resolver = worker.dev_create_account().await?;
+    let other_resolver = worker.dev_create_account().await?;
+    let token = worker.dev_create_account().await?;
+    let signer = common::OrderSigner::from_seed(1);
+    signed_order_setup(&contract, &maker, &token, &signer).await?;
+
+    let params = order(&maker, &token, 1, b"retried");
+    let signature = signer.sign_order(&contract, &params).await?;
+    let args = json!({
+        "params": params,
+        "signature": signature,
+        "public_key": signer.public_key(),
+    });
+    resolver
+        .call(contract.id(), "initiate_source_escrow")
+        .args_json(&args)
+        .deposit(NearToken::from_millinear(100))
+        .max_gas()
+        .transact()
+        .await?
+        .into_result()?;
+
+    // The filling resolver learns its escrow exists; anyone else just sees a spent nonce.
+    let outcome = resolver
+        .call(contract.id(), "initiate_source_escrow")
+        .args_json(&args)
+        .deposit(NearToken::from_millinear(100))
+        .max_gas()
+        .transact()
+        .await?;
+    common::assert_failure_contains(
+        outcome,
+        &format!(
+            "Order already filled by this resolver: hashlock='{}'",
+            common::hashlock(b"retried")
+        ),
+    );
+
+    let outcome = other_resolver
+        .call(contract.id(), "initiate_source_escrow")
+        .args_json(&args)
+        .deposit(NearToken::from_millinear(100))
+        .max_gas()
+        .transact()
+        .await?;
+    common::assert_failure_contains(outcome, "Nonce already used");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_source_and_destination_escrows_can_share_a_hashlock() -> Result<()> {
+    let (worker, contract, _owner) = common::setup().await?;
+    let maker = worker.dev_create_account().await?;
+    let resolver = worker.dev_create_account().await?;
+    let token = worker.dev_create_account().await?;
+    let signer = common::OrderSigner::from_seed(1);
+    signed_order_setup(&contract, &maker, &token, &signer).await?;
+
+    let params = order(&maker, &token, 1, b"both legs");
+    let signature = signer.sign_order(&contract, &params).await?;
+    resolver
+        .call(contract.id(), "initiate_source_escrow")
+        .args_json(json!({
+            "params": params,
+            "signature": signature,
+            "public_key": signer.public_key(),
+        }))
+        .deposit(NearToken::from_millinear(100))
+        .max_gas()
+        .transact()
+        .await?
+        .into_result()?;
+    let hashlock = common::hashlock(b"both legs");
+    common::create_destination_escrow(
+        &contract,
+        &token,
+        &resolver,
+        json!({
+            "type": "CreateDestinationEscrow",
+            "hashlock": hashlock,
+            "maker_id": maker.id(),
+            "timelocks": common::default_timelocks(),
+        }),
+        100,
+        NearToken::from_millinear(100),
+    )
+    .await?
+    .into_result()?;
+
+    // Both escrows are stored side by side under their own escrow ids.
+    let history: Vec<serde_json::Value> = contract
+        .view("get_account_escrow_history")
+        .args_json(json!({ "account_id": maker.id(), "from_index": 0, "limit": 10 }))
+        .await?
+        .json()?;
+    assert_eq!(history.len(), 2);
+    for entry in &history {
+        let escrow = &entry["escrow"];
+        assert_eq!(escrow["hashlock_b58"], hashlock);
+        let by_id: serde_json::Value = contract
+            .view("get_escrow")
+            .args_json(json!({ "hashlock": escrow["escrow_id"] }))
+            .await?
+            .json()?;
+        assert_eq!(by_id["is_source"], escrow["is_source"]);
+    }
+    assert_ne!(
+        history[0]["escrow"]["is_source"],
+        history[1]["escrow"]["is_source"]
+    );
+
+    // The bare hashlock no longer identifies a single escrow.
+    let err = contract
+        .view("get_escrow")
+        .args_json(json!({ "hashlock": hashlock }))
+        .await
+        .unwrap_err();
+    assert!(err
+        .to_string()
+        .contains("Hashlock is shared by a source and a destination escrow"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_non_base64_signature_is_rejected() -> Result<()> {
+    let (worker, contract, _owner) = common::setup().await?;
+    let maker = worker.dev_create_account().await?;
+    let resolver = worker.dev_create_account().await?;
+    let token = worker.dev_create_account().await?;
+    let signer = common::OrderSigner::from_seed(1);
+
+    let outcome = resolver
+        .call(contract.id(), "initiate_source_escrow")
+        .args_json(json!({
+            "params": order(&maker, &token, 1, b"garbled"),
+            "signature": "not base64!",
+            "public_key": signer.public_key(),
+        }))
+        .deposit(NearToken::from_millinear(100))
+        .max_gas()
+        .transact()
+        .await?;
+    common::assert_failure_contains(outcome, "Invalid base64 signature");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_orders_and_destination_escrows_fall_back_to_default_timelocks() -> Result<()> {
+    let (worker, contract, owner) = common::setup().await?;
+    let maker = worker.dev_create_account().await?;
+    let resolver = worker.dev_create_account().await?;
+    let token = worker.dev_create_account().await?;
+    let signer = common::OrderSigner::from_seed(1);
+    signed_order_setup(&contract, &maker, &token, &signer).await?;
+
+    let without_timelocks = |nonce: u128, secret: &[u8]| {
+        let mut params = order(&maker, &token, nonce, secret);
+        params.as_object_mut().unwrap().remove("timelocks");
+        params
+    };
+    let fill = |params: serde_json::Value, signature: String| {
+        resolver
+            .call(contract.id(), "initiate_source_escrow")
+            .args_json(json!({
+                "params": params,
+                "signature": signature,
+                "public_key": signer.public_key(),
+            }))
+            .deposit(NearToken::from_millinear(100))
+            .max_gas()
+            .transact()
+    };
+
+    // Without configured defaults, an order must spell out its timelocks.
+    let outcome = fill(without_timelocks(1, b"no defaults"), "AAAA".to_string()).await?;
+    common::assert_failure_contains(outcome, "No default timelocks are configured");
+
+    owner
+        .call(contract.id(), "set_default_timelocks")
+        .args_json(json!({ "timelocks": common::default_timelocks() }))
+        .transact()
+        .await?
+        .into_result()?;
+
+    let params = without_timelocks(1, b"defaulted");
+    let signature = signer.sign_order(&contract, &params).await?;
+    fill(params, signature).await?.into_result()?;
+    let escrow: serde_json::Value = contract
+        .view("get_escrow")
+        .args_json(json!({ "hashlock": common::hashlock(b"defaulted") }))
+        .await?
+        .json()?;
+    assert_eq!(escrow["timelocks"]["delays"], common::default_timelocks());
+
+    // The signature covers the defaults at signing time, so changing them invalidates it.
+    let params = without_timelocks(2, b"stale defaults");
+    let signature = signer.sign_order(&contract, &params).await?;
+    let mut new_defaults = common::default_timelocks();
+    new_defaults["src_public_cancellation_delay"] = json!(1200);
+    owner
+        .call(contract.id(), "set_default_timelocks")
+        .args_json(json!({ "timelocks": new_defaults }))
+        .transact()
+        .await?
+        .into_result()?;
+    common::assert_failure_contains(
+        fill(params, signature).await?,
+        "Signature verification failed",
+    );
+
+    common::create_destination_escrow(
+        &contract,
+        &token,
+        &resolver,
+        json!({
+            "type": "CreateDestinationEscrow",
+            "hashlock": common::hashlock(b"defaulted destination"),
+            "maker_id": maker.id(),
+        }),
+        100,
+        NearToken::from_millinear(100),
+    )
+    .await?
+    .into_result()?;
+    let escrow: serde_json::Value = contract
+        .view("get_escrow")
+        .args_json(json!({ "hashlock": common::hashlock(b"defaulted destination") }))
+        .await?
+        .json()?;
+    assert_eq!(escrow["timelocks"]["delays"], new_defaults);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_signature_threshold_requires_enough_distinct_keys() -> Result<()> {
+    let (worker, contract, _owner) = common::setup().await?;
+    let maker = worker.dev_create_account().await?;
+    let resolver = worker.dev_create_account().await?;
+    let token = worker.dev_create_account().await?;
+    let signers = [1, 2, 3].map(common::OrderSigner::from_seed);
+    maker
+        .call(contract.id(), "register_keys")
+        .args_json(json!({
+            "public_keys": signers.iter().map(|signer| signer.public_key()).collect::<Vec<_>>(),
+        }))
+        .transact()
+        .await?
+        .into_result()?;
+    common::deposit(&contract, &token, &maker, 1_000).await?;
+
+    let outcome = maker
+        .call(contract.id(), "set_signature_threshold")
+        .args_json(json!({ "threshold": 4 }))
+        .transact()
+        .await?;
+    common::assert_failure_contains(outcome, "Signature threshold exceeds the registered keys");
+    maker
+        .call(contract.id(), "set_signature_threshold")
+        .args_json(json!({ "threshold": 2 }))
+        .transact()
+        .await?
+        .into_result()?;
+
+    let order = order(&maker, &token, 1, b"multisig");
+    let mut signatures = Vec::new();
+    for signer in &signers[..2] {
+        signatures.push((
+            signer.public_key(),
+            signer.sign_order(&contract, &order).await?,
+        ));
+    }
+    let initiate = |co_signatures: Vec<(String, String)>| {
+        resolver
+            .call(contract.id(), "initiate_source_escrow")
+            .args_json(json!({
+                "params": order,
+                "signature": signatures[0].1,
+                "public_key": signatures[0].0,
+                "options": { "co_signatures": co_signatures },
+            }))
+            .deposit(NearToken::from_millinear(100))
+            .max_gas()
+    };
+
+    let outcome = initiate(vec![]).transact().await?;
+    common::assert_failure_contains(outcome, "Not enough maker signatures");
+
+    // The same key signing twice still counts once.
+    let outcome = initiate(vec![signatures[0].clone()]).transact().await?;
+    common::assert_failure_contains(outcome, "Each signature must come from a different key");
+
+    initiate(vec![signatures[1].clone()])
+        .transact()
+        .await?
+        .into_result()?;
+    let escrow: serde_json::Value = contract
+        .view("get_escrow")
+        .args_json(json!({ "hashlock": common::hashlock(b"multisig") }))
+        .await?
+        .json()?;
+    assert_eq!(escrow["taker"], json!(resolver.id()));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_counterparty_tx_is_signed_and_recorded() -> Result<()> {
+    let (worker, contract, _owner) = common::setup().await?;
+    let maker = worker.dev_create_account().await?;
+    let resolver = worker.dev_create_account().await?;
+    let token = worker.dev_create_account().await?;
+    let signer = common::OrderSigner::from_seed(1);
+    signed_order_setup(&contract, &maker, &token, &signer).await?;
+
+    let counterparty_tx = format!("0x{}", "ab".repeat(32));
+    let mut signed = order(&maker, &token, 1, b"paired");
+    signed["counterparty_tx"] = json!(counterparty_tx);
+    let signature = signer.sign_order(&contract, &signed).await?;
+    let initiate = |params: &serde_json::Value| {
+        resolver
+            .call(contract.id(), "initiate_source_escrow")
+            .args_json(json!({
+                "params": params,
+                "signature": signature,
+                "public_key": signer.public_key(),
+            }))
+            .deposit(NearToken::from_millinear(100))
+            .max_gas()
+    };
+
+    // Pairing the escrow with a different counterparty transaction breaks the signature.
+    let mut tampered = signed.clone();
+    tampered["counterparty_tx"] = json!(format!("0x{}", "cd".repeat(32)));
+    let outcome = initiate(&tampered).transact().await?;
+    common::assert_failure_contains(outcome, "Signature verification failed");
+
+    initiate(&signed).transact().await?.into_result()?;
+    let escrow: serde_json::Value = contract
+        .view("get_escrow")
+        .args_json(json!({ "hashlock": common::hashlock(b"paired") }))
+        .await?
+        .json()?;
+    assert_eq!(escrow["counterparty_tx"], json!(counterparty_tx));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_posted_orders_can_be_listed_filled_cancelled_and_pruned() -> Result<()> {
+    let (worker, contract, _owner) = common::setup().await?;
+    let maker = worker.dev_create_account().await?;
+    let resolver = worker.dev_create_account().await?;
+    let token = worker.dev_create_account().await?;
+    let signer = common::OrderSigner::from_seed(1);
+    signed_order_setup(&contract, &maker, &token, &signer).await?;
+
+    let post = |order: serde_json::Value, signature: String| {
+        json!({
+            "order": order,
+            "signature": signature,
+            "public_key": signer.public_key(),
+        })
+    };
+
+    // Only the maker can post its order.
+    let first = order(&maker, &token, 1, b"posted first");
+    let first_signature = signer.sign_order(&contract, &first).await?;
+    let outcome = resolver
+        .call(contract.id(), "post_order")
+        .args_json(post(first.clone(), first_signature.clone()))
+        .deposit(NearToken::from_millinear(100))
+        .transact()
+        .await?;
+    common::assert_failure_contains(outcome, "Only the maker can post its order");
+
+    let second = order(&maker, &token, 2, b"posted second");
+    let second_signature = signer.sign_order(&contract, &second).await?;
+    for args in [post(first, first_signature), post(second, second_signature)] {
+        maker
+            .call(contract.id(), "post_order")
+            .args_json(args)
+            .deposit(NearToken::from_millinear(100))
+            .transact()
+            .await?
+            .into_result()?;
+    }
+    let open: serde_json::Value = contract
+        .view("get_open_orders")
+        .args_json(json!({ "from_index": 0, "limit": 10 }))
+        .await?
+        .json()?;
+    assert_eq!(open["orders"].as_array().unwrap().len(), 2);
+    assert!(open["next_index"].is_null());
+    let first_page: serde_json::Value = contract
+        .view("get_open_orders")
+        .args_json(json!({ "from_index": 0, "limit": 1 }))
+        .await?
+        .json()?;
+    assert_eq!(first_page["orders"].as_array().unwrap().len(), 1);
+    assert_eq!(first_page["next_index"], 1);
+
+    // Cancelling removes the order and retracts its nonce.
+    maker
+        .call(contract.id(), "cancel_order")
+        .args_json(json!({ "nonce": 2 }))
+        .transact()
+        .await?
+        .into_result()?;
+    let used: bool = contract
+        .view("is_nonce_used")
+        .args_json(json!({ "maker_id": maker.id(), "nonce": 2 }))
+        .await?
+        .json()?;
+    assert!(used);
+
+    // A resolver fills the remaining order straight from the book.
+    resolver
+        .call(contract.id(), "fill_posted_order")
+        .args_json(json!({ "maker_id": maker.id(), "nonce": 1 }))
+        .deposit(NearToken::from_millinear(100))
+        .max_gas()
+        .transact()
+        .await?
+        .into_result()?;
+    let open: serde_json::Value = contract
+        .view("get_open_orders")
+        .args_json(json!({ "from_index": 0, "limit": 10 }))
+        .await?
+        .json()?;
+    assert!(open["orders"].as_array().unwrap().is_empty());
+
+    // An expired order drops out of the listing and can be pruned by anyone.
+    let now: String = contract.view("now").await?.json()?;
+    let mut expiring = order(&maker, &token, 3, b"posted expiring");
+    expiring["deadline"] = json!(now.parse::<u64>()? + 5_000_000_000);
+    let expiring_signature = signer.sign_order(&contract, &expiring).await?;
+    maker
+        .call(contract.id(), "post_order")
+        .args_json(post(expiring, expiring_signature))
+        .deposit(NearToken::from_millinear(100))
+        .transact()
+        .await?
+        .into_result()?;
+    worker.fast_forward(20).await?;
+    let open: serde_json::Value = contract
+        .view("get_open_orders")
+        .args_json(json!({ "from_index": 0, "limit": 10 }))
+        .await?
+        .json()?;
+    assert!(open["orders"].as_array().unwrap().is_empty());
+    let pruned: u32 = resolver
+        .call(contract.id(), "prune_posted_orders")
+        .args_json(json!({ "from_index": 0, "limit": 10 }))
+        .transact()
+        .await?
+        .json()?;
+    assert_eq!(pruned, 1);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_source_escrow_records_its_order_hash() -> Result<()> {
+    let (worker, contract, _owner) = common::setup().await?;
+    let maker = worker.dev_create_account().await?;
+    let resolver = worker.dev_create_account().await?;
+    let token = worker.dev_create_account().await?;
+    let signer = common::OrderSigner::from_seed(1);
+    signed_order_setup(&contract, &maker, &token, &signer).await?;
+
+    let order = order(&maker, &token, 1, b"audited order");
+    resolver
+        .call(contract.id(), "initiate_source_escrow")
+        .args_json(json!({
+            "params": order,
+            "signature": signer.sign_order(&contract, &order).await?,
+            "public_key": signer.public_key(),
+        }))
+        .deposit(NearToken::from_millinear(100))
+        .max_gas()
+        .transact()
+        .await?
+        .into_result()?;
+
+    // The stored hash is the sha256 of the exact bytes the maker signed.
+    let message: String = contract
+        .view("get_order_message_bytes")
+        .args_json(json!({ "order": order }))
+        .await?
+        .json()?;
+    let message = base64::engine::general_purpose::STANDARD.decode(message)?;
+    let stored: Option<String> = contract
+        .view("get_escrow_order_hash")
+        .args_json(json!({ "hashlock": common::hashlock(b"audited order") }))
+        .await?
+        .json()?;
+    let stored = base64::engine::general_purpose::STANDARD.decode(stored.unwrap())?;
+    assert_eq!(stored, Sha256::digest(&message).to_vec());
+
+    let unknown: Option<String> = contract
+        .view("get_escrow_order_hash")
+        .args_json(json!({ "hashlock": common::hashlock(b"unknown") }))
+        .await?
+        .json()?;
+    assert!(unknown.is_none());
+
+    Ok(())
+}
@@ -0,0 +1,495 @@
+mod common;
+
+use anyhow::Result;
+use base64::Engine;
+use near_workspaces::types::NearToken;
+use serde_json::json;
+
+#[tokio::test]
+async fn test_overflowing_delays_are_rejected() -> Result<()> {
+    let (worker, contract, _owner) = common::setup().await?;
+    let token = worker.dev_create_account().await?;
+    let maker = worker.dev_create_account().await?;
+    let resolver = worker.dev_create_account().await?;
+
+    // Each delay is consistent with the others, but the last one overflows in nanoseconds.
+    for extreme in [u64::MAX, u64::MAX / 1_000_000_000] {
+        let mut timelocks = common::default_timelocks();
+        timelocks["src_public_cancellation_delay"] = json!(extreme);
+
+        let outcome = common::create_destination_escrow(
+            &contract,
+            &token,
+            &resolver,
+            json!({
+                "type": "CreateDestinationEscrow",
+                "hashlock": common::hashlock(b"overflow"),
+                "maker_id": maker.id(),
+                "timelocks": timelocks,
+            }),
+            100,
+            NearToken::from_millinear(100),
+        )
+        .await?;
+        common::assert_failure_contains(outcome, "Timelock delay is too large");
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_public_grace_delays_public_window() -> Result<()> {
+    let (worker, contract, owner) = common::setup().await?;
+    let token = worker.dev_create_account().await?;
+    let maker = worker.dev_create_account().await?;
+    let resolver = worker.dev_create_account().await?;
+    let stranger = worker.dev_create_account().await?;
+
+    let secret = b"graceful";
+    let mut timelocks = common::default_timelocks();
+    timelocks["dst_public_withdrawal_delay"] = json!(0);
+    common::create_destination_escrow(
+        &contract,
+        &token,
+        &resolver,
+        json!({
+            "type": "CreateDestinationEscrow",
+            "hashlock": common::hashlock(secret),
+            "maker_id": maker.id(),
+            "timelocks": timelocks,
+        }),
+        100,
+        NearToken::from_millinear(100),
+    )
+    .await?
+    .into_result()?;
+
+    // Only the owner may configure the grace.
+    let outcome = stranger
+        .call(contract.id(), "set_public_grace")
+        .args_json(json!({ "grace_seconds": 1000 }))
+        .transact()
+        .await?;
+    common::assert_failure_contains(outcome, "Only the owner can call this method");
+
+    owner
+        .call(contract.id(), "set_public_grace")
+        .args_json(json!({ "grace_seconds": 1000 }))
+        .transact()
+        .await?
+        .into_result()?;
+    let grace: u64 = contract.view("get_public_grace").await?.json()?;
+    assert_eq!(grace, 1000);
+
+    // Within the grace, a public caller is rejected even though the escrow's own delay has passed.
+    let outcome = stranger
+        .call(contract.id(), "withdraw")
+        .args_json(json!({ "secret": base64::engine::general_purpose::STANDARD.encode(secret) }))
+        .max_gas()
+        .transact()
+        .await?;
+    common::assert_failure_contains(outcome, "Public withdrawal period (dst) has not started");
+
+    let eligibility: serde_json::Value = contract
+        .view("who_can_act_now")
+        .args_json(json!({ "hashlock": common::hashlock(secret) }))
+        .await?
+        .json()?;
+    assert_eq!(eligibility["callers"], json!({ "Taker": resolver.id() }));
+
+    // Once the grace is lifted, the public window is open again.
+    owner
+        .call(contract.id(), "set_public_grace")
+        .args_json(json!({ "grace_seconds": 0 }))
+        .transact()
+        .await?
+        .into_result()?;
+    let eligibility: serde_json::Value = contract
+        .view("who_can_act_now")
+        .args_json(json!({ "hashlock": common::hashlock(secret) }))
+        .await?
+        .json()?;
+    assert_eq!(eligibility["callers"], "Anyone");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_scheduled_activation_shifts_windows() -> Result<()> {
+    let (worker, contract, _owner) = common::setup().await?;
+    let token = worker.dev_create_account().await?;
+    let maker = worker.dev_create_account().await?;
+    let resolver = worker.dev_create_account().await?;
+
+    let now = worker.view_block().await?.timestamp();
+    let activation_time = now + 1_000 * 1_000_000_000;
+    let hashlock = common::hashlock(b"scheduled");
+    common::create_destination_escrow(
+        &contract,
+        &token,
+        &resolver,
+        json!({
+            "type": "CreateDestinationEscrow",
+            "hashlock": hashlock,
+            "maker_id": maker.id(),
+            "timelocks": common::default_timelocks(),
+            "activation_time": activation_time,
+        }),
+        100,
+        NearToken::from_millinear(100),
+    )
+    .await?
+    .into_result()?;
+
+    let escrow: serde_json::Value = contract
+        .view("get_escrow")
+        .args_json(json!({ "hashlock": hashlock }))
+        .await?
+        .json()?;
+    assert_eq!(escrow["timelocks"]["created_at"], activation_time);
+
+    // A zero withdrawal delay would normally be open immediately, but the escrow is not live yet.
+    let state: String = contract
+        .view("get_escrow_state")
+        .args_json(json!({ "hashlock": hashlock }))
+        .await?
+        .json()?;
+    assert_eq!(state, "Open");
+
+    for (activation_time, expected) in [
+        (now - 1, "Activation time is in the past"),
+        (
+            now + 31 * 24 * 60 * 60 * 1_000_000_000,
+            "Activation time is too far in the future",
+        ),
+    ] {
+        let outcome = common::create_destination_escrow(
+            &contract,
+            &token,
+            &resolver,
+            json!({
+                "type": "CreateDestinationEscrow",
+                "hashlock": common::hashlock(b"badly scheduled"),
+                "maker_id": maker.id(),
+                "timelocks": common::default_timelocks(),
+                "activation_time": activation_time,
+            }),
+            100,
+            NearToken::from_millinear(100),
+        )
+        .await?;
+        common::assert_failure_contains(outcome, expected);
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_time_until_window_is_anchored_to_chain_time() -> Result<()> {
+    let (worker, contract, _owner) = common::setup().await?;
+    let token = worker.dev_create_account().await?;
+    let maker = worker.dev_create_account().await?;
+    let resolver = worker.dev_create_account().await?;
+
+    let before: String = contract.view("now").await?.json()?;
+
+    let mut pending = common::default_timelocks();
+    pending["dst_withdrawal_delay"] = json!(100);
+    pending["dst_public_withdrawal_delay"] = json!(200);
+    pending["dst_cancellation_delay"] = json!(300);
+    for (secret, timelocks) in [
+        (&b"open"[..], common::default_timelocks()),
+        (&b"pending"[..], pending),
+    ] {
+        common::create_destination_escrow(
+            &contract,
+            &token,
+            &resolver,
+            json!({
+                "type": "CreateDestinationEscrow",
+                "hashlock": common::hashlock(secret),
+                "maker_id": maker.id(),
+                "timelocks": timelocks,
+            }),
+            100,
+            NearToken::from_millinear(100),
+        )
+        .await?
+        .into_result()?;
+    }
+
+    // A zero withdrawal delay opens the window at creation, so it is already open (or opens this second).
+    let open: Option<i64> = contract
+        .view("time_until_window")
+        .args_json(json!({ "hashlock": common::hashlock(b"open") }))
+        .await?
+        .json()?;
+    assert!(open.unwrap() <= 0);
+
+    let pending: Option<i64> = contract
+        .view("time_until_window")
+        .args_json(json!({ "hashlock": common::hashlock(b"pending") }))
+        .await?
+        .json()?;
+    let pending = pending.unwrap();
+    assert!(pending > 0 && pending <= 100);
+
+    let unknown: Option<i64> = contract
+        .view("time_until_window")
+        .args_json(json!({ "hashlock": common::hashlock(b"unknown") }))
+        .await?
+        .json()?;
+    assert!(unknown.is_none());
+
+    let after: String = contract.view("now").await?.json()?;
+    assert!(after.parse::<u64>()? > before.parse::<u64>()?);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_windows_at_creation_time() -> Result<()> {
+    let (worker, contract, _owner) = common::setup().await?;
+    let token = worker.dev_create_account().await?;
+    let maker = worker.dev_create_account().await?;
+    let resolver = worker.dev_create_account().await?;
+    let stranger = worker.dev_create_account().await?;
+
+    let signer = common::OrderSigner::from_seed(1);
+    maker
+        .call(contract.id(), "register_keys")
+        .args_json(json!({ "public_keys": [signer.public_key()] }))
+        .transact()
+        .await?
+        .into_result()?;
+    common::deposit(&contract, &token, &maker, 100).await?;
+
+    // Only the private withdrawal delay is zero; every other window is still closed.
+    let secret = b"boundary";
+    let order = json!({
+        "nonce": 1,
+        "maker_id": maker.id(),
+        "asset_id": token.id(),
+        "amount": "100",
+        "hashlock": common::hashlock(secret),
+        "timelocks": common::default_timelocks(),
+        "deadline": u64::MAX,
+    });
+    resolver
+        .call(contract.id(), "initiate_source_escrow")
+        .args_json(json!({
+            "params": order,
+            "signature": signer.sign_order(&contract, &order).await?,
+            "public_key": signer.public_key(),
+        }))
+        .deposit(NearToken::from_millinear(100))
+        .max_gas()
+        .transact()
+        .await?
+        .into_result()?;
+
+    let encoded_secret = base64::engine::general_purpose::STANDARD.encode(secret);
+    let outcome = stranger
+        .call(contract.id(), "withdraw")
+        .args_json(json!({ "secret": encoded_secret }))
+        .max_gas()
+        .transact()
+        .await?;
+    common::assert_failure_contains(outcome, "Public withdrawal period (src) has not started");
+
+    let outcome = resolver
+        .call(contract.id(), "cancel")
+        .args_json(json!({ "hashlock": common::hashlock(secret) }))
+        .max_gas()
+        .transact()
+        .await?;
+    common::assert_failure_contains(outcome, "Private cancellation period (src) has not started");
+
+    let outcome = resolver
+        .call(contract.id(), "withdraw")
+        .args_json(json!({ "secret": encoded_secret }))
+        .max_gas()
+        .transact()
+        .await?;
+    assert!(outcome
+        .logs()
+        .iter()
+        .any(|log| log.starts_with("ESCROW_CLAIMED")));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_min_reveal_grace_boundary() -> Result<()> {
+    let (worker, contract, owner) = common::setup().await?;
+    let token = worker.dev_create_account().await?;
+    let maker = worker.dev_create_account().await?;
+    let resolver = worker.dev_create_account().await?;
+
+    // The default timelocks leave 600 - 120 = 480 seconds between the destination public
+    // withdrawal and the source cancellation.
+    for (min_reveal_grace, secret, accepted) in [
+        (480, &b"exactly enough"[..], true),
+        (481, &b"one second short"[..], false),
+    ] {
+        owner
+            .call(contract.id(), "set_min_reveal_grace")
+            .args_json(json!({ "grace_seconds": min_reveal_grace }))
+            .transact()
+            .await?
+            .into_result()?;
+        let outcome = common::create_destination_escrow(
+            &contract,
+            &token,
+            &resolver,
+            json!({
+                "type": "CreateDestinationEscrow",
+                "hashlock": common::hashlock(secret),
+                "maker_id": maker.id(),
+                "timelocks": common::default_timelocks(),
+            }),
+            100,
+            NearToken::from_millinear(100),
+        )
+        .await?;
+        if accepted {
+            outcome.into_result()?;
+        } else {
+            common::assert_failure_contains(
+                outcome,
+                "X-CHAIN: Reveal window before source cancellation is too short",
+            );
+        }
+    }
+
+    let outcome = resolver
+        .call(contract.id(), "set_min_reveal_grace")
+        .args_json(json!({ "grace_seconds": 0 }))
+        .transact()
+        .await?;
+    common::assert_failure_contains(outcome, "Only the owner can call this method");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_timelock_constraints_follow_owner_changes() -> Result<()> {
+    let (_worker, contract, owner) = common::setup().await?;
+
+    let constraints: serde_json::Value = contract.view("get_timelock_constraints").await?.json()?;
+    assert_eq!(
+        constraints,
+        json!({
+            "min_reveal_grace": 0,
+            "public_grace": 0,
+            "max_activation_horizon": 30 * 24 * 60 * 60,
+        })
+    );
+
+    owner
+        .call(contract.id(), "set_min_reveal_grace")
+        .args_json(json!({ "grace_seconds": 300 }))
+        .transact()
+        .await?
+        .into_result()?;
+    owner
+        .call(contract.id(), "set_public_grace")
+        .args_json(json!({ "grace_seconds": 60 }))
+        .transact()
+        .await?
+        .into_result()?;
+
+    let constraints: serde_json::Value = contract.view("get_timelock_constraints").await?.json()?;
+    assert_eq!(constraints["min_reveal_grace"], 300);
+    assert_eq!(constraints["public_grace"], 60);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_millisecond_delays_open_windows_at_millisecond_offsets() -> Result<()> {
+    let (worker, contract, _owner) = common::setup().await?;
+    let token = worker.dev_create_account().await?;
+    let maker = worker.dev_create_account().await?;
+    let resolver = worker.dev_create_account().await?;
+
+    // The default delays scaled to milliseconds, with sub-second withdrawal delays.
+    let millis = |withdrawal_delay: u64| {
+        json!({
+            "src_withdrawal_delay": 0,
+            "src_public_withdrawal_delay": 300_000,
+            "src_cancellation_delay": 600_000,
+            "src_public_cancellation_delay": 900_000,
+            "dst_withdrawal_delay": withdrawal_delay,
+            "dst_public_withdrawal_delay": 120_000,
+            "dst_cancellation_delay": 240_000,
+            "time_unit": "Milliseconds",
+        })
+    };
+    for (secret, withdrawal_delay) in [(&b"soon"[..], 3_500), (&b"later"[..], 90_500)] {
+        common::create_destination_escrow(
+            &contract,
+            &token,
+            &resolver,
+            json!({
+                "type": "CreateDestinationEscrow",
+                "hashlock": common::hashlock(secret),
+                "maker_id": maker.id(),
+                "timelocks": millis(withdrawal_delay),
+            }),
+            100,
+            NearToken::from_millinear(100),
+        )
+        .await?
+        .into_result()?;
+    }
+
+    let opens_at = |escrow: &serde_json::Value, delay_ms: u64| {
+        escrow["timelocks"]["created_at"].as_u64().unwrap() + delay_ms * 1_000_000
+    };
+    let later: serde_json::Value = contract
+        .view("get_escrow")
+        .args_json(json!({ "hashlock": common::hashlock(b"later") }))
+        .await?
+        .json()?;
+    assert_eq!(later["timelocks"]["delays"]["time_unit"], "Milliseconds");
+    let block = worker.view_block().await?;
+    let until: Option<i64> = contract
+        .view("time_until_window")
+        .args_json(json!({ "hashlock": common::hashlock(b"later") }))
+        .block_height(block.height())
+        .await?
+        .json()?;
+    assert_eq!(
+        until.unwrap(),
+        ((opens_at(&later, 90_500) - block.timestamp()) / 1_000_000_000) as i64
+    );
+
+    // The short window opens exactly 3.5 seconds after creation, not 3,500 seconds.
+    let soon: serde_json::Value = contract
+        .view("get_escrow")
+        .args_json(json!({ "hashlock": common::hashlock(b"soon") }))
+        .await?
+        .json()?;
+    let soon_opens_at = opens_at(&soon, 3_500);
+    for _ in 0..30 {
+        let block = worker.view_block().await?;
+        let state: String = contract
+            .view("get_escrow_state")
+            .args_json(json!({ "hashlock": common::hashlock(b"soon") }))
+            .block_height(block.height())
+            .await?
+            .json()?;
+        let expected = if block.timestamp() >= soon_opens_at {
+            "Withdrawable"
+        } else {
+            "Open"
+        };
+        assert_eq!(state, expected);
+        if block.timestamp() >= soon_opens_at {
+            return Ok(());
+        }
+        worker.fast_forward(1).await?;
+    }
+    panic!("The millisecond withdrawal window never opened");
+}
@@ -0,0 +1,449 @@
+mod common;
+
+use anyhow::Result;
+use base64::Engine;
+use near_workspaces::types::{Gas, NearToken};
+use serde_json::json;
+
+#[tokio::test]
+async fn test_low_gas_withdraw_fails_early() -> Result<()> {
+    let (worker, contract, _owner) = common::setup().await?;
+    let token = worker.dev_create_account().await?;
+    let maker = worker.dev_create_account().await?;
+    let resolver = worker.dev_create_account().await?;
+
+    let secret = b"underfunded";
+    common::create_destination_escrow(
+        &contract,
+        &token,
+        &resolver,
+        json!({
+            "type": "CreateDestinationEscrow",
+            "hashlock": common::hashlock(secret),
+            "maker_id": maker.id(),
+            "timelocks": common::default_timelocks(),
+        }),
+        100,
+        NearToken::from_millinear(100),
+    )
+    .await?
+    .into_result()?;
+
+    let outcome = resolver
+        .call(contract.id(), "withdraw")
+        .args_json(json!({ "secret": base64::engine::general_purpose::STANDARD.encode(secret) }))
+        .gas(Gas::from_tgas(30))
+        .transact()
+        .await?;
+    common::assert_failure_contains(outcome, "Not enough gas attached for settlement");
+
+    // Nothing was claimed, so the escrow is still withdrawable.
+    let state: String = contract
+        .view("get_escrow_state")
+        .args_json(json!({ "hashlock": common::hashlock(secret) }))
+        .await?
+        .json()?;
+    assert_eq!(state, "Withdrawable");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_withdraw_by_secret_and_by_explicit_hashlock() -> Result<()> {
+    let (worker, contract, _owner) = common::setup().await?;
+    let token = worker.dev_create_account().await?;
+    let maker = worker.dev_create_account().await?;
+    let resolver = worker.dev_create_account().await?;
+
+    let convenient = b"convenient";
+    let explicit = b"explicit";
+    for secret in [&convenient[..], &explicit[..]] {
+        common::create_destination_escrow(
+            &contract,
+            &token,
+            &resolver,
+            json!({
+                "type": "CreateDestinationEscrow",
+                "hashlock": common::hashlock(secret),
+                "maker_id": maker.id(),
+                "timelocks": common::default_timelocks(),
+            }),
+            100,
+            NearToken::from_millinear(100),
+        )
+        .await?
+        .into_result()?;
+    }
+
+    let outcome = resolver
+        .call(contract.id(), "withdraw")
+        .args_json(
+            json!({ "secret": base64::engine::general_purpose::STANDARD.encode(convenient) }),
+        )
+        .max_gas()
+        .transact()
+        .await?;
+    assert!(outcome
+        .logs()
+        .iter()
+        .any(|log| log.starts_with("ESCROW_CLAIMED")));
+
+    // The secret of one escrow cannot be used against another escrow's hashlock.
+    let outcome = resolver
+        .call(contract.id(), "withdraw_escrow")
+        .args_json(json!({
+            "hashlock": common::hashlock(explicit),
+            "secret": base64::engine::general_purpose::STANDARD.encode(convenient),
+        }))
+        .max_gas()
+        .transact()
+        .await?;
+    common::assert_failure_contains(outcome, "Secret does not match hashlock");
+
+    let outcome = resolver
+        .call(contract.id(), "withdraw_escrow")
+        .args_json(json!({
+            "hashlock": common::hashlock(explicit),
+            "secret": base64::engine::general_purpose::STANDARD.encode(explicit),
+        }))
+        .max_gas()
+        .transact()
+        .await?;
+    assert!(outcome
+        .logs()
+        .iter()
+        .any(|log| log.starts_with("ESCROW_CLAIMED")));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_withdraw_salted_preimage() -> Result<()> {
+    let (worker, contract, _owner) = common::setup().await?;
+    let token = worker.dev_create_account().await?;
+    let maker = worker.dev_create_account().await?;
+    let resolver = worker.dev_create_account().await?;
+
+    let secret = b"structured";
+    let salt = b"|order-metadata";
+    let preimage = [&secret[..], &salt[..]].concat();
+    common::create_destination_escrow(
+        &contract,
+        &token,
+        &resolver,
+        json!({
+            "type": "CreateDestinationEscrow",
+            "hashlock": common::hashlock(&preimage),
+            "maker_id": maker.id(),
+            "timelocks": common::default_timelocks(),
+            "preimage_scheme": "SecretWithSalt",
+        }),
+        100,
+        NearToken::from_millinear(100),
+    )
+    .await?
+    .into_result()?;
+
+    // The bare secret does not hash to the salted hashlock.
+    let outcome = resolver
+        .call(contract.id(), "withdraw")
+        .args_json(json!({ "secret": base64::engine::general_purpose::STANDARD.encode(secret) }))
+        .max_gas()
+        .transact()
+        .await?;
+    common::assert_failure_contains(outcome, "Escrow not found");
+
+    let outcome = resolver
+        .call(contract.id(), "withdraw")
+        .args_json(json!({
+            "secret": base64::engine::general_purpose::STANDARD.encode(secret),
+            "salt": base64::engine::general_purpose::STANDARD.encode(salt),
+        }))
+        .max_gas()
+        .transact()
+        .await?;
+    assert!(outcome
+        .logs()
+        .iter()
+        .any(|log| log.starts_with("ESCROW_CLAIMED")));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_withdraw_and_cancel_return_settlement_receipts() -> Result<()> {
+    let (worker, contract, owner) = common::setup().await?;
+    let token = worker.dev_create_account().await?;
+    let maker = worker.dev_create_account().await?;
+    let resolver = worker.dev_create_account().await?;
+
+    let withdrawn = b"receipt withdraw";
+    let cancelled = b"receipt cancel";
+    for secret in [&withdrawn[..], &cancelled[..]] {
+        common::create_destination_escrow(
+            &contract,
+            &token,
+            &resolver,
+            json!({
+                "type": "CreateDestinationEscrow",
+                "hashlock": common::hashlock(secret),
+                "maker_id": maker.id(),
+                "timelocks": common::default_timelocks(),
+            }),
+            100,
+            NearToken::from_millinear(100),
+        )
+        .await?
+        .into_result()?;
+    }
+
+    let receipt: serde_json::Value = resolver
+        .call(contract.id(), "withdraw")
+        .args_json(json!({ "secret": base64::engine::general_purpose::STANDARD.encode(withdrawn) }))
+        .max_gas()
+        .transact()
+        .await?
+        .json()?;
+    assert_eq!(
+        receipt,
+        json!({
+            "hashlock": common::hashlock(withdrawn),
+            "action": "Withdraw",
+            "recipient": maker.id(),
+            "amount": "100",
+            "safety_deposit_returned_to": resolver.id(),
+        })
+    );
+
+    owner
+        .call(contract.id(), "add_relayer")
+        .args_json(json!({ "account_id": owner.id() }))
+        .transact()
+        .await?
+        .into_result()?;
+    let receipt: serde_json::Value = owner
+        .call(contract.id(), "relayer_cancel")
+        .args_json(json!({ "hashlock": common::hashlock(cancelled) }))
+        .max_gas()
+        .transact()
+        .await?
+        .json()?;
+    assert_eq!(
+        receipt,
+        json!({
+            "hashlock": common::hashlock(cancelled),
+            "action": "Cancel",
+            "recipient": resolver.id(),
+            "amount": "100",
+            "safety_deposit_returned_to": resolver.id(),
+        })
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_withdraw_rejects_non_base64_secret() -> Result<()> {
+    let (worker, contract, _owner) = common::setup().await?;
+    let resolver = worker.dev_create_account().await?;
+
+    let outcome = resolver
+        .call(contract.id(), "withdraw")
+        .args_json(json!({ "secret": "not base64!" }))
+        .max_gas()
+        .transact()
+        .await?;
+    common::assert_failure_contains(outcome, "Invalid base64 secret");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_retried_settlement_pays_the_safety_deposit_once() -> Result<()> {
+    let (worker, contract, _owner) = common::setup().await?;
+    let token = worker.dev_create_account().await?;
+    let maker = worker.dev_create_account().await?;
+    let resolver = worker.dev_create_account().await?;
+
+    for secret in [&b"retried"[..], &b"untouched"[..]] {
+        common::create_destination_escrow(
+            &contract,
+            &token,
+            &resolver,
+            json!({
+                "type": "CreateDestinationEscrow",
+                "hashlock": common::hashlock(secret),
+                "maker_id": maker.id(),
+                "timelocks": common::default_timelocks(),
+            }),
+            100,
+            NearToken::from_millinear(100),
+        )
+        .await?
+        .into_result()?;
+    }
+
+    // `token` is not an FT contract, so the payout to the maker fails and `claimed` is reverted,
+    // while the native safety deposit has already gone out to the resolver.
+    let withdraw = || {
+        resolver
+            .call(contract.id(), "withdraw")
+            .args_json(json!({
+                "secret": base64::engine::general_purpose::STANDARD.encode(b"retried"),
+            }))
+            .max_gas()
+            .transact()
+    };
+    let outcome = withdraw().await?;
+    assert!(outcome
+        .logs()
+        .iter()
+        .any(|log| log.starts_with("ESCROW_SETTLEMENT_FAILED")));
+    let escrow: serde_json::Value = contract
+        .view("get_escrow")
+        .args_json(json!({ "hashlock": common::hashlock(b"retried") }))
+        .await?
+        .json()?;
+    assert_eq!(escrow["claimed"], false);
+    assert_eq!(escrow["safety_deposit_returned"], true);
+
+    let balance_before_retry = resolver.view_account().await?.balance;
+    let outcome = withdraw().await?;
+    assert!(outcome
+        .logs()
+        .iter()
+        .any(|log| log.starts_with("ESCROW_SETTLEMENT_FAILED")));
+
+    // The retry only cost gas, and only the untouched escrow's deposit remains in the pool.
+    assert!(resolver.view_account().await?.balance < balance_before_retry);
+    let pool: NearToken = contract.view("get_safety_deposit_pool").await?.json()?;
+    assert_eq!(pool, NearToken::from_millinear(100));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_authorized_claimer_withdraws_in_the_private_window() -> Result<()> {
+    let (worker, contract, _owner) = common::setup().await?;
+    let token = worker.dev_create_account().await?;
+    let maker = worker.dev_create_account().await?;
+    let resolver = worker.dev_create_account().await?;
+    let delegate = worker.dev_create_account().await?;
+    let stranger = worker.dev_create_account().await?;
+
+    // The resolver cannot name itself as its own delegate.
+    let outcome = common::create_destination_escrow(
+        &contract,
+        &token,
+        &resolver,
+        json!({
+            "type": "CreateDestinationEscrow",
+            "hashlock": common::hashlock(b"self-delegate"),
+            "maker_id": maker.id(),
+            "timelocks": common::default_timelocks(),
+            "authorized_claimer": resolver.id(),
+        }),
+        100,
+        NearToken::from_millinear(100),
+    )
+    .await?;
+    common::assert_failure_contains(outcome, "Authorized claimer must differ from the taker");
+
+    let secret = b"delegated";
+    common::create_destination_escrow(
+        &contract,
+        &token,
+        &resolver,
+        json!({
+            "type": "CreateDestinationEscrow",
+            "hashlock": common::hashlock(secret),
+            "maker_id": maker.id(),
+            "timelocks": common::default_timelocks(),
+            "authorized_claimer": delegate.id(),
+        }),
+        100,
+        NearToken::from_millinear(100),
+    )
+    .await?
+    .into_result()?;
+
+    let eligibility: serde_json::Value = contract
+        .view("who_can_act_now")
+        .args_json(json!({ "hashlock": common::hashlock(secret) }))
+        .await?
+        .json()?;
+    assert_eq!(
+        eligibility["callers"],
+        json!({ "TakerOrClaimer": { "taker": resolver.id(), "claimer": delegate.id() } })
+    );
+
+    // Only the private window is open, so other accounts cannot withdraw yet.
+    let outcome = stranger
+        .call(contract.id(), "withdraw")
+        .args_json(json!({ "secret": base64::engine::general_purpose::STANDARD.encode(secret) }))
+        .max_gas()
+        .transact()
+        .await?;
+    common::assert_failure_contains(outcome, "Public withdrawal period (dst) has not started");
+
+    let receipt: serde_json::Value = delegate
+        .call(contract.id(), "withdraw")
+        .args_json(json!({ "secret": base64::engine::general_purpose::STANDARD.encode(secret) }))
+        .max_gas()
+        .transact()
+        .await?
+        .json()?;
+    assert_eq!(receipt["recipient"], json!(maker.id()));
+    assert_eq!(receipt["safety_deposit_returned_to"], json!(delegate.id()));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_fee_change_waits_for_its_delay() -> Result<()> {
+    let (worker, contract, owner) = common::setup().await?;
+    let call = |method: &str, args: serde_json::Value| {
+        owner.call(contract.id(), method).args_json(args).max_gas()
+    };
+
+    // With a one-second delay, the proposal applies once the chain has moved past it.
+    call("set_fee_change_delay", json!({ "delay_seconds": 1 }))
+        .transact()
+        .await?
+        .into_result()?;
+    call("propose_fee_change", json!({ "fee_bps": 30 }))
+        .transact()
+        .await?
+        .into_result()?;
+    let pending: serde_json::Value = contract.view("get_pending_fee_change").await?.json()?;
+    assert_eq!(pending["fee_bps"], 30);
+    worker.fast_forward(10).await?;
+    call("apply_fee_change", json!({}))
+        .transact()
+        .await?
+        .into_result()?;
+    let fee_bps: u16 = contract.view("get_fee_bps").await?.json()?;
+    assert_eq!(fee_bps, 30);
+    let pending: serde_json::Value = contract.view("get_pending_fee_change").await?.json()?;
+    assert!(pending.is_null());
+
+    // Once raised, the delay cannot be lowered again, and a proposal cannot be applied early.
+    call("set_fee_change_delay", json!({ "delay_seconds": 3600 }))
+        .transact()
+        .await?
+        .into_result()?;
+    let outcome = call("set_fee_change_delay", json!({ "delay_seconds": 0 }))
+        .transact()
+        .await?;
+    common::assert_failure_contains(outcome, "Fee change delay can only be raised");
+    call("propose_fee_change", json!({ "fee_bps": 100 }))
+        .transact()
+        .await?
+        .into_result()?;
+    let outcome = call("apply_fee_change", json!({})).transact().await?;
+    common::assert_failure_contains(outcome, "Fee change is not effective yet");
+    let fee_bps: u16 = contract.view("get_fee_bps").await?.json()?;
+    assert_eq!(fee_bps, 30);
+
+    Ok(())
+}